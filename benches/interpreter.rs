@@ -0,0 +1,78 @@
+#[macro_use]
+extern crate criterion;
+extern crate chip8;
+
+use std::env;
+use std::fs;
+
+use criterion::{Criterion, Throughput};
+
+use chip8::bench::WORKLOADS;
+use chip8::chip8::Chip8;
+
+const FRAMES: u64 = 1000;
+
+// Directory with ROMs to benchmark in addition to the built-in workloads.
+const ROMS_VAR: &str = "CHIP8_BENCH_ROMS";
+
+fn load(rom: &[u8], decode_cache: bool) -> Chip8 {
+    let mut chip = Chip8::new();
+    chip.initialize();
+    chip.load_rom(rom.to_vec()).unwrap();
+    chip.set_decode_cache(decode_cache);
+    chip
+}
+
+// Runs `rom` headless and returns the number of instructions executed.
+fn run_headless(rom: &[u8], decode_cache: bool) -> u64 {
+    let mut chip = load(rom, decode_cache);
+    for _ in 0..FRAMES {
+        chip.step_frame();
+    }
+    chip.instructions()
+}
+
+fn bench_rom(c: &mut Criterion, group: &str, name: &str, rom: &[u8]) {
+    let mut group = c.benchmark_group(group);
+    group.throughput(Throughput::Elements(run_headless(rom, true)));
+    group.bench_function(name, |b| b.iter(|| run_headless(rom, true)));
+    group.finish();
+}
+
+/// The instruction mix, drawing and memory access of the built-in workloads.
+fn workloads(c: &mut Criterion) {
+    for workload in WORKLOADS.iter() {
+        bench_rom(c, "workloads", workload.name, workload.rom);
+    }
+}
+
+/// The ROMs in the directory `CHIP8_BENCH_ROMS`, if set.
+fn roms(c: &mut Criterion) {
+    let dir = match env::var_os(ROMS_VAR) {
+        Some(dir) => dir,
+        None => return,
+    };
+    let mut paths: Vec<_> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.is_file())
+        .collect();
+    paths.sort();
+    for path in paths {
+        let rom = fs::read(&path).unwrap();
+        let name = path.file_name().unwrap().to_string_lossy().into_owned();
+        bench_rom(c, "roms", &name, &rom);
+    }
+}
+
+fn decode_cache(c: &mut Criterion) {
+    let rom = WORKLOADS[0].rom;
+    let mut group = c.benchmark_group("decode_cache");
+    group.throughput(Throughput::Elements(run_headless(rom, true)));
+    group.bench_function("disabled", |b| b.iter(|| run_headless(rom, false)));
+    group.bench_function("enabled", |b| b.iter(|| run_headless(rom, true)));
+    group.finish();
+}
+
+criterion_group!(benches, workloads, roms, decode_cache);
+criterion_main!(benches);
@@ -0,0 +1,13 @@
+#!/bin/sh
+# Downloads the CHIP-8 community database into this directory, from which it is compiled into
+# the emulator. Set CHIP8_DATABASE_REF to a tag or commit of chip-8/chip-8-database to pin it.
+set -e
+
+ref=${CHIP8_DATABASE_REF:-master}
+url=https://raw.githubusercontent.com/chip-8/chip-8-database/$ref/database
+dir=$(dirname "$0")
+
+for file in sha1-hashes.json programs.json; do
+    curl --fail --silent --show-error --location -o "$dir/$file.tmp" "$url/$file"
+    mv "$dir/$file.tmp" "$dir/$file"
+done
@@ -0,0 +1,28 @@
+//! Runs the program libFuzzer generates on `Chip8` and on the reference interpreter of the
+//! differential tests and panics when they differ after any instruction.
+//!
+//! The first byte selects the quirks preset, the rest is the program.
+
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate chip8;
+extern crate rand;
+
+#[path = "../../tests/reference/mod.rs"]
+mod reference;
+
+use chip8::quirks::{self, Quirks};
+
+const STEPS: usize = 1000;
+
+fuzz_target!(|data: &[u8]| {
+    let (&selector, program) = match data.split_first() {
+        Some(split) => split,
+        None => return,
+    };
+    let preset = quirks::PRESETS[selector as usize % quirks::PRESETS.len()];
+    if let Err(msg) = reference::run(program, Quirks::preset(preset).unwrap(), STEPS) {
+        panic!("Differs under {} at {}", preset, msg);
+    }
+});
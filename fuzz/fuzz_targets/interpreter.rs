@@ -0,0 +1,53 @@
+//! Runs the instruction stream libFuzzer generates and checks that the interpreter stays in a
+//! valid state: it must not panic, the program counter has to stay within the 4 KB of memory
+//! and the stack within its 16 entries.
+//!
+//! The first byte selects the quirks preset and the timing model, the rest is the program.
+
+#![no_main]
+#[macro_use]
+extern crate libfuzzer_sys;
+extern crate chip8;
+
+use chip8::chip8::{Chip8, State, ADDRESS_MASK};
+use chip8::quirks::{self, Quirks};
+use chip8::stack::STACK_SIZE;
+use chip8::timing::{self, Timing};
+
+const CYCLES: usize = 1000;
+
+fuzz_target!(|data: &[u8]| {
+    let (&selector, program) = match data.split_first() {
+        Some(split) => split,
+        None => return,
+    };
+    let preset = quirks::PRESETS[selector as usize % quirks::PRESETS.len()];
+    let model = timing::PRESETS[selector as usize / 4 % timing::PRESETS.len()];
+
+    let mut chip = Chip8::new();
+    chip.set_database(None);
+    chip.set_seed(selector as u64);
+    chip.set_timing(Timing::preset(model).unwrap());
+    chip.initialize();
+    if chip.load_rom(program.to_vec()).is_err() {
+        return;
+    }
+    chip.set_quirks(Quirks::preset(preset).unwrap());
+
+    for _ in 0..CYCLES {
+        match chip.state() {
+            State::Running => {
+                chip.cycle();
+            }
+            State::WaitingForVblank => chip.step_frame(),
+            State::WaitingForKey { .. } |
+            State::Halted { .. } => break,
+        }
+        assert!(chip.program_counter() <= ADDRESS_MASK,
+                "PC {:04X} outside of memory",
+                chip.program_counter());
+        assert!(chip.stack_depth() <= STACK_SIZE,
+                "Stack depth {}",
+                chip.stack_depth());
+    }
+});
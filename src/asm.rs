@@ -0,0 +1,348 @@
+use std::collections::HashMap;
+
+use instruction::Instruction;
+
+#[derive(Debug, Clone, PartialEq)]
+enum Operand {
+    Register(u8),
+    I,
+    IndirectI,
+    Dt,
+    St,
+    K,
+    F,
+    Hf,
+    B,
+    R,
+    Long(Box<Operand>),
+    Number(u32),
+    Label(String),
+}
+
+enum Statement {
+    Instruction(String, Vec<Operand>),
+    Bytes(Vec<Operand>),
+    Words(Vec<Operand>),
+}
+
+struct SourceLine {
+    number: usize,
+    statement: Statement,
+}
+
+/// Assembles `source` into a ROM image to be loaded at `origin`.
+///
+/// The syntax follows the mnemonics printed by the disassembler. Comments start with `;`,
+/// labels end with `:` and `DB`/`DW` emit raw bytes and words.
+pub fn assemble(source: &str, origin: usize) -> Result<Vec<u8>, String> {
+    let mut labels = HashMap::new();
+    let mut lines = Vec::new();
+    let mut address = origin;
+
+    for (index, text) in source.lines().enumerate() {
+        let number = index + 1;
+        let mut text = text.split(';').next().unwrap_or("").trim();
+        if let Some(colon) = text.find(':') {
+            let label = text[..colon].trim();
+            if !is_identifier(label) {
+                return Err(format!("line {}: invalid label '{}'", number, label));
+            }
+            if labels.insert(label.to_uppercase(), address as u32).is_some() {
+                return Err(format!("line {}: duplicate label '{}'", number, label));
+            }
+            text = text[colon + 1..].trim();
+        }
+        if text.is_empty() {
+            continue;
+        }
+
+        let statement = parse_statement(text)
+            .map_err(|err| format!("line {}: {}", number, err))?;
+        address += statement_size(&statement);
+        lines.push(SourceLine {
+                       number,
+                       statement,
+                   });
+    }
+
+    let mut rom = Vec::new();
+    for line in &lines {
+        emit(&line.statement, &labels, &mut rom)
+            .map_err(|err| format!("line {}: {}", line.number, err))?;
+    }
+    Ok(rom)
+}
+
+fn is_identifier(text: &str) -> bool {
+    let mut chars = text.chars();
+    match chars.next() {
+        Some(first) if first.is_alphabetic() || first == '_' => {
+            chars.all(|c| c.is_alphanumeric() || c == '_')
+        }
+        _ => false,
+    }
+}
+
+fn parse_statement(text: &str) -> Result<Statement, String> {
+    let (mnemonic, rest) = match text.find(char::is_whitespace) {
+        Some(index) => (&text[..index], text[index..].trim()),
+        None => (text, ""),
+    };
+
+    let mut operands = Vec::new();
+    if !rest.is_empty() {
+        for operand in rest.split(',') {
+            operands.push(parse_operand(operand.trim())?);
+        }
+    }
+
+    let mnemonic = mnemonic.to_uppercase();
+    Ok(match mnemonic.as_str() {
+           "DB" => Statement::Bytes(operands),
+           "DW" => Statement::Words(operands),
+           _ => Statement::Instruction(mnemonic, operands),
+       })
+}
+
+fn parse_operand(text: &str) -> Result<Operand, String> {
+    let upper = text.to_uppercase();
+    if let Some(address) = upper.strip_prefix("LONG ") {
+        return Ok(Operand::Long(Box::new(parse_operand(address.trim())?)));
+    }
+
+    Ok(match upper.as_str() {
+           "I" => Operand::I,
+           "[I]" => Operand::IndirectI,
+           "DT" => Operand::Dt,
+           "ST" => Operand::St,
+           "K" => Operand::K,
+           "F" => Operand::F,
+           "HF" => Operand::Hf,
+           "B" => Operand::B,
+           "R" => Operand::R,
+           _ if upper.len() == 2 && upper.starts_with('V') => {
+               match u8::from_str_radix(&upper[1..], 16) {
+                   Ok(register) => Operand::Register(register),
+                   Err(_) => Operand::Label(upper),
+               }
+           }
+           _ => {
+               let number = if let Some(hex) = upper.strip_prefix("0X") {
+                   u32::from_str_radix(hex, 16)
+               } else if let Some(hex) = upper.strip_prefix('#').or(upper.strip_prefix('$')) {
+                   u32::from_str_radix(hex, 16)
+               } else if let Some(binary) = upper.strip_prefix("0B") {
+                   u32::from_str_radix(binary, 2)
+               } else if upper.chars().all(|c| c.is_ascii_digit()) && !upper.is_empty() {
+                   upper.parse()
+               } else if is_identifier(text) {
+                   return Ok(Operand::Label(upper));
+               } else {
+                   return Err(format!("invalid operand '{}'", text));
+               };
+               number.map_err(|_| format!("invalid number '{}'", text)).map(Operand::Number)?
+           }
+       })
+}
+
+fn statement_size(statement: &Statement) -> usize {
+    match *statement {
+        Statement::Bytes(ref operands) => operands.len(),
+        Statement::Words(ref operands) => operands.len() * 2,
+        Statement::Instruction(_, ref operands) => {
+            if operands.iter().any(|operand| matches!(*operand, Operand::Long(_))) {
+                4
+            } else {
+                2
+            }
+        }
+    }
+}
+
+fn resolve(operand: &Operand, labels: &HashMap<String, u32>) -> Result<Operand, String> {
+    match *operand {
+        Operand::Label(ref label) => {
+            match labels.get(label) {
+                Some(&address) => Ok(Operand::Number(address)),
+                None => Err(format!("unknown label '{}'", label)),
+            }
+        }
+        Operand::Long(ref inner) => Ok(Operand::Long(Box::new(resolve(inner, labels)?))),
+        ref other => Ok(other.clone()),
+    }
+}
+
+fn value(operand: &Operand, max: u32) -> Result<u32, String> {
+    match *operand {
+        Operand::Number(number) if number <= max => Ok(number),
+        Operand::Number(number) => Err(format!("value 0x{:X} out of range", number)),
+        _ => Err(format!("expected a number, found {:?}", operand)),
+    }
+}
+
+fn emit(statement: &Statement,
+        labels: &HashMap<String, u32>,
+        rom: &mut Vec<u8>)
+        -> Result<(), String> {
+    match *statement {
+        Statement::Bytes(ref operands) => {
+            for operand in operands {
+                rom.push(value(&resolve(operand, labels)?, 0xFF)? as u8);
+            }
+        }
+        Statement::Words(ref operands) => {
+            for operand in operands {
+                let word = value(&resolve(operand, labels)?, 0xFFFF)?;
+                rom.push((word >> 8) as u8);
+                rom.push(word as u8);
+            }
+        }
+        Statement::Instruction(ref mnemonic, ref operands) => {
+            let mut resolved = Vec::new();
+            for operand in operands {
+                resolved.push(resolve(operand, labels)?);
+            }
+
+            if let (true, &[Operand::I, Operand::Long(ref address)]) =
+                (mnemonic == "LD", resolved.as_slice()) {
+                let address = value(address, 0xFFFF)?;
+                rom.extend_from_slice(&Instruction::LdILong.to_bytes());
+                rom.push((address >> 8) as u8);
+                rom.push(address as u8);
+                return Ok(());
+            }
+
+            let instruction = build(mnemonic, &resolved)?;
+            rom.extend_from_slice(&instruction.to_bytes());
+        }
+    }
+    Ok(())
+}
+
+fn build(mnemonic: &str, operands: &[Operand]) -> Result<Instruction, String> {
+    use self::Operand::*;
+
+    let address = |operand: &Operand| value(operand, 0xFFF).map(|address| address as u16);
+    let byte = |operand: &Operand| value(operand, 0xFF).map(|byte| byte as u8);
+    let nibble = |operand: &Operand| value(operand, 0xF).map(|nibble| nibble as u8);
+
+    Ok(match (mnemonic, operands) {
+           ("CLS", &[]) => Instruction::Cls,
+           ("RET", &[]) => Instruction::Ret,
+           ("SYS", [nnn]) => Instruction::Sys(address(nnn)?),
+           ("JP", &[Register(0), ref nnn]) => Instruction::JpV0(address(nnn)?),
+           ("JP", [nnn]) => Instruction::Jp(address(nnn)?),
+           ("CALL", [nnn]) => Instruction::Call(address(nnn)?),
+           ("SE", &[Register(x), Register(y)]) => Instruction::SeReg(x, y),
+           ("SE", &[Register(x), ref nn]) => Instruction::SeByte(x, byte(nn)?),
+           ("SNE", &[Register(x), Register(y)]) => Instruction::SneReg(x, y),
+           ("SNE", &[Register(x), ref nn]) => Instruction::SneByte(x, byte(nn)?),
+           ("LD", &[Register(x), Register(y)]) => Instruction::LdReg(x, y),
+           ("LD", &[Register(x), Dt]) => Instruction::LdVxDt(x),
+           ("LD", &[Register(x), K]) => Instruction::LdVxK(x),
+           ("LD", &[Register(x), IndirectI]) => Instruction::LdVxI(x),
+           ("LD", &[Register(x), R]) => Instruction::LdVxR(x),
+           ("LD", &[Register(x), ref nn]) => Instruction::LdByte(x, byte(nn)?),
+           ("LD", &[I, ref nnn]) => Instruction::LdI(address(nnn)?),
+           ("LD", &[Dt, Register(x)]) => Instruction::LdDt(x),
+           ("LD", &[St, Register(x)]) => Instruction::LdSt(x),
+           ("LD", &[F, Register(x)]) => Instruction::LdF(x),
+           ("LD", &[Hf, Register(x)]) => Instruction::LdHf(x),
+           ("LD", &[B, Register(x)]) => Instruction::LdB(x),
+           ("LD", &[IndirectI, Register(x)]) => Instruction::LdIVx(x),
+           ("LD", &[R, Register(x)]) => Instruction::LdRVx(x),
+           ("ADD", &[Register(x), Register(y)]) => Instruction::AddReg(x, y),
+           ("ADD", &[Register(x), ref nn]) => Instruction::AddByte(x, byte(nn)?),
+           ("ADD", &[I, Register(x)]) => Instruction::AddI(x),
+           ("OR", &[Register(x), Register(y)]) => Instruction::Or(x, y),
+           ("AND", &[Register(x), Register(y)]) => Instruction::And(x, y),
+           ("XOR", &[Register(x), Register(y)]) => Instruction::Xor(x, y),
+           ("SUB", &[Register(x), Register(y)]) => Instruction::Sub(x, y),
+           ("SHR", &[Register(x)]) => Instruction::Shr(x, x),
+           ("SHR", &[Register(x), Register(y)]) => Instruction::Shr(x, y),
+           ("SUBN", &[Register(x), Register(y)]) => Instruction::Subn(x, y),
+           ("SHL", &[Register(x)]) => Instruction::Shl(x, x),
+           ("SHL", &[Register(x), Register(y)]) => Instruction::Shl(x, y),
+           ("RND", &[Register(x), ref nn]) => Instruction::Rnd(x, byte(nn)?),
+           ("DRW", &[Register(x), Register(y), ref n]) => {
+               Instruction::Drw {
+                   x,
+                   y,
+                   n: nibble(n)?,
+               }
+           }
+           ("SKP", &[Register(x)]) => Instruction::Skp(x),
+           ("SKNP", &[Register(x)]) => Instruction::Sknp(x),
+           ("SCD", [n]) => Instruction::ScrollDown(nibble(n)?),
+           ("SCU", [n]) => Instruction::ScrollUp(nibble(n)?),
+           ("SCR", &[]) => Instruction::ScrollRight,
+           ("SCL", &[]) => Instruction::ScrollLeft,
+           ("EXIT", &[]) => Instruction::Exit,
+           ("LOW", &[]) => Instruction::Low,
+           ("HIGH", &[]) => Instruction::High,
+           ("SAVE", &[Register(x), Register(y)]) => Instruction::SaveRange(x, y),
+           ("LOAD", &[Register(x), Register(y)]) => Instruction::LoadRange(x, y),
+           ("PLANE", [n]) => Instruction::Plane(nibble(n)?),
+           ("AUDIO", &[]) => Instruction::Audio,
+           ("PITCH", &[Register(x)]) => Instruction::Pitch(x),
+           _ => return Err(format!("invalid instruction '{}' with {:?}", mnemonic, operands)),
+       })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use disasm;
+
+    #[test]
+    fn assemble_instructions() {
+        let rom = assemble("CLS\nLD VA, 0x1F\nDRW V0, V1, 5", 0x200).unwrap();
+        assert_eq!(rom, vec![0x00, 0xE0, 0x6A, 0x1F, 0xD0, 0x15]);
+    }
+
+    #[test]
+    fn assemble_labels() {
+        let source = "start: CALL sub ; comment\n       JP start\nsub:\n       RET";
+        let rom = assemble(source, 0x200).unwrap();
+        assert_eq!(rom, vec![0x22, 0x04, 0x12, 0x00, 0x00, 0xEE]);
+    }
+
+    #[test]
+    fn assemble_data() {
+        let rom = assemble("DB 0xF0, #90, 1\nDW $1234", 0x200).unwrap();
+        assert_eq!(rom, vec![0xF0, 0x90, 0x01, 0x12, 0x34]);
+    }
+
+    #[test]
+    fn assemble_long_load() {
+        let rom = assemble("LD I, LONG data\nDB 0\ndata: DB 0xFF", 0x200).unwrap();
+        assert_eq!(rom, vec![0xF0, 0x00, 0x02, 0x05, 0x00, 0xFF]);
+    }
+
+    #[test]
+    fn assemble_errors() {
+        assert!(assemble("JP nowhere", 0x200).is_err());
+        assert!(assemble("LD V0, 0x100", 0x200).is_err());
+        assert!(assemble("FOO V0", 0x200).is_err());
+        assert!(assemble("a:\na: CLS", 0x200).is_err());
+    }
+
+    #[test]
+    fn disassembly_round_trip() {
+        let rom: Vec<u8> = (0..0x10000u32)
+            .flat_map(|word| vec![(word >> 8) as u8, word as u8])
+            .collect();
+        let source: String = disasm::disassemble(&rom, 0x200)
+            .iter()
+            .map(|line| {
+                     let listing = disasm::listing(&[disasm::Line {
+                                                          address: line.address,
+                                                          bytes: line.bytes.clone(),
+                                                          instruction: line.instruction,
+                                                      }]);
+                     listing.split_once(": ").unwrap().1[12..].to_string()
+                 })
+            .collect();
+        assert_eq!(assemble(&source, 0x200).unwrap(), rom);
+    }
+}
@@ -0,0 +1,103 @@
+//! Workloads for measuring the emulation speed, shared by `chip8 bench` and the criterion
+//! benchmarks in `benches/`.
+
+use std::fmt;
+
+/// A program exercising one part of the interpreter in an endless loop.
+pub struct Workload {
+    pub name: &'static str,
+    pub rom: &'static [u8],
+}
+
+/// Arithmetic, skips and a subroutine call.
+const MIX: [u8; 24] = [0x60, 0x01, 0x61, 0x02, 0x80, 0x14, 0x81, 0x05, 0x30, 0x05, 0x70, 0x01,
+                       0xA3, 0x00, 0xF0, 0x1E, 0x22, 0x16, 0x12, 0x00, 0x00, 0x00, 0x00, 0xEE];
+
+/// Draws a sprite of 15 rows at two unaligned positions that move every loop and clears the
+/// display whenever they collide.
+const DRAW: [u8; 39] = [0x60, 0x00, 0x61, 0x00, 0xA2, 0x18, 0xD0, 0x1F, 0x70, 0x05, 0x71, 0x03,
+                        0xD0, 0x1F, 0x70, 0x0B, 0x71, 0x07, 0x3F, 0x00, 0x00, 0xE0, 0x12, 0x06,
+                        0xFF, 0x81, 0xBD, 0xA5, 0xA5, 0xBD, 0x81, 0xFF, 0x18, 0x3C, 0x7E, 0xFF,
+                        0x7E, 0x3C, 0x18];
+
+/// Converts V0 to decimal and stores and loads all registers.
+const MEMORY: [u8; 12] = [0xA3, 0x00, 0xF0, 0x33, 0xFF, 0x55, 0xFF, 0x65, 0x70, 0x01, 0x12,
+                          0x02];
+
+pub const WORKLOADS: [Workload; 3] = [Workload {
+                                          name: "mix",
+                                          rom: &MIX,
+                                      },
+                                      Workload {
+                                          name: "draw",
+                                          rom: &DRAW,
+                                      },
+                                      Workload {
+                                          name: "memory",
+                                          rom: &MEMORY,
+                                      }];
+
+/// Speed of a benchmark run.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Report {
+    pub frames: u64,
+    pub instructions: u64,
+    pub seconds: f64,
+}
+
+impl Report {
+    pub fn frames_per_second(&self) -> f64 {
+        self.frames as f64 / self.seconds
+    }
+
+    pub fn instructions_per_second(&self) -> f64 {
+        self.instructions as f64 / self.seconds
+    }
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f,
+               "{} frames in {:.3} s: {:.0} frames/s, {:.0} instructions/s",
+               self.frames,
+               self.seconds,
+               self.frames_per_second(),
+               self.instructions_per_second())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chip8::{Chip8, State, CYCLES_PER_FRAME};
+
+    #[test]
+    fn bench_workloads_run_forever() {
+        for workload in WORKLOADS.iter() {
+            let mut chip = Chip8::new();
+            chip.initialize();
+            chip.load_rom(workload.rom.to_vec()).unwrap();
+            for _ in 0..100 {
+                chip.step_frame();
+            }
+            assert_eq!(chip.state(), State::Running, "{}", workload.name);
+            assert!(chip.program_counter() < 0x200 + workload.rom.len() as u16);
+            assert_eq!(chip.instructions(),
+                       100 * CYCLES_PER_FRAME as u64,
+                       "{}",
+                       workload.name);
+        }
+    }
+
+    #[test]
+    fn bench_report() {
+        let report = Report {
+            frames: 60,
+            instructions: 600,
+            seconds: 0.5,
+        };
+        assert_eq!(report.frames_per_second(), 120.0);
+        assert_eq!(report.to_string(),
+                   "60 frames in 0.500 s: 120 frames/s, 1200 instructions/s");
+    }
+}
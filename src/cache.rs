@@ -0,0 +1,82 @@
+/// Decoded instructions keyed by the memory address they were fetched from.
+///
+/// Entries have to be invalidated whenever the memory they were decoded from changes.
+/// Addresses wrap around at the size of the cache, like the memory it covers.
+pub struct DecodeCache<T: Copy> {
+    entries: Vec<Option<T>>,
+}
+
+impl<T: Copy> DecodeCache<T> {
+    pub fn new(size: usize) -> DecodeCache<T> {
+        DecodeCache { entries: vec![None; size] }
+    }
+
+    pub fn get(&self, address: usize) -> Option<T> {
+        self.entries[address % self.entries.len()]
+    }
+
+    pub fn insert(&mut self, address: usize, entry: T) {
+        let size = self.entries.len();
+        self.entries[address % size] = Some(entry);
+    }
+
+    /// Drops the entries covering the byte at `address`, which includes an instruction starting
+    /// one byte earlier.
+    pub fn invalidate(&mut self, address: usize) {
+        let size = self.entries.len();
+        self.entries[address % size] = None;
+        self.entries[(address + size - 1) % size] = None;
+    }
+
+    pub fn clear(&mut self) {
+        for entry in self.entries.iter_mut() {
+            *entry = None;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cache_insert_get() {
+        let mut cache = DecodeCache::new(16);
+        assert_eq!(cache.get(4), None);
+        cache.insert(4, 0x1234);
+        assert_eq!(cache.get(4), Some(0x1234));
+    }
+
+    #[test]
+    fn cache_invalidate() {
+        let mut cache = DecodeCache::new(16);
+        cache.insert(4, 0x1234);
+        cache.insert(6, 0x5678);
+        cache.invalidate(5);
+        assert_eq!(cache.get(4), None);
+        assert_eq!(cache.get(6), Some(0x5678));
+        cache.invalidate(6);
+        assert_eq!(cache.get(6), None);
+    }
+
+    #[test]
+    fn cache_wraps_around() {
+        let mut cache = DecodeCache::new(16);
+        cache.insert(15, 0x1234);
+        cache.insert(16, 0x5678);
+        assert_eq!(cache.get(0), Some(0x5678));
+        assert_eq!(cache.get(31), Some(0x1234));
+        // The instruction at the last address covers the first byte as well.
+        cache.invalidate(0);
+        assert_eq!(cache.get(15), None);
+        assert_eq!(cache.get(0), None);
+    }
+
+    #[test]
+    fn cache_clear() {
+        let mut cache = DecodeCache::new(16);
+        cache.insert(4, 0x1234);
+        cache.clear();
+        assert_eq!(cache.get(4), None);
+    }
+}
@@ -0,0 +1,255 @@
+use std::collections::HashMap;
+
+use gif;
+use serde_json;
+
+use chip8::PROGRAM_START;
+use database::RomInfo;
+use disasm;
+use error::Chip8Error;
+use instruction::{Instruction, Platform};
+use keyboard::NUM_KEYS;
+use keymap::{HostKey, KeyMap};
+use octo;
+use palette::Palette;
+use quirks::Quirks;
+
+/// Options Octo stores with a cartridge. Colors are `#RRGGBB` strings and `keys` maps CHIP-8
+/// keys, as hex digits, onto the browser names of the keys that press them.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Options {
+    pub tickrate: Option<usize>,
+    pub fill_color: Option<String>,
+    pub background_color: Option<String>,
+    pub shift_quirks: Option<bool>,
+    pub load_store_quirks: Option<bool>,
+    pub jump_quirks: Option<bool>,
+    pub logic_quirks: Option<bool>,
+    pub clip_quirks: Option<bool>,
+    pub v_blank_quirks: Option<bool>,
+    pub keys: Option<HashMap<String, Vec<String>>>,
+}
+
+#[derive(Deserialize)]
+struct Payload {
+    program: String,
+    #[serde(default)]
+    options: Options,
+}
+
+/// Game exported by Octo as a GIF image.
+///
+/// The image data holds the Octo source of the game and its options as JSON. Every pixel
+/// carries a nybble of the data in the low bits of its color index, high nybble first. The
+/// first four bytes are the big-endian length of the JSON text.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cartridge {
+    pub source: String,
+    pub options: Options,
+}
+
+impl Cartridge {
+    /// Returns whether `data` is a GIF image rather than a plain ROM.
+    pub fn is_cartridge(data: &[u8]) -> bool {
+        data.starts_with(b"GIF8")
+    }
+
+    pub fn decode(data: &[u8]) -> Result<Cartridge, Chip8Error> {
+        let mut options = gif::DecodeOptions::new();
+        options.set_color_output(gif::ColorOutput::Indexed);
+        let mut decoder = options.read_info(data).map_err(|err| err.to_string())?;
+
+        let mut nybbles = Vec::new();
+        while let Some(frame) = decoder.read_next_frame().map_err(|err| err.to_string())? {
+            nybbles.extend(frame.buffer.iter().map(|index| index & 0x0F));
+        }
+        let bytes: Vec<u8> = nybbles.chunks(2)
+            .filter(|pair| pair.len() == 2)
+            .map(|pair| pair[0] << 4 | pair[1])
+            .collect();
+
+        if bytes.len() < 4 {
+            return Err(Chip8Error::Message("Cartridge holds no data".to_string()));
+        }
+        let size = (bytes[0] as usize) << 24 | (bytes[1] as usize) << 16 |
+                   (bytes[2] as usize) << 8 | bytes[3] as usize;
+        let json = match bytes.get(4..4 + size) {
+            Some(json) => json,
+            None => return Err(Chip8Error::Message("Cartridge data is truncated".to_string())),
+        };
+        let payload: Payload = serde_json::from_slice(json).map_err(|err| err.to_string())?;
+        Ok(Cartridge {
+               source: payload.program,
+               options: payload.options,
+           })
+    }
+
+    /// Compiles the Octo source into a ROM loaded at `0x200`.
+    pub fn compile(&self) -> Result<Vec<u8>, Chip8Error> {
+        octo::compile(&self.source).map_err(|err| Chip8Error::Message(format!("Octo: {}", err)))
+    }
+
+    /// Quirks selected in the options. Octo turns off quirks that are not set.
+    pub fn quirks(&self) -> Quirks {
+        Quirks {
+            key_release: false,
+            shift: self.options.shift_quirks.unwrap_or(false),
+            load_store: self.options.load_store_quirks.unwrap_or(false),
+            jump: self.options.jump_quirks.unwrap_or(false),
+            logic: self.options.logic_quirks.unwrap_or(false),
+            clip: self.options.clip_quirks.unwrap_or(false),
+            collision_rows: false,
+            display_wait: self.options.v_blank_quirks.unwrap_or(false),
+            add_i_overflow: false,
+        }
+    }
+
+    pub fn palette(&self) -> Option<Palette> {
+        match (&self.options.fill_color, &self.options.background_color) {
+            (Some(fill), Some(background)) => {
+                Palette::resolve(&format!("{},{}", fill, background)).ok()
+            }
+            _ => None,
+        }
+    }
+
+    /// Key bindings of the cartridge. Keys that are not valid CHIP-8 keys are skipped.
+    pub fn keymap(&self) -> Option<KeyMap> {
+        let keys = self.options.keys.as_ref()?;
+        let mut keymap = KeyMap::new();
+        for (chip8_key, names) in keys {
+            let chip8_key = match u8::from_str_radix(chip8_key, 16) {
+                Ok(key) if (key as usize) < NUM_KEYS => key,
+                _ => continue,
+            };
+            for name in names {
+                keymap.bind(HostKey::keycode(sdl_key_name(name)), chip8_key);
+            }
+        }
+        Some(keymap)
+    }
+
+    /// Describes the compiled `program` and the cartridge options the way the ROM database
+    /// describes known ROMs.
+    pub fn rom_info(&self, title: &str, program: &[u8]) -> RomInfo {
+        let platform = disasm::disassemble(program, PROGRAM_START)
+            .iter()
+            .filter_map(|line| match line.instruction {
+                            Instruction::Unknown(_) => None,
+                            instruction => Some(instruction.platform()),
+                        })
+            .max()
+            .unwrap_or(Platform::Chip8);
+        RomInfo {
+            title: title.to_string(),
+            authors: Vec::new(),
+            platform_id: "octo".to_string(),
+            platform,
+            quirks: self.quirks(),
+            tickrate: self.options.tickrate,
+            start_address: Some(PROGRAM_START),
+            palette: self.palette(),
+            keys: Vec::new(),
+            keymap: self.keymap(),
+        }
+    }
+}
+
+/// Translates the browser name of a key into its SDL name.
+fn sdl_key_name(name: &str) -> &str {
+    match name {
+        "ArrowUp" => "Up",
+        "ArrowDown" => "Down",
+        "ArrowLeft" => "Left",
+        "ArrowRight" => "Right",
+        " " => "Space",
+        "Enter" => "Return",
+        "Control" => "Left Ctrl",
+        "Shift" => "Left Shift",
+        "Alt" => "Left Alt",
+        name => name,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WIDTH: u16 = 16;
+
+    fn encode(json: &str) -> Vec<u8> {
+        let mut bytes = vec![(json.len() >> 24) as u8,
+                             (json.len() >> 16) as u8,
+                             (json.len() >> 8) as u8,
+                             json.len() as u8];
+        bytes.extend_from_slice(json.as_bytes());
+        // Label colors in the high nybble must not change the data.
+        let mut pixels: Vec<u8> = bytes.iter()
+            .flat_map(|byte| vec![0x30 | byte >> 4, byte & 0x0F])
+            .collect();
+        while !pixels.len().is_multiple_of(WIDTH as usize) {
+            pixels.push(0);
+        }
+
+        let height = (pixels.len() / WIDTH as usize) as u16;
+        let palette = vec![0; 256 * 3];
+        let mut image = Vec::new();
+        {
+            let mut encoder = gif::Encoder::new(&mut image, WIDTH, height, &palette).unwrap();
+            let frame = gif::Frame::from_indexed_pixels(WIDTH, height, pixels, None);
+            encoder.write_frame(&frame).unwrap();
+        }
+        image
+    }
+
+    #[test]
+    fn cartridge_decode() {
+        let json = r##"{"program": ": main clear loop again",
+                        "options": {"tickrate": 20, "fillColor": "#FFCC00",
+                                    "backgroundColor": "#996600", "shiftQuirks": true,
+                                    "vBlankQuirks": true}}"##;
+        let data = encode(json);
+        assert!(Cartridge::is_cartridge(&data));
+
+        let cartridge = Cartridge::decode(&data).unwrap();
+        assert_eq!(cartridge.source, ": main clear loop again");
+        assert_eq!(cartridge.options.tickrate, Some(20));
+        assert!(cartridge.quirks().shift);
+        assert!(!cartridge.quirks().load_store);
+        assert!(cartridge.quirks().display_wait);
+        assert_eq!(cartridge.palette().unwrap().foreground, (0xFF, 0xCC, 0x00));
+
+        let program = cartridge.compile().unwrap();
+        assert_eq!(program, vec![0x12, 0x02, 0x00, 0xE0, 0x12, 0x04]);
+        let info = cartridge.rom_info("game", &program);
+        assert_eq!(info.tickrate, Some(20));
+        assert_eq!(info.platform, Platform::Chip8);
+    }
+
+    #[test]
+    fn cartridge_keymap() {
+        let json = r#"{"program": ": main loop again",
+                       "options": {"keys": {"5": ["w", "ArrowUp"], "8": ["s"], "10": ["x"]}}}"#;
+        let cartridge = Cartridge::decode(&encode(json)).unwrap();
+        let keymap = cartridge.keymap().unwrap();
+        assert_eq!(keymap.lookup(&HostKey::keycode("W")), Some(0x5));
+        assert_eq!(keymap.lookup(&HostKey::keycode("Up")), Some(0x5));
+        assert_eq!(keymap.lookup(&HostKey::keycode("S")), Some(0x8));
+        assert_eq!(keymap.lookup(&HostKey::keycode("X")), None);
+
+        let program = cartridge.compile().unwrap();
+        assert_eq!(cartridge.rom_info("game", &program).keymap, Some(keymap));
+        let cartridge = Cartridge::decode(&encode("{\"program\": \"\"}")).unwrap();
+        assert_eq!(cartridge.keymap(), None);
+    }
+
+    #[test]
+    fn cartridge_invalid() {
+        assert!(!Cartridge::is_cartridge(&[0x00, 0xE0]));
+        assert!(Cartridge::decode(b"GIF89a").is_err());
+        assert!(Cartridge::decode(&encode("{\"options\": {}}")).is_err());
+        let cartridge = Cartridge::decode(&encode("{\"program\": \"clear\"}")).unwrap();
+        assert!(cartridge.compile().is_err());
+    }
+}
@@ -0,0 +1,423 @@
+/// Memory and I/O seen by the CPU.
+pub trait Bus {
+    fn read(&mut self, address: u16) -> u8;
+    fn write(&mut self, address: u16, value: u8);
+    /// `OUT N` for N = 1-7.
+    fn output(&mut self, port: u8, value: u8);
+    /// `INP N` for N = 1-7.
+    fn input(&mut self, port: u8) -> u8;
+    /// Returns true while the external flag EF1-EF4 is asserted.
+    fn flag(&mut self, flag: u8) -> bool;
+}
+
+/// RCA CDP1802 CPU. One machine cycle is 8 clock cycles.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cdp1802 {
+    /// Scratchpad registers R0-RF.
+    pub r: [u16; 16],
+    /// Index of the program counter register.
+    pub p: u8,
+    /// Index of the data pointer register.
+    pub x: u8,
+    pub d: u8,
+    pub df: bool,
+    /// X and P saved by an interrupt.
+    pub t: u8,
+    /// Interrupt enable.
+    pub ie: bool,
+    pub q: bool,
+    /// Halted by `IDL` until the next interrupt or DMA cycle.
+    pub idle: bool,
+}
+
+impl Cdp1802 {
+    pub fn new() -> Cdp1802 {
+        let mut cpu = Cdp1802 {
+            r: [0; 16],
+            p: 0,
+            x: 0,
+            d: 0,
+            df: false,
+            t: 0,
+            ie: true,
+            q: false,
+            idle: false,
+        };
+        cpu.reset();
+        cpu
+    }
+
+    /// Clears X, P, Q and R0 and enables interrupts. The other registers keep their values.
+    pub fn reset(&mut self) {
+        self.p = 0;
+        self.x = 0;
+        self.q = false;
+        self.ie = true;
+        self.idle = false;
+        self.r[0] = 0;
+    }
+
+    /// Acknowledges an interrupt: saves X and P in T and continues with P = 1 and X = 2.
+    /// Takes 1 machine cycle.
+    pub fn interrupt(&mut self) {
+        self.t = self.x << 4 | self.p;
+        self.p = 1;
+        self.x = 2;
+        self.ie = false;
+        self.idle = false;
+    }
+
+    /// Reads the byte at R0 for the DMA output channel and advances R0. Takes 1 machine cycle.
+    pub fn dma_out(&mut self, bus: &mut dyn Bus) -> u8 {
+        let value = bus.read(self.r[0]);
+        self.r[0] = self.r[0].wrapping_add(1);
+        self.idle = false;
+        value
+    }
+
+    /// Executes one instruction and returns the machine cycles it took: 3 for long branches
+    /// and skips, 2 for all others.
+    pub fn step(&mut self, bus: &mut dyn Bus) -> u32 {
+        let opcode = self.fetch(bus);
+        let n = (opcode & 0x0F) as usize;
+        let x = self.x as usize;
+
+        match opcode >> 4 {
+            0x0 if n == 0 => self.idle = true,
+            0x0 => self.d = bus.read(self.r[n]),
+            0x1 => self.r[n] = self.r[n].wrapping_add(1),
+            0x2 => self.r[n] = self.r[n].wrapping_sub(1),
+            0x3 => {
+                let condition = self.condition(n as u8, bus);
+                self.short_branch(condition, bus);
+            }
+            0x4 => {
+                self.d = bus.read(self.r[n]);
+                self.r[n] = self.r[n].wrapping_add(1);
+            }
+            0x5 => bus.write(self.r[n], self.d),
+            0x6 if n == 0 => self.r[x] = self.r[x].wrapping_add(1),
+            0x6 if n < 8 => {
+                let value = bus.read(self.r[x]);
+                bus.output(n as u8, value);
+                self.r[x] = self.r[x].wrapping_add(1);
+            }
+            // 68 is not an 1802 instruction.
+            0x6 if n == 8 => {}
+            0x6 => {
+                self.d = bus.input(n as u8 - 8);
+                bus.write(self.r[x], self.d);
+            }
+            0x7 => self.execute_7(n as u8, bus),
+            0x8 => self.d = self.r[n] as u8,
+            0x9 => self.d = (self.r[n] >> 8) as u8,
+            0xA => self.r[n] = self.r[n] & 0xFF00 | self.d as u16,
+            0xB => self.r[n] = self.r[n] & 0x00FF | (self.d as u16) << 8,
+            0xC => {
+                self.long_branch(n as u8, bus);
+                return 3;
+            }
+            0xD => self.p = n as u8,
+            0xE => self.x = n as u8,
+            _ => {
+                match n {
+                    0x6 => self.shift_right(false),
+                    0xE => self.shift_left(false),
+                    // F8-FF take the operand from the program instead of M(RX).
+                    _ => {
+                        let operand = if n >= 8 {
+                            self.fetch(bus)
+                        } else {
+                            bus.read(self.r[x])
+                        };
+                        self.alu(n as u8 & 0x7, operand, false);
+                    }
+                }
+            }
+        }
+        2
+    }
+
+    fn fetch(&mut self, bus: &mut dyn Bus) -> u8 {
+        let p = self.p as usize;
+        let value = bus.read(self.r[p]);
+        self.r[p] = self.r[p].wrapping_add(1);
+        value
+    }
+
+    fn execute_7(&mut self, n: u8, bus: &mut dyn Bus) {
+        let x = self.x as usize;
+        match n {
+            0x0 | 0x1 => {
+                let value = bus.read(self.r[x]);
+                self.r[x] = self.r[x].wrapping_add(1);
+                self.x = value >> 4;
+                self.p = value & 0x0F;
+                self.ie = n == 0;
+            }
+            0x2 => {
+                self.d = bus.read(self.r[x]);
+                self.r[x] = self.r[x].wrapping_add(1);
+            }
+            0x3 => {
+                bus.write(self.r[x], self.d);
+                self.r[x] = self.r[x].wrapping_sub(1);
+            }
+            0x8 => bus.write(self.r[x], self.t),
+            0x9 => {
+                self.t = self.x << 4 | self.p;
+                bus.write(self.r[2], self.t);
+                self.x = self.p;
+                self.r[2] = self.r[2].wrapping_sub(1);
+            }
+            0xA => self.q = false,
+            0xB => self.q = true,
+            0x6 => self.shift_right(true),
+            0xE => self.shift_left(true),
+            // 74, 75, 77 and 7C, 7D, 7F: arithmetic with carry or borrow.
+            _ => {
+                let operand = if n >= 8 {
+                    self.fetch(bus)
+                } else {
+                    bus.read(self.r[x])
+                };
+                self.alu(n & 0x7, operand, true);
+            }
+        }
+    }
+
+    /// F0-F5 and F7 (`with_carry` false) and 74, 75 and 77 (true): 0 LDX, 1 OR, 2 AND, 3 XOR,
+    /// 4 ADD, 5 SD, 7 SM.
+    fn alu(&mut self, operation: u8, operand: u8, with_carry: bool) {
+        let carry = (with_carry && self.df) as u16;
+        let borrow = (with_carry && !self.df) as u16;
+        match operation {
+            0 => self.d = operand,
+            1 => self.d |= operand,
+            2 => self.d &= operand,
+            3 => self.d ^= operand,
+            4 => {
+                let sum = self.d as u16 + operand as u16 + carry;
+                self.d = sum as u8;
+                self.df = sum > 0xFF;
+            }
+            5 => self.subtract(operand, self.d, borrow),
+            _ => self.subtract(self.d, operand, borrow),
+        }
+    }
+
+    fn subtract(&mut self, minuend: u8, subtrahend: u8, borrow: u16) {
+        let difference = (minuend as u16).wrapping_sub(subtrahend as u16 + borrow);
+        self.d = difference as u8;
+        // DF is 1 when there was no borrow.
+        self.df = difference <= 0xFF;
+    }
+
+    // SHR/SHRC: with `through_df` the old DF is shifted in.
+    fn shift_right(&mut self, through_df: bool) {
+        let df = self.df;
+        self.df = self.d & 0x01 != 0;
+        self.d = self.d >> 1 | ((through_df && df) as u8) << 7;
+    }
+
+    // SHL/SHLC
+    fn shift_left(&mut self, through_df: bool) {
+        let df = self.df;
+        self.df = self.d & 0x80 != 0;
+        self.d = self.d << 1 | (through_df && df) as u8;
+    }
+
+    // Condition of the short branch 3N, where 38-3F are the negations of 30-37.
+    fn condition(&mut self, n: u8, bus: &mut dyn Bus) -> bool {
+        let condition = match n & 0x7 {
+            0 => true,
+            1 => self.q,
+            2 => self.d == 0,
+            3 => self.df,
+            flag => bus.flag(flag - 3),
+        };
+        condition != (n >= 8)
+    }
+
+    fn short_branch(&mut self, taken: bool, bus: &mut dyn Bus) {
+        let p = self.p as usize;
+        if taken {
+            let target = bus.read(self.r[p]);
+            self.r[p] = self.r[p] & 0xFF00 | target as u16;
+        } else {
+            self.r[p] = self.r[p].wrapping_add(1);
+        }
+    }
+
+    // CN: long branches C0-C3 and C8-CB, NOP C4 and long skips C5-C7 and CC-CF.
+    fn long_branch(&mut self, n: u8, bus: &mut dyn Bus) {
+        let p = self.p as usize;
+        let condition = match n & 0x3 {
+            0 => true,
+            1 => self.q,
+            2 => self.d == 0,
+            _ => self.df,
+        };
+        match n {
+            0x4 => {}
+            0xC => {
+                // LSIE
+                if self.ie {
+                    self.r[p] = self.r[p].wrapping_add(2);
+                }
+            }
+            0x5..=0x7 | 0xD..=0xF => {
+                // Long skips: C5-C7 skip when the condition is false, CD-CF when it is true.
+                if condition == (n >= 8) {
+                    self.r[p] = self.r[p].wrapping_add(2);
+                }
+            }
+            _ => {
+                if condition != (n >= 8) {
+                    let high = bus.read(self.r[p]) as u16;
+                    let low = bus.read(self.r[p].wrapping_add(1)) as u16;
+                    self.r[p] = high << 8 | low;
+                } else {
+                    self.r[p] = self.r[p].wrapping_add(2);
+                }
+            }
+        }
+    }
+}
+
+impl Default for Cdp1802 {
+    fn default() -> Cdp1802 {
+        Cdp1802::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Ram {
+        memory: Vec<u8>,
+        outputs: Vec<(u8, u8)>,
+        flags: [bool; 4],
+    }
+
+    impl Bus for Ram {
+        fn read(&mut self, address: u16) -> u8 {
+            self.memory[address as usize]
+        }
+
+        fn write(&mut self, address: u16, value: u8) {
+            self.memory[address as usize] = value;
+        }
+
+        fn output(&mut self, port: u8, value: u8) {
+            self.outputs.push((port, value));
+        }
+
+        fn input(&mut self, port: u8) -> u8 {
+            port * 0x11
+        }
+
+        fn flag(&mut self, flag: u8) -> bool {
+            self.flags[flag as usize - 1]
+        }
+    }
+
+    // Runs `program` from 0x0000 until it reaches `end` and returns the machine cycles taken.
+    fn run(program: &[u8], end: u16) -> (Cdp1802, Ram, u32) {
+        let mut ram = Ram {
+            memory: vec![0; 0x100],
+            outputs: Vec::new(),
+            flags: [false, false, true, false],
+        };
+        ram.memory[..program.len()].copy_from_slice(program);
+        let mut cpu = Cdp1802::new();
+        let mut cycles = 0;
+        while cpu.r[cpu.p as usize] != end {
+            cycles += cpu.step(&mut ram);
+        }
+        (cpu, ram, cycles)
+    }
+
+    #[test]
+    fn cdp1802_arithmetic() {
+        // LDI 80, ADI 90: D = 10, DF = 1. ADCI 01: D = 12, DF = 0.
+        let (cpu, _, cycles) = run(&[0xF8, 0x80, 0xFC, 0x90], 4);
+        assert_eq!((cpu.d, cpu.df, cycles), (0x10, true, 4));
+        let (cpu, _, _) = run(&[0xF8, 0x80, 0xFC, 0x90, 0x7C, 0x01], 6);
+        assert_eq!((cpu.d, cpu.df), (0x12, false));
+
+        // SMI 01 from 00 borrows: DF = 0. SMBI 00 subtracts the borrow.
+        let (cpu, _, _) = run(&[0xF8, 0x00, 0xFF, 0x01, 0x7F, 0x00], 6);
+        assert_eq!((cpu.d, cpu.df), (0xFE, true));
+        // SDI 05: 5 - 3.
+        let (cpu, _, _) = run(&[0xF8, 0x03, 0xFD, 0x05], 4);
+        assert_eq!((cpu.d, cpu.df), (0x02, true));
+
+        // SHL shifts out 1, SHRC shifts it back in from the top.
+        let (cpu, _, _) = run(&[0xF8, 0x81, 0xFE, 0x76], 4);
+        assert_eq!((cpu.d, cpu.df), (0x81, false));
+    }
+
+    #[test]
+    fn cdp1802_registers_and_memory() {
+        // LDI 40, PLO 5, LDI 12, STR 5, INC 5, SEX 5, STXD, LDN 5 via R5 - 1, GHI/GLO.
+        let program = [0xF8, 0x40, 0xA5, 0xF8, 0x12, 0x55, 0x15, 0xE5, 0x73, 0xF0, 0x85];
+        let (cpu, ram, _) = run(&program, program.len() as u16);
+        assert_eq!(ram.memory[0x40], 0x12);
+        assert_eq!(ram.memory[0x41], 0x12);
+        assert_eq!(cpu.r[5], 0x40);
+        assert_eq!(cpu.d, 0x40);
+        assert_eq!(cpu.x, 5);
+    }
+
+    #[test]
+    fn cdp1802_branches() {
+        // BZ taken with D = 0, LBR to 0x0010 takes 3 cycles.
+        let (_, _, cycles) = run(&[0x32, 0x03, 0x00, 0xC0, 0x00, 0x10], 0x10);
+        assert_eq!(cycles, 2 + 3);
+        // B3 branches while EF3 is asserted, BN1 while EF1 is not.
+        let (_, _, cycles) = run(&[0x36, 0x04, 0x00, 0x00, 0x3C, 0x08], 0x08);
+        assert_eq!(cycles, 4);
+        // LSNQ skips LDI 11 while Q is off, SEQ, LSQ skips LDI 22.
+        let program = [0xC5, 0xF8, 0x11, 0x7B, 0xCD, 0xF8, 0x22];
+        let (cpu, _, cycles) = run(&program, program.len() as u16);
+        assert!(cpu.q);
+        assert_eq!(cpu.d, 0x00);
+        assert_eq!(cycles, 3 + 2 + 3);
+    }
+
+    #[test]
+    fn cdp1802_io() {
+        // SEX 0 (the program counter), OUT 2 with the next byte, INP 4 stores 0x44 there.
+        let (cpu, ram, _) = run(&[0xE0, 0x62, 0x05, 0x6C, 0x00], 4);
+        assert_eq!(ram.outputs, vec![(2, 0x05)]);
+        assert_eq!(cpu.d, 0x44);
+        assert_eq!(ram.memory[4], 0x44);
+    }
+
+    #[test]
+    fn cdp1802_interrupt() {
+        // The main program sets R1 to the routine at 0x10 and R2 to the stack at 0x80. The
+        // routine saves T and returns with RET.
+        let mut program = vec![0xF8, 0x10, 0xA1, 0xF8, 0x80, 0xA2, 0x30, 0x06];
+        program.resize(0x0F, 0);
+        program.extend_from_slice(&[0x70, 0x22, 0x78, 0x30, 0x0F]);
+        let (mut cpu, mut ram, _) = run(&program, 6);
+        cpu.x = 3;
+        cpu.interrupt();
+        assert_eq!((cpu.p, cpu.x, cpu.t, cpu.ie), (1, 2, 0x30, false));
+        while cpu.p != 0 {
+            cpu.step(&mut ram);
+        }
+        assert_eq!((cpu.x, cpu.ie, cpu.r[2]), (3, true, 0x80));
+        assert_eq!(ram.memory[0x7F], 0x30);
+
+        // DMA reads at R0 and wakes up IDL.
+        cpu.idle = true;
+        cpu.r[0] = 0x0F;
+        assert_eq!(cpu.dma_out(&mut ram), 0x70);
+        assert_eq!(cpu.r[0], 0x10);
+        assert!(!cpu.idle);
+    }
+}
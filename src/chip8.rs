@@ -1,30 +1,102 @@
 use super::*;
 
-use std::sync::{Arc, Mutex, Condvar};
-use std::time::Duration;
+use std::fmt;
+use std::io::{self, Write};
+use std::sync::Arc;
 
-use opcode::Opcode;
+use rand::{Rng, SeedableRng, XorShiftRng};
+
+use instruction::{self, Instruction};
 use gfx::GfxMemory;
 use register::Register;
 use keyboard::Keyboard;
+use input::{InputQueue, Recording};
 use memory::Memory;
+use frontend::Frontend;
+use cache::DecodeCache;
+use quirks::Quirks;
+use layout::Layout;
+use font::Font;
+use timing::{self, Timing};
+use config;
+use database::{Database, RomInfo};
+use profile::Profile;
+use coverage::Coverage;
+use stack::StackError;
+
+pub const PROGRAM_START: usize = 0x200;
+pub const CYCLES_PER_FRAME: usize = 10;
+/// The program counter wraps around at the end of the 4 KB of memory.
+pub const ADDRESS_MASK: u16 = 0x0FFF;
+
+/// Execution state of the interpreter.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum State {
+    Running,
+    /// Halted on `FX0A` until a key is pressed into `register`. With the key release quirk
+    /// `pressed` holds the key waiting to be released.
+    WaitingForKey { register: u8, pressed: Option<u8> },
+    /// Halted on `DXYN` until the next vertical blank at the start of a frame (display wait
+    /// quirk).
+    WaitingForVblank,
+    /// Stopped by `fault` at `address`.
+    Halted { fault: Fault, address: u16 },
+}
 
-const PROGRAM_START: usize = 0x200;
-const FONT_SET_SIZE: usize = 80;
-const FONT_SET: [u8; FONT_SET_SIZE] =
-    [0xF0, 0x90, 0x90, 0x90, 0xF0, 0x20, 0x60, 0x20, 0x20, 0x70, 0xF0, 0x10, 0xF0, 0x80, 0xF0,
-     0xF0, 0x10, 0xF0, 0x10, 0xF0, 0x90, 0x90, 0xF0, 0x10, 0x10, 0xF0, 0x80, 0xF0, 0x10, 0xF0,
-     0xF0, 0x80, 0xF0, 0x90, 0xF0, 0xF0, 0x10, 0x20, 0x40, 0x40, 0xF0, 0x90, 0xF0, 0x90, 0xF0,
-     0xF0, 0x90, 0xF0, 0x10, 0xF0, 0xF0, 0x90, 0xF0, 0x90, 0x90, 0xE0, 0x90, 0xE0, 0x90, 0xE0,
-     0xF0, 0x80, 0x80, 0x80, 0xF0, 0xE0, 0x90, 0x90, 0x90, 0xE0, 0xF0, 0x80, 0xF0, 0x80, 0xF0,
-     0xF0, 0x80, 0xF0, 0x80, 0x80];
+/// Why the interpreter halted.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Fault {
+    /// A `2NNN` with a full stack or a `00EE` with an empty one.
+    Stack(StackError),
+    /// A SUPER-CHIP or XO-CHIP instruction, which the interpreter decodes but does not run.
+    Unsupported(Instruction),
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Fault::Stack(error) => write!(f, "{}", error),
+            Fault::Unsupported(instruction) => {
+                write!(f, "Unsupported instruction {}", instruction)
+            }
+        }
+    }
+}
 
 pub struct Chip8 {
-    reg_v: Arc<Mutex<Register>>,
-    reg_gfx: Arc<Mutex<GfxMemory>>,
-    stack: Arc<Mutex<stack::Stack>>,
-    keys: Arc<Mutex<Keyboard>>,
+    reg_v: Register,
+    reg_gfx: GfxMemory,
+    stack: stack::Stack,
+    keys: Keyboard,
+    input: InputQueue,
+    recording: Option<Recording>,
+    profile: Option<Profile>,
+    coverage: Option<Coverage>,
     memory: Memory,
+    decode_cache: DecodeCache<Instruction>,
+    decode_cache_enabled: bool,
+    quirks: Quirks,
+    layout: Layout,
+    font: Font,
+    cycles_per_frame: usize,
+    timing: Timing,
+    // Machine cycles left in the current frame with VIP timing, negative when an instruction
+    // ran over into the next frame.
+    cycle_budget: i64,
+    start_address: usize,
+    start_address_set: bool,
+    database: Option<Arc<Database>>,
+    rom_info: Option<RomInfo>,
+    rng: XorShiftRng,
+    trace: Option<Box<dyn Write>>,
+    trace_error: Option<io::Error>,
+    state: State,
+    // No instruction was executed since the last vertical blank.
+    vblank: bool,
+    // Keys already down when FX0A started, ignored until they are released.
+    held_keys: [bool; keyboard::NUM_KEYS],
+    // Instructions executed since `initialize`.
+    instructions: u64,
 
     program_counter: u16,
 
@@ -34,14 +106,35 @@ pub struct Chip8 {
 
 impl Chip8 {
     pub fn new() -> Chip8 {
-        let gfx = Arc::new(Mutex::new(GfxMemory::new()));
-        let keys = Arc::new(Mutex::new(Keyboard::new()));
         Chip8 {
-            stack: Arc::new(Mutex::new(stack::Stack::new())),
-            reg_v: Arc::new(Mutex::new(Register::new())),
-            reg_gfx: gfx.clone(),
-            keys: keys.clone(),
+            stack: stack::Stack::new(),
+            reg_v: Register::new(),
+            reg_gfx: GfxMemory::new(),
+            keys: Keyboard::new(),
+            input: InputQueue::new(),
+            recording: None,
+            profile: None,
+            coverage: None,
             memory: Memory::new(),
+            decode_cache: DecodeCache::new(memory::MEMORY_SIZE),
+            decode_cache_enabled: true,
+            quirks: Quirks::default(),
+            layout: Layout::default(),
+            font: Font::default(),
+            cycles_per_frame: CYCLES_PER_FRAME,
+            timing: Timing::default(),
+            cycle_budget: 0,
+            start_address: PROGRAM_START,
+            start_address_set: false,
+            database: Some(Database::bundled()),
+            rom_info: None,
+            rng: rand::weak_rng(),
+            trace: None,
+            trace_error: None,
+            state: State::Running,
+            vblank: true,
+            held_keys: [false; keyboard::NUM_KEYS],
+            instructions: 0,
             program_counter: 0,
             delay_timer: 0,
             sound_timer: 0,
@@ -49,62 +142,170 @@ impl Chip8 {
     }
 
     pub fn initialize(&mut self) {
-        self.reg_v.as_ref().lock().unwrap().clear();
-        self.reg_gfx.as_ref().lock().unwrap().clear();
-        self.program_counter = PROGRAM_START as u16;
-        self.memory.store(0, &FONT_SET);
-    }
-
-    pub fn load_rom(&mut self, rom: Vec<u8>) {
-        self.memory.store(PROGRAM_START, &rom)
-    }
-
-    #[cfg(not(test))]
-    pub fn run(&mut self) -> Result<(), error::Chip8Error> {
-        let gfx = self.reg_gfx.clone();
-        let keys = self.keys.clone();
-        let shutdown = Arc::new((Mutex::new(false), Condvar::new()));
-        let started = Arc::new((Mutex::new(false), Condvar::new()));
-        let render_shutdown = shutdown.clone();
-        let render_started = started.clone();
-        let rendering = std::thread::spawn(move || {
-                                               renderer::Renderer::start(gfx,
-                                                                         keys,
-                                                                         render_shutdown,
-                                                                         render_started);
-                                           });
-
-        loop {
-            let &(ref lock, ref condition) = &*started;
-            let start = lock.lock().unwrap();
-            if !*start {
-                let _ = condition
-                    .wait_timeout(start, Duration::from_millis(100))
-                    .unwrap()
-                    .0;
-            } else {
-                break;
+        self.reg_v.clear();
+        self.reg_gfx.clear();
+        self.program_counter = self.start_address as u16;
+        self.state = State::Running;
+        self.cycle_budget = 0;
+        self.instructions = 0;
+        let font_address = self.layout.font_address;
+        self.memory.store(font_address, &self.font.small);
+        self.memory.store(font_address + self.font.small.len(), &self.font.large);
+        self.decode_cache.clear();
+    }
+
+    /// Loads `rom` and applies the settings the ROM database recommends for it.
+    ///
+    /// Fails if the ROM does not fit between the start address and the memory the interpreter
+    /// reserves.
+    pub fn load_rom(&mut self, rom: Vec<u8>) -> Result<(), error::Chip8Error> {
+        let info = self.database
+            .as_ref()
+            .and_then(|database| database.lookup(&config::rom_hash(&rom)));
+        self.load_program(rom, info)
+    }
+
+    /// Loads `rom` and applies the settings of `info` instead of those of the ROM database,
+    /// e.g. the options of an Octo cartridge.
+    pub fn load_program(&mut self,
+                        rom: Vec<u8>,
+                        info: Option<RomInfo>)
+                        -> Result<(), error::Chip8Error> {
+        self.rom_info = info;
+        if let Some(ref info) = self.rom_info {
+            self.quirks = info.quirks;
+            if let Some(tickrate) = info.tickrate {
+                self.cycles_per_frame = tickrate;
+            }
+            match info.start_address {
+                Some(address) if !self.start_address_set => {
+                    self.start_address = address;
+                    self.program_counter = address as u16;
+                }
+                _ => {}
             }
         }
 
-        let &(ref lock, ref condition) = &*shutdown;
-        let mut stopped = lock.lock().unwrap();
-        while !*stopped {
-            self.cycle();
-            stopped = condition
-                .wait_timeout(stopped, Duration::new(0, 180))
-                .unwrap()
-                .0;
+        let capacity = self.layout.capacity(self.start_address);
+        if rom.len() > capacity {
+            let msg = format!("ROM of {} bytes does not fit into the {} bytes from 0x{:03X}",
+                              rom.len(),
+                              capacity,
+                              self.start_address);
+            return Err(error::Chip8Error::Message(msg));
+        }
+        self.memory.store(self.start_address, &rom);
+        self.decode_cache.clear();
+        Ok(())
+    }
+
+    pub fn run(&mut self, frontend: &mut dyn Frontend) -> Result<(), error::Chip8Error> {
+        while !frontend.quit_requested() {
+            frontend.poll_input(&mut self.input);
+            let applied = self.input.apply(&mut self.keys);
+            if let Some(ref mut recording) = self.recording {
+                recording.events.extend(applied);
+            }
+            self.step_frame();
+            frontend.wait_for_key(matches!(self.state, State::WaitingForKey { .. }));
+            if let Some(err) = self.trace_error.take() {
+                return Err(err.into());
+            }
+            if let State::Halted { fault, address } = self.state {
+                let msg = format!("{} at 0x{:03X}", fault, address);
+                return Err(error::Chip8Error::Message(msg));
+            }
+            frontend.play_audio(self.sound_timer > 0);
+            frontend.present(&self.reg_gfx)?;
         }
 
-        try!(rendering.join());
         Ok(())
     }
 
-    fn cycle(&mut self) {
-        let opcode = self.fetch_opcode();
-        self.execute_opcode(opcode);
+    /// Executes the instructions of one frame. The frame starts with a vertical blank.
+    pub fn step_frame(&mut self) {
+        self.vblank = true;
+        if self.state == State::WaitingForVblank {
+            self.state = State::Running;
+        }
+        match self.timing {
+            Timing::Instructions => {
+                for _ in 0..self.cycles_per_frame {
+                    self.cycle();
+                }
+            }
+            Timing::Vip => {
+                self.tick_timers();
+                self.cycle_budget += timing::VIP_FRAME_BUDGET as i64;
+                while self.cycle_budget > 0 {
+                    let cycles = self.cycle();
+                    if cycles == 0 && self.state != State::Running {
+                        // Waiting for a key or the next vertical blank uses up the frame.
+                        self.cycle_budget = 0;
+                    }
+                    self.cycle_budget -= cycles as i64;
+                }
+            }
+        }
+    }
+
+    /// Executes one instruction and returns the machine cycles the COSMAC VIP takes for it,
+    /// or 0 without VIP timing and while waiting. Without VIP timing the timers count down as
+    /// well.
+    pub fn cycle(&mut self) -> u32 {
+        let cycles = self.run_instruction();
+        if self.timing == Timing::Instructions {
+            self.tick_timers();
+        }
+        cycles
+    }
 
+    fn run_instruction(&mut self) -> u32 {
+        match self.state {
+            State::Running => {
+                let instruction = self.fetch_instruction();
+                match instruction {
+                    Instruction::Drw { .. } if self.quirks.display_wait && !self.vblank => {
+                        self.state = State::WaitingForVblank;
+                        0
+                    }
+                    _ => {
+                        self.trace(instruction);
+                        self.instructions += 1;
+                        if let Some(ref mut coverage) = self.coverage {
+                            coverage.execute(self.program_counter as usize);
+                        }
+                        if self.timing == Timing::Instructions && self.profile.is_none() {
+                            self.execute(instruction);
+                            self.vblank = false;
+                            return 0;
+                        }
+                        let address = self.program_counter;
+                        let mut cycles = timing::vip_cycles(instruction, &self.reg_v);
+                        if self.execute(instruction) {
+                            cycles += timing::vip_skip_cycles(instruction);
+                        }
+                        self.vblank = false;
+                        if let Some(ref mut profile) = self.profile {
+                            profile.record(address, instruction, cycles);
+                        }
+                        match self.timing {
+                            Timing::Instructions => 0,
+                            Timing::Vip => cycles,
+                        }
+                    }
+                }
+            }
+            State::WaitingForKey { register, pressed } => {
+                self.wait_for_key(register, pressed);
+                0
+            }
+            State::WaitingForVblank |
+            State::Halted { .. } => 0,
+        }
+    }
+
+    fn tick_timers(&mut self) {
         if self.delay_timer > 0 {
             self.delay_timer -= 1;
         }
@@ -114,274 +315,779 @@ impl Chip8 {
         }
     }
 
-    fn fetch_opcode(&self) -> Opcode {
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    /// Returns the number of instructions executed since `initialize`, not counting those
+    /// waiting for a key or the vertical blank.
+    pub fn instructions(&self) -> u64 {
+        self.instructions
+    }
+
+    pub fn program_counter(&self) -> u16 {
+        self.program_counter
+    }
+
+    pub fn registers(&self) -> &Register {
+        &self.reg_v
+    }
+
+    pub fn memory(&self) -> &Memory {
+        &self.memory
+    }
+
+    /// Returns the number of subroutines `2NNN` entered and not yet returned from.
+    pub fn stack_depth(&self) -> usize {
+        self.stack.depth()
+    }
+
+    pub fn delay_timer(&self) -> u8 {
+        self.delay_timer
+    }
+
+    pub fn sound_timer(&self) -> u8 {
+        self.sound_timer
+    }
+
+    /// Starts recording the key changes applied by `run`.
+    pub fn record(&mut self) {
+        self.recording = Some(Recording::new());
+    }
+
+    pub fn take_recording(&mut self) -> Option<Recording> {
+        self.recording.take()
+    }
+
+    /// Starts profiling the executed instructions, see `profile::Profile`.
+    pub fn profile(&mut self) {
+        self.profile = Some(Profile::new(self.program_counter));
+    }
+
+    pub fn take_profile(&mut self) -> Option<Profile> {
+        self.profile.take()
+    }
+
+    /// Starts recording the coverage of memory, see `coverage::Coverage`.
+    pub fn record_coverage(&mut self) {
+        self.coverage = Some(Coverage::new());
+    }
+
+    pub fn take_coverage(&mut self) -> Option<Coverage> {
+        self.coverage.take()
+    }
+
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    pub fn gfx(&self) -> &GfxMemory {
+        &self.reg_gfx
+    }
+
+    pub fn speed(&self) -> usize {
+        self.cycles_per_frame
+    }
+
+    /// Sets the number of instructions executed per frame.
+    pub fn set_speed(&mut self, cycles_per_frame: usize) {
+        self.cycles_per_frame = cycles_per_frame;
+    }
+
+    pub fn timing(&self) -> Timing {
+        self.timing
+    }
+
+    /// Selects how many instructions a frame runs: the speed, or as many as the COSMAC VIP
+    /// would. With VIP timing the timers count down once per frame instead of once per
+    /// instruction.
+    pub fn set_timing(&mut self, timing: Timing) {
+        self.timing = timing;
+        self.cycle_budget = 0;
+    }
+
+    /// Sets where the program, the font and the reserved interpreter memory are. Call before
+    /// `initialize`.
+    pub fn set_layout(&mut self, layout: Layout) {
+        self.layout = layout;
+        if !self.start_address_set {
+            self.start_address = layout.program_start;
+        }
+    }
+
+    /// Sets the font `initialize` stores at the font address of the layout.
+    pub fn set_font(&mut self, font: Font) {
+        self.font = font;
+    }
+
+    /// Sets where `initialize` starts execution and `load_rom` loads the program, overriding
+    /// the layout and the ROM database.
+    pub fn start_address(&self) -> usize {
+        self.start_address
+    }
+
+    pub fn set_start_address(&mut self, address: usize) {
+        self.start_address = address;
+        self.start_address_set = true;
+    }
+
+    /// Replaces the bundled ROM database. `None` turns off automatic configuration.
+    pub fn set_database(&mut self, database: Option<Arc<Database>>) {
+        self.database = database;
+    }
+
+    /// Returns the database entry of the loaded ROM.
+    pub fn rom_info(&self) -> Option<&RomInfo> {
+        self.rom_info.as_ref()
+    }
+
+    /// Makes `CXNN` produce the same numbers on every run.
+    pub fn set_seed(&mut self, seed: u64) {
+        // XorShift must not be seeded with all zeroes.
+        self.rng = XorShiftRng::from_seed([seed as u32, (seed >> 32) as u32, 0x9E37_79B9,
+                                           0x7F4A_7C15]);
+    }
+
+    /// Writes the address and mnemonic of every executed instruction to `trace`.
+    pub fn set_trace(&mut self, trace: Box<dyn Write>) {
+        self.trace = Some(trace);
+    }
+
+    fn trace(&mut self, instruction: Instruction) {
+        let result = match self.trace {
+            Some(ref mut trace) => writeln!(trace, "{:03X}: {}", self.program_counter, instruction),
+            None => return,
+        };
+        if let Err(err) = result {
+            self.trace = None;
+            self.trace_error = Some(err);
+        }
+    }
+
+    /// Enables or disables the decoded instruction cache.
+    pub fn set_decode_cache(&mut self, enabled: bool) {
+        self.decode_cache_enabled = enabled;
+        self.decode_cache.clear();
+    }
+
+    fn fetch_instruction(&mut self) -> Instruction {
         let index = self.program_counter as usize;
-        let opcode = self.memory.load16(index);
-        Opcode::new(opcode)
+        if self.decode_cache_enabled {
+            if let Some(instruction) = self.decode_cache.get(index) {
+                return instruction;
+            }
+        }
+
+        let instruction = instruction::decode(self.memory.load16(index));
+        if self.decode_cache_enabled {
+            self.decode_cache.insert(index, instruction);
+        }
+        instruction
+    }
+
+    fn load8(&mut self, index: usize) -> u8 {
+        if let Some(ref mut coverage) = self.coverage {
+            coverage.load(index);
+        }
+        self.memory.load8(index)
+    }
+
+    fn store8(&mut self, index: usize, element: u8) {
+        let index = index % memory::MEMORY_SIZE;
+        if let Some(ref mut coverage) = self.coverage {
+            coverage.store(index);
+        }
+        self.memory.store8(index, element);
+        self.decode_cache.invalidate(index);
     }
 
     fn display(&mut self, x: usize, y: usize, height: u8) {
-        self.reg_v.as_ref().lock().unwrap()[0xF] = 0x00;
-        let reg_v = self.reg_v.clone();
-        let reg_i = reg_v.lock().unwrap().reg_i;
-        let reg_gfx = self.reg_gfx.clone();
-        for y_line in 0..height {
-            let memory_position = (reg_i + y_line as u16) as usize;
-            let pixel = self.memory.load8(memory_position);
-            for x_line in 0..8 {
-                if (pixel & (0x80 >> x_line)) != 0x00 {
-                    let x = reg_v.lock().unwrap()[x];
-                    let y = reg_v.lock().unwrap()[y];
-                    let gfx_position = (x as usize + x_line +
-                                        ((y as usize + y_line as usize) * super::DISPLAY_WIDTH)) %
-                                       gfx::GFX_MEMORY_SIZE;
-                    let current_pixel = reg_gfx.lock().unwrap()[gfx_position as usize];
-                    reg_v.lock().unwrap()[0xF] = current_pixel & 0x01;
-
-                    reg_gfx.lock().unwrap()[gfx_position as usize] = !current_pixel;
+        let reg_i = self.reg_v.reg_i as usize;
+        let sprite: Vec<u8> = (0..height as usize)
+            .map(|row| self.load8(reg_i + row))
+            .collect();
+        self.reg_v[0xF] = self.reg_gfx.draw(self.reg_v[x],
+                                            self.reg_v[y],
+                                            &sprite,
+                                            self.quirks.clip,
+                                            self.quirks.collision_rows);
+    }
+
+    fn wait_for_key(&mut self, register: u8, pressed: Option<u8>) {
+        for index in 0..keyboard::NUM_KEYS {
+            if self.keys[index] == 0x00 {
+                self.held_keys[index] = false;
+            }
+        }
+
+        let key = match pressed {
+            Some(key) if self.keys[key as usize] == 0x00 => key,
+            Some(_) => return,
+            None => {
+                match (0..keyboard::NUM_KEYS)
+                          .find(|&index| self.keys[index] != 0x00 && !self.held_keys[index]) {
+                    Some(index) if self.quirks.key_release => {
+                        self.state = State::WaitingForKey {
+                            register,
+                            pressed: Some(index as u8),
+                        };
+                        return;
+                    }
+                    Some(index) => index as u8,
+                    None => return,
                 }
             }
+        };
+
+        self.reg_v[register as usize] = key;
+        self.state = State::Running;
+        self.program_counter = (self.program_counter + 2) & ADDRESS_MASK;
+    }
+
+    /// Returns the register a shift instruction operates on.
+    fn shift_source(&self, x: u8, y: u8) -> u8 {
+        if self.quirks.shift {
+            self.reg_v[x as usize]
+        } else {
+            self.reg_v[y as usize]
         }
     }
 
-    fn execute_opcode(&mut self, opcode: Opcode) {
-        let reg_v = self.reg_v.clone();
-        let reg_gfx = self.reg_gfx.clone();
-        match opcode.category {
-            0 => {
-                match opcode.byte {
-                    0xE0 => {
-                        reg_gfx.lock().unwrap().clear();
-                        self.program_counter += 2;
-                    }
-                    0xEE => {
-                        self.program_counter = self.stack.lock().unwrap().pop();
-                        self.program_counter += 2;
-                    }
-                    _ => {}
+    // Stops at the current instruction.
+    fn halt(&mut self, fault: Fault) {
+        self.state = State::Halted {
+            fault,
+            address: self.program_counter,
+        };
+    }
+
+    // Skips the next instruction if `condition` holds and returns whether it did.
+    fn skip_if(&mut self, condition: bool) -> bool {
+        if condition {
+            self.program_counter += 4;
+        } else {
+            self.program_counter += 2;
+        }
+        condition
+    }
+
+    // Executes `instruction` and returns whether it skipped the next one.
+    fn execute(&mut self, instruction: Instruction) -> bool {
+        use instruction::Instruction::*;
+
+        let mut skipped = false;
+        match instruction {
+            Cls => {
+                self.reg_gfx.clear();
+                self.program_counter += 2;
+            }
+            Ret => {
+                match self.stack.pop() {
+                    Ok(address) => self.program_counter = address + 2,
+                    Err(error) => self.halt(Fault::Stack(error)),
                 }
             }
-            1 => self.program_counter = opcode.address,
-            2 => {
-                self.stack.lock().unwrap().push(self.program_counter);
-                self.program_counter = opcode.address;
+            Jp(address) => self.program_counter = address,
+            Call(address) => {
+                match self.stack.push(self.program_counter) {
+                    Ok(()) => self.program_counter = address,
+                    Err(error) => self.halt(Fault::Stack(error)),
+                }
             }
-            3 => {
-                if reg_v.lock().unwrap()[opcode.x] == opcode.byte {
-                    self.program_counter += 4;
-                } else {
-                    self.program_counter += 2;
+            SeByte(x, byte) => {
+                let condition = self.reg_v[x as usize] == byte;
+                skipped = self.skip_if(condition);
+            }
+            SneByte(x, byte) => {
+                let condition = self.reg_v[x as usize] != byte;
+                skipped = self.skip_if(condition);
+            }
+            SeReg(x, y) => {
+                let condition = self.reg_v[x as usize] == self.reg_v[y as usize];
+                skipped = self.skip_if(condition);
+            }
+            LdByte(x, byte) => {
+                self.reg_v[x as usize] = byte;
+                self.program_counter += 2;
+            }
+            AddByte(x, byte) => {
+                let vx = self.reg_v[x as usize];
+                self.reg_v[x as usize] = vx.wrapping_add(byte);
+                self.program_counter += 2;
+            }
+            LdReg(x, y) => {
+                self.reg_v[x as usize] = self.reg_v[y as usize];
+                self.program_counter += 2;
+            }
+            Or(x, y) => {
+                let vy = self.reg_v[y as usize];
+                self.reg_v[x as usize] |= vy;
+                if self.quirks.logic {
+                    self.reg_v[0xF] = 0;
                 }
+                self.program_counter += 2;
             }
-            4 => {
-                if reg_v.lock().unwrap()[opcode.x] != opcode.byte {
-                    self.program_counter += 4;
-                } else {
-                    self.program_counter += 2;
+            And(x, y) => {
+                let vy = self.reg_v[y as usize];
+                self.reg_v[x as usize] &= vy;
+                if self.quirks.logic {
+                    self.reg_v[0xF] = 0;
                 }
+                self.program_counter += 2;
             }
-            5 => {
-                let x = reg_v.lock().unwrap()[opcode.x];
-                let y = reg_v.lock().unwrap()[opcode.y];
-                if x == y {
-                    self.program_counter += 4;
-                } else {
-                    self.program_counter += 2;
+            Xor(x, y) => {
+                let vy = self.reg_v[y as usize];
+                self.reg_v[x as usize] ^= vy;
+                if self.quirks.logic {
+                    self.reg_v[0xF] = 0;
                 }
+                self.program_counter += 2;
             }
-            6 => {
-                reg_v.lock().unwrap()[opcode.x] = opcode.byte;
+            AddReg(x, y) => {
+                let vx = self.reg_v[x as usize];
+                let vy = self.reg_v[y as usize];
+                self.reg_v[x as usize] = vx.wrapping_add(vy);
+                self.reg_v[0xF] = ((vx as u16 + vy as u16) > 0xFF) as u8;
                 self.program_counter += 2;
             }
-            7 => {
-                let x = reg_v.lock().unwrap()[opcode.x];
-                reg_v.lock().unwrap()[opcode.x] = x.wrapping_add(opcode.byte);
+            Sub(x, y) => {
+                let vx = self.reg_v[x as usize];
+                let vy = self.reg_v[y as usize];
+                self.reg_v[x as usize] = vx.wrapping_sub(vy);
+                self.reg_v[0xF] = (vx >= vy) as u8;
                 self.program_counter += 2;
             }
-            8 => {
-                match opcode.nibble {
-                    0 => {
-                        let y = reg_v.lock().unwrap()[opcode.y];
-                        reg_v.lock().unwrap()[opcode.x] = y;
-                        self.program_counter += 2;
-                    }
-                    1 => {
-                        let y = reg_v.lock().unwrap()[opcode.y];
-                        reg_v.lock().unwrap()[opcode.x] |= y;
-                        self.program_counter += 2;
-                    }
-                    2 => {
-                        let y = reg_v.lock().unwrap()[opcode.y];
-                        reg_v.lock().unwrap()[opcode.x] &= y;
-                        self.program_counter += 2;
-                    }
-                    3 => {
-                        let y = reg_v.lock().unwrap()[opcode.y];
-                        reg_v.lock().unwrap()[opcode.x] ^= y;
-                        self.program_counter += 2;
-                    }
-                    4 => {
-                        let x = reg_v.lock().unwrap()[opcode.x];
-                        let y = reg_v.lock().unwrap()[opcode.y];
-                        reg_v.lock().unwrap()[0xF] = ((x as u16 + y as u16) > 0xFF) as u8;
-
-                        reg_v.lock().unwrap()[opcode.x] = x.wrapping_add(y);
-                        self.program_counter += 2;
-                    }
-                    5 => {
-                        let x = reg_v.lock().unwrap()[opcode.x];
-                        let y = reg_v.lock().unwrap()[opcode.y];
-                        reg_v.lock().unwrap()[0xF] = (x >= y) as u8;
-                        reg_v.lock().unwrap()[opcode.x] = x.wrapping_sub(y);
-                        self.program_counter += 2;
-                    }
-                    6 => {
-                        let carry = reg_v.lock().unwrap()[opcode.x] & 0x01;
-                        reg_v.lock().unwrap()[0xF] = carry;
-                        reg_v.lock().unwrap()[opcode.x] >>= 1;
-                        self.program_counter += 2;
-                    }
-                    7 => {
-                        let x = reg_v.lock().unwrap()[opcode.x];
-                        let y = reg_v.lock().unwrap()[opcode.y];
-                        reg_v.lock().unwrap()[0xF] = (y >= x) as u8;
-                        reg_v.lock().unwrap()[opcode.x] = y.wrapping_sub(x);
-                        self.program_counter += 2;
-                    }
-                    0xE => {
-                        let carry = (reg_v.lock().unwrap()[opcode.x] & 0x80) >> 7;
-                        reg_v.lock().unwrap()[0xF] = carry;
-                        reg_v.lock().unwrap()[opcode.x] <<= 1;
-                        self.program_counter += 2;
-                    }
-                    _ => {}
-                }
+            Shr(x, y) => {
+                let value = self.shift_source(x, y);
+                self.reg_v[x as usize] = value >> 1;
+                self.reg_v[0xF] = value & 0x01;
+                self.program_counter += 2;
             }
-            9 => {
-                let x = reg_v.lock().unwrap()[opcode.x];
-                let y = reg_v.lock().unwrap()[opcode.y];
-                if x != y {
-                    self.program_counter += 4;
+            Subn(x, y) => {
+                let vx = self.reg_v[x as usize];
+                let vy = self.reg_v[y as usize];
+                self.reg_v[x as usize] = vy.wrapping_sub(vx);
+                self.reg_v[0xF] = (vy >= vx) as u8;
+                self.program_counter += 2;
+            }
+            Shl(x, y) => {
+                let value = self.shift_source(x, y);
+                self.reg_v[x as usize] = value << 1;
+                self.reg_v[0xF] = (value & 0x80) >> 7;
+                self.program_counter += 2;
+            }
+            SneReg(x, y) => {
+                let condition = self.reg_v[x as usize] != self.reg_v[y as usize];
+                skipped = self.skip_if(condition);
+            }
+            LdI(address) => {
+                self.reg_v.reg_i = address;
+                self.program_counter += 2;
+            }
+            JpV0(address) => {
+                let register = if self.quirks.jump {
+                    (address >> 8) as usize
                 } else {
-                    self.program_counter += 2;
+                    0
+                };
+                self.program_counter = address + self.reg_v[register] as u16;
+            }
+            Rnd(x, byte) => {
+                self.reg_v[x as usize] = self.rng.gen::<u8>() & byte;
+                self.program_counter += 2;
+            }
+            Drw { x, y, n } => {
+                self.display(x as usize, y as usize, n);
+                self.program_counter += 2;
+            }
+            Skp(x) => {
+                let condition = self.keys[(self.reg_v[x as usize] & 0x0F) as usize] != 0;
+                skipped = self.skip_if(condition);
+            }
+            Sknp(x) => {
+                let condition = self.keys[(self.reg_v[x as usize] & 0x0F) as usize] == 0;
+                skipped = self.skip_if(condition);
+            }
+            LdVxDt(x) => {
+                self.reg_v[x as usize] = self.delay_timer;
+                self.program_counter += 2;
+            }
+            LdVxK(x) => {
+                for index in 0..keyboard::NUM_KEYS {
+                    self.held_keys[index] = self.keys[index] != 0x00;
                 }
+                self.state = State::WaitingForKey {
+                    register: x,
+                    pressed: None,
+                };
             }
-            0xA => {
-                reg_v.lock().unwrap().reg_i = opcode.address;
+            LdDt(x) => {
+                self.delay_timer = self.reg_v[x as usize];
                 self.program_counter += 2;
             }
-            0xB => {
-                self.program_counter = opcode.address + reg_v.lock().unwrap()[0] as u16;
+            LdSt(x) => {
+                self.sound_timer = self.reg_v[x as usize];
+                self.program_counter += 2;
             }
-            0xC => {
-                reg_v.lock().unwrap()[opcode.x] = rand::random::<u8>() & opcode.byte;
+            AddI(x) => {
+                let i = self.reg_v.reg_i.wrapping_add(self.reg_v[x as usize] as u16);
+                if self.quirks.add_i_overflow {
+                    self.reg_v[0xF] = (i > 0xFFF) as u8;
+                }
+
+                self.reg_v.reg_i = i;
                 self.program_counter += 2;
             }
-            0xD => {
-                self.display(opcode.x, opcode.y, opcode.nibble);
+            LdF(x) => {
+                let digit = self.reg_v[x as usize];
+                self.reg_v.reg_i = self.font.small_digit(self.layout.font_address, digit) as u16;
                 self.program_counter += 2;
             }
-            0xE => {
-                match opcode.byte {
-                    0x9E => {
-                        if self.keys.lock().unwrap()[reg_v.lock().unwrap()[opcode.x] as usize] !=
-                           0 {
-                            self.program_counter += 4;
-                        } else {
-                            self.program_counter += 2;
-                        }
-                    }
-                    0xA1 => {
-                        if self.keys.lock().unwrap()[reg_v.lock().unwrap()[opcode.x] as usize] ==
-                           0 {
-                            self.program_counter += 4;
-                        } else {
-                            self.program_counter += 2;
-                        }
-                    }
-                    _ => panic!("Invalid OpCode!"),
+            LdHf(x) => {
+                let digit = self.reg_v[x as usize];
+                self.reg_v.reg_i = self.font.large_digit(self.layout.font_address, digit) as u16;
+                self.program_counter += 2;
+            }
+            LdB(x) => {
+                let vx = self.reg_v[x as usize];
+                let reg_i = self.reg_v.reg_i as usize;
+                self.store8(reg_i, vx / 100);
+                self.store8(reg_i + 1, (vx / 10) % 10);
+                self.store8(reg_i + 2, vx % 100 % 10);
+                self.program_counter += 2;
+            }
+            LdIVx(x) => {
+                let reg_i = self.reg_v.reg_i as usize;
+                for index in 0..(x as usize + 1) {
+                    let value = self.reg_v[index];
+                    self.store8(reg_i + index, value);
                 }
+                if !self.quirks.load_store {
+                    self.reg_v.reg_i = self.reg_v.reg_i.wrapping_add(x as u16 + 1);
+                }
+
+                self.program_counter += 2;
             }
-            0xF => {
-                match opcode.byte {
-                    0x07 => {
-                        reg_v.lock().unwrap()[opcode.x] = self.delay_timer;
-                        self.program_counter += 2;
-                    }
-                    0x0A => {
-                        for index in 0..keyboard::NUM_KEYS {
-                            if self.keys.lock().unwrap()[index] != 0x00 {
-                                reg_v.lock().unwrap()[opcode.x] = index as u8;
-                                self.program_counter += 2;
-                                break;
-                            }
-                        }
-                    }
-                    0x15 => {
-                        self.delay_timer = reg_v.lock().unwrap()[opcode.x];
-                        self.program_counter += 2;
-                    }
-                    0x18 => {
-                        self.sound_timer = reg_v.lock().unwrap()[opcode.x];
-                        self.program_counter += 2;
-                    }
-                    0x1E => {
-                        let reg_i = reg_v.lock().unwrap().reg_i;
-                        let x = reg_v.lock().unwrap()[opcode.x];
-                        let i = reg_i + x as u16;
-                        if i > 0xFFF {
-                            reg_v.lock().unwrap()[0xF] = 1;
-                        } else {
-                            reg_v.lock().unwrap()[0xF] = 0;
-                        }
+            LdVxI(x) => {
+                let reg_i = self.reg_v.reg_i as usize;
+                for index in 0..(x as usize + 1) {
+                    self.reg_v[index] = self.load8(reg_i + index);
+                }
+                if !self.quirks.load_store {
+                    self.reg_v.reg_i = self.reg_v.reg_i.wrapping_add(x as u16 + 1);
+                }
+
+                self.program_counter += 2;
+            }
+            Sys(_) | Unknown(_) => {}
+            _ => self.halt(Fault::Unsupported(instruction)),
+        }
+        self.program_counter &= ADDRESS_MASK;
+        skipped
+    }
+}
+
+impl Default for Chip8 {
+    fn default() -> Chip8 {
+        Chip8::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use proptest::prelude::*;
+
+    struct TestFrontend {
+        frames: usize,
+        max_frames: usize,
+        pressed_key: Option<usize>,
+        audio: bool,
+        waiting_for_key: bool,
+    }
+
+    impl TestFrontend {
+        fn new(max_frames: usize, pressed_key: Option<usize>) -> TestFrontend {
+            TestFrontend {
+                frames: 0,
+                max_frames,
+                pressed_key,
+                audio: false,
+                waiting_for_key: false,
+            }
+        }
+    }
+
+    impl Frontend for TestFrontend {
+        fn present(&mut self, _: &GfxMemory) -> Result<(), error::Chip8Error> {
+            self.frames += 1;
+            Ok(())
+        }
+
+        // The key goes down after the first frame.
+        fn poll_input(&mut self, input: &mut InputQueue) {
+            if let Some(key) = self.pressed_key {
+                input.push(key as u8, self.frames > 0);
+            }
+        }
+
+        fn play_audio(&mut self, playing: bool) {
+            self.audio = playing;
+        }
+
+        fn wait_for_key(&mut self, waiting: bool) {
+            self.waiting_for_key = waiting;
+        }
+
+        fn quit_requested(&self) -> bool {
+            self.frames >= self.max_frames
+        }
+    }
+
+    #[test]
+    fn run_until_quit() {
+        let rom = vec![0xF0, 0x0A, 0x12, 0x02];
+
+        let mut chip = Chip8::new();
+        chip.initialize();
+        chip.load_rom(rom).unwrap();
+        let mut frontend = TestFrontend::new(2, Some(5));
+        chip.run(&mut frontend).unwrap();
+
+        assert_eq!(frontend.frames, 2);
+        assert_eq!(chip.reg_v[0], 0x05);
+        assert_eq!(chip.program_counter, 0x0202);
+        assert!(!frontend.waiting_for_key);
+    }
+
+    #[test]
+    fn run_records_and_replays_input() {
+        use headless::Headless;
+        use input::Replay;
+
+        let rom = vec![0xF0, 0x0A, 0x12, 0x02];
+
+        let mut chip = Chip8::new();
+        chip.initialize();
+        chip.load_rom(rom.clone()).unwrap();
+        chip.record();
+        chip.run(&mut TestFrontend::new(2, Some(5))).unwrap();
+        let recording = chip.take_recording().unwrap();
+        assert_eq!(recording.to_string(), "1 5 down\n");
+
+        let mut chip = Chip8::new();
+        chip.initialize();
+        chip.load_rom(rom).unwrap();
+        let mut replay = Replay::new(Box::new(Headless::new(Some(2))), recording);
+        chip.run(&mut replay).unwrap();
+        assert_eq!(chip.reg_v[0], 0x05);
+        assert_eq!(chip.program_counter, 0x0202);
+    }
+
+    #[test]
+    fn run_reports_key_wait() {
+        let rom = vec![0xF0, 0x0A];
+
+        let mut chip = Chip8::new();
+        chip.initialize();
+        chip.load_rom(rom).unwrap();
+        let mut frontend = TestFrontend::new(1, None);
+        chip.run(&mut frontend).unwrap();
+
+        assert!(frontend.waiting_for_key);
+        assert_eq!(chip.state(),
+                   State::WaitingForKey {
+                       register: 0,
+                       pressed: None,
+                   });
+    }
+
+    #[test]
+    fn run_plays_audio() {
+        let rom = vec![0x60, 0xFF, 0xF0, 0x18, 0x12, 0x04];
+
+        let mut chip = Chip8::new();
+        chip.initialize();
+        chip.load_rom(rom).unwrap();
+        let mut frontend = TestFrontend::new(1, None);
+        chip.run(&mut frontend).unwrap();
+
+        assert!(frontend.audio);
+    }
+
+    struct SharedBuffer(::std::rc::Rc<::std::cell::RefCell<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn speed_sets_cycles_per_frame() {
+        let rom = vec![0x70, 0x01, 0x12, 0x00];
+
+        let mut chip = Chip8::new();
+        chip.set_speed(20);
+        chip.initialize();
+        chip.load_rom(rom).unwrap();
+        chip.step_frame();
+
+        assert_eq!(chip.reg_v[0], 10);
+    }
+
+    #[test]
+    fn start_address() {
+        let rom = vec![0x60, 0x2A];
+
+        let mut chip = Chip8::new();
+        chip.set_start_address(0x600);
+        chip.initialize();
+        chip.load_rom(rom).unwrap();
+        chip.cycle();
+
+        assert_eq!(chip.reg_v[0], 0x2A);
+        assert_eq!(chip.program_counter, 0x0602);
+    }
+
+    #[test]
+    fn layout_moves_program_and_font() {
+        let rom = vec![0x60, 0x02, 0xF0, 0x29];
 
-                        reg_v.lock().unwrap().reg_i = i;
-                        self.program_counter += 2;
-                    }
-                    0x29 => {
-                        let x = reg_v.lock().unwrap()[opcode.x];
-                        reg_v.lock().unwrap().reg_i = x as u16 * 0x5;
-                        self.program_counter += 2;
-                    }
-                    0x33 => {
-                        let x = reg_v.lock().unwrap()[opcode.x];
-                        self.memory
-                            .store8(reg_v.lock().unwrap().reg_i as usize, x / 100);
-                        self.memory
-                            .store8((reg_v.lock().unwrap().reg_i + 1) as usize, (x / 10) % 10);
-                        self.memory
-                            .store8((reg_v.lock().unwrap().reg_i + 2) as usize, x % 100 % 10);
-                        self.program_counter += 2;
-                    }
-                    0x55 => {
-                        for x in 0..(opcode.x + 1) {
-                            let reg_i = reg_v.lock().unwrap().reg_i;
-                            self.memory
-                                .store8(reg_i as usize + x, reg_v.lock().unwrap()[x]);
-                        }
+        let mut chip = Chip8::new();
+        chip.set_layout(::layout::resolve(Some("eti660"), Some(0x100)).unwrap());
+        chip.initialize();
+        chip.load_rom(rom).unwrap();
+        assert_eq!(chip.program_counter, 0x0600);
+        assert_eq!(chip.memory.load8(0x100), Font::default().small[0]);
+        chip.cycle();
+        chip.cycle();
+        assert_eq!(chip.reg_v.reg_i, 0x10A);
 
-                        self.program_counter += 2;
-                    }
-                    0x65 => {
-                        for x in 0..(opcode.x + 1) {
-                            let reg_i = reg_v.lock().unwrap().reg_i;
-                            reg_v.lock().unwrap()[x] = self.memory.load8(reg_i as usize + x);
-                        }
+        let mut chip = Chip8::new();
+        chip.set_layout(Layout::vip());
+        chip.initialize();
+        assert!(chip.load_rom(vec![0; 0xCA1]).is_err());
+        assert!(chip.load_rom(vec![0; 0xCA0]).is_ok());
+    }
 
-                        self.program_counter += 2;
-                    }
-                    _ => {}
-                }
+    #[test]
+    fn load_rom_applies_database() {
+        let rom = vec![0x60, 0x2A];
+        let hashes = format!("{{\"{}\": 0}}", config::rom_hash(&rom));
+        let programs = format!("[{{\"title\": \"Test\", \"roms\": {{\"{}\": \
+                                {{\"platforms\": [\"originalChip8\"], \"tickrate\": 20, \
+                                \"startAddress\": 1536}}}}}}]",
+                               config::rom_hash(&rom));
+        let database = Arc::new(Database::from_json(&hashes, &programs).unwrap());
+
+        let mut chip = Chip8::new();
+        chip.set_database(Some(database.clone()));
+        chip.initialize();
+        chip.load_rom(rom.clone()).unwrap();
+        assert_eq!(chip.rom_info().unwrap().title, "Test");
+        assert_eq!(chip.quirks, Quirks::vip());
+        assert_eq!(chip.speed(), 20);
+        chip.cycle();
+        assert_eq!(chip.reg_v[0], 0x2A);
+        assert_eq!(chip.program_counter, 0x0602);
+
+        let mut chip = Chip8::new();
+        chip.set_database(Some(database));
+        chip.set_start_address(0x200);
+        chip.initialize();
+        chip.load_rom(rom.clone()).unwrap();
+        assert_eq!(chip.program_counter, 0x0200);
+
+        let mut chip = Chip8::new();
+        chip.set_database(None);
+        chip.initialize();
+        chip.load_rom(rom).unwrap();
+        assert!(chip.rom_info().is_none());
+        assert_eq!(chip.speed(), CYCLES_PER_FRAME);
+    }
+
+    #[test]
+    fn seed_repeats_random_numbers() {
+        let rom = vec![0xC0, 0xFF, 0xC1, 0xFF, 0xC2, 0xFF];
+        let run = |seed| {
+            let mut chip = Chip8::new();
+            chip.set_seed(seed);
+            chip.initialize();
+            chip.load_rom(rom.clone()).unwrap();
+            for _ in 0..3 {
+                chip.cycle();
             }
-            _ => {}
+            (chip.reg_v[0], chip.reg_v[1], chip.reg_v[2])
+        };
+
+        assert_eq!(run(0), run(0));
+        assert_ne!(run(1), run(2));
+    }
+
+    #[test]
+    fn trace_instructions() {
+        let rom = vec![0x60, 0x2A, 0x12, 0x00];
+        let buffer = ::std::rc::Rc::new(::std::cell::RefCell::new(Vec::new()));
+
+        let mut chip = Chip8::new();
+        chip.initialize();
+        chip.load_rom(rom).unwrap();
+        chip.set_trace(Box::new(SharedBuffer(buffer.clone())));
+        chip.cycle();
+        chip.cycle();
+
+        assert_eq!(String::from_utf8(buffer.borrow().clone()).unwrap(),
+                   "200: LD V0, 0x2A\n202: JP 0x200\n");
+    }
+
+    #[test]
+    fn profile_subroutines() {
+        let rom = vec![0x22, 0x04, 0x12, 0x02, 0x60, 0x01, 0x00, 0xEE];
+
+        let mut chip = Chip8::new();
+        chip.initialize();
+        chip.load_rom(rom).unwrap();
+        chip.profile();
+        for _ in 0..5 {
+            chip.cycle();
         }
+        let profile = chip.take_profile().unwrap();
+
+        let call = timing::vip_cycles(Instruction::Call(0x204), &Register::new()) as u64;
+        let jump = timing::vip_cycles(Instruction::Jp(0x202), &Register::new()) as u64;
+        assert_eq!(profile.samples()[0].1.cycles, 2 * jump);
+        assert_eq!(profile.routines()[1].1.calls, 1);
+        assert!(profile.folded().starts_with(&format!("main {}\n", call + 2 * jump)));
+        assert!(profile.folded().contains("main;sub_204 "));
     }
-}
 
+    #[test]
+    fn coverage_memory() {
+        // Draws the sprite at 0x20A, stores V0 at 0x20B and loops.
+        let rom = vec![0xA2, 0x0A, 0xD0, 0x01, 0xA2, 0x0B, 0xF0, 0x55, 0x12, 0x08, 0xF0, 0x00];
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+        let mut chip = Chip8::new();
+        chip.initialize();
+        chip.load_rom(rom).unwrap();
+        chip.record_coverage();
+        for _ in 0..6 {
+            chip.cycle();
+        }
+        let coverage = chip.take_coverage().unwrap();
+
+        assert_eq!(coverage.executions(0x200), 1);
+        assert_eq!(coverage.executions(0x208), 2);
+        assert_eq!(coverage.executions(0x20A), 0);
+        assert_eq!(coverage.loads(0x20A), 1);
+        assert_eq!(coverage.stores(0x20B), 1);
+        assert_eq!(coverage.stores(0x20A), 0);
+    }
 
     #[test]
     fn instruction_clear_display() {
@@ -389,7 +1095,7 @@ mod tests {
 
         let mut chip = Chip8::new();
         chip.initialize();
-        chip.load_rom(rom);
+        chip.load_rom(rom).unwrap();
         chip.cycle();
 
         assert_eq!(chip.program_counter, 0x0202);
@@ -401,12 +1107,12 @@ mod tests {
 
         let mut chip = Chip8::new();
         chip.initialize();
-        chip.load_rom(rom);
+        chip.load_rom(rom).unwrap();
         chip.cycle();
 
         assert_eq!(chip.program_counter, 0x02FC);
-        assert_eq!(chip.stack.lock().unwrap().get_pointer(), 0x0001);
-        assert_eq!(chip.stack.lock().unwrap().current(), 0x200);
+        assert_eq!(chip.stack.get_pointer(), 0x0001);
+        assert_eq!(chip.stack.current(), 0x200);
     }
 
     #[test]
@@ -415,12 +1121,12 @@ mod tests {
 
         let mut chip = Chip8::new();
         chip.initialize();
-        chip.load_rom(rom);
+        chip.load_rom(rom).unwrap();
         chip.cycle();
         chip.cycle();
 
         assert_eq!(chip.program_counter, 0x0202);
-        assert_eq!(chip.stack.lock().unwrap().get_pointer(), 0x0000);
+        assert_eq!(chip.stack.get_pointer(), 0x0000);
     }
 
     #[test]
@@ -429,7 +1135,7 @@ mod tests {
 
         let mut chip = Chip8::new();
         chip.initialize();
-        chip.load_rom(rom);
+        chip.load_rom(rom).unwrap();
         chip.cycle();
 
         assert_eq!(chip.program_counter, 0x02FC);
@@ -441,8 +1147,8 @@ mod tests {
 
         let mut chip = Chip8::new();
         chip.initialize();
-        chip.load_rom(rom);
-        chip.reg_v.as_ref().lock().unwrap()[0] = 0x15;
+        chip.load_rom(rom).unwrap();
+        chip.reg_v[0] = 0x15;
         chip.cycle();
 
         assert_eq!(chip.program_counter, 0x0204);
@@ -454,8 +1160,8 @@ mod tests {
 
         let mut chip = Chip8::new();
         chip.initialize();
-        chip.load_rom(rom);
-        chip.reg_v.as_ref().lock().unwrap()[0] = 0x14;
+        chip.load_rom(rom).unwrap();
+        chip.reg_v[0] = 0x14;
         chip.cycle();
 
         assert_eq!(chip.program_counter, 0x0202);
@@ -467,8 +1173,8 @@ mod tests {
 
         let mut chip = Chip8::new();
         chip.initialize();
-        chip.load_rom(rom);
-        chip.reg_v.as_ref().lock().unwrap()[0] = 0x14;
+        chip.load_rom(rom).unwrap();
+        chip.reg_v[0] = 0x14;
         chip.cycle();
 
         assert_eq!(chip.program_counter, 0x0204);
@@ -480,8 +1186,8 @@ mod tests {
 
         let mut chip = Chip8::new();
         chip.initialize();
-        chip.load_rom(rom);
-        chip.reg_v.as_ref().lock().unwrap()[0] = 0x15;
+        chip.load_rom(rom).unwrap();
+        chip.reg_v[0] = 0x15;
         chip.cycle();
 
         assert_eq!(chip.program_counter, 0x0202);
@@ -493,9 +1199,9 @@ mod tests {
 
         let mut chip = Chip8::new();
         chip.initialize();
-        chip.load_rom(rom);
-        chip.reg_v.as_ref().lock().unwrap()[0] = 0x14;
-        chip.reg_v.as_ref().lock().unwrap()[1] = 0x14;
+        chip.load_rom(rom).unwrap();
+        chip.reg_v[0] = 0x14;
+        chip.reg_v[1] = 0x14;
         chip.cycle();
 
         assert_eq!(chip.program_counter, 0x0204);
@@ -507,9 +1213,9 @@ mod tests {
 
         let mut chip = Chip8::new();
         chip.initialize();
-        chip.load_rom(rom);
-        chip.reg_v.as_ref().lock().unwrap()[0] = 0x14;
-        chip.reg_v.as_ref().lock().unwrap()[1] = 0x15;
+        chip.load_rom(rom).unwrap();
+        chip.reg_v[0] = 0x14;
+        chip.reg_v[1] = 0x15;
         chip.cycle();
 
         assert_eq!(chip.program_counter, 0x0202);
@@ -521,11 +1227,11 @@ mod tests {
 
         let mut chip = Chip8::new();
         chip.initialize();
-        chip.load_rom(rom);
+        chip.load_rom(rom).unwrap();
         chip.cycle();
 
         assert_eq!(chip.program_counter, 0x0202);
-        assert_eq!(chip.reg_v.as_ref().lock().unwrap()[0], 0x15);
+        assert_eq!(chip.reg_v[0], 0x15);
     }
 
     #[test]
@@ -534,12 +1240,12 @@ mod tests {
 
         let mut chip = Chip8::new();
         chip.initialize();
-        chip.load_rom(rom);
-        chip.reg_v.as_ref().lock().unwrap()[0] = 0x15;
+        chip.load_rom(rom).unwrap();
+        chip.reg_v[0] = 0x15;
         chip.cycle();
 
         assert_eq!(chip.program_counter, 0x0202);
-        assert_eq!(chip.reg_v.as_ref().lock().unwrap()[0], 0x25);
+        assert_eq!(chip.reg_v[0], 0x25);
     }
 
     #[test]
@@ -548,12 +1254,12 @@ mod tests {
 
         let mut chip = Chip8::new();
         chip.initialize();
-        chip.load_rom(rom);
-        chip.reg_v.as_ref().lock().unwrap()[1] = 0x15;
+        chip.load_rom(rom).unwrap();
+        chip.reg_v[1] = 0x15;
         chip.cycle();
 
         assert_eq!(chip.program_counter, 0x0202);
-        assert_eq!(chip.reg_v.as_ref().lock().unwrap()[0], 0x15);
+        assert_eq!(chip.reg_v[0], 0x15);
     }
 
     #[test]
@@ -562,13 +1268,13 @@ mod tests {
 
         let mut chip = Chip8::new();
         chip.initialize();
-        chip.load_rom(rom);
-        chip.reg_v.as_ref().lock().unwrap()[0] = 0x0F;
-        chip.reg_v.as_ref().lock().unwrap()[1] = 0xF0;
+        chip.load_rom(rom).unwrap();
+        chip.reg_v[0] = 0x0F;
+        chip.reg_v[1] = 0xF0;
         chip.cycle();
 
         assert_eq!(chip.program_counter, 0x0202);
-        assert_eq!(chip.reg_v.as_ref().lock().unwrap()[0], 0xFF);
+        assert_eq!(chip.reg_v[0], 0xFF);
     }
 
     #[test]
@@ -577,13 +1283,13 @@ mod tests {
 
         let mut chip = Chip8::new();
         chip.initialize();
-        chip.load_rom(rom);
-        chip.reg_v.as_ref().lock().unwrap()[0] = 0x0F;
-        chip.reg_v.as_ref().lock().unwrap()[1] = 0xF0;
+        chip.load_rom(rom).unwrap();
+        chip.reg_v[0] = 0x0F;
+        chip.reg_v[1] = 0xF0;
         chip.cycle();
 
         assert_eq!(chip.program_counter, 0x0202);
-        assert_eq!(chip.reg_v.as_ref().lock().unwrap()[0], 0x00);
+        assert_eq!(chip.reg_v[0], 0x00);
     }
 
     #[test]
@@ -592,364 +1298,549 @@ mod tests {
 
         let mut chip = Chip8::new();
         chip.initialize();
-        chip.load_rom(rom);
-        chip.reg_v.as_ref().lock().unwrap()[0] = 0x15;
-        chip.reg_v.as_ref().lock().unwrap()[1] = 0x35;
+        chip.load_rom(rom).unwrap();
+        chip.reg_v[0] = 0x15;
+        chip.reg_v[1] = 0x35;
         chip.cycle();
 
         assert_eq!(chip.program_counter, 0x0202);
-        assert_eq!(chip.reg_v.as_ref().lock().unwrap()[0], 0x20);
+        assert_eq!(chip.reg_v[0], 0x20);
     }
 
     #[test]
-    fn instruction_add_carry() {
-        let rom = vec![0x80, 0x14];
+    fn instruction_jump_not_equal_regs() {
+        let rom = vec![0x90, 0x10];
 
         let mut chip = Chip8::new();
         chip.initialize();
-        chip.load_rom(rom);
-        chip.reg_v.as_ref().lock().unwrap()[0] = 0xA5;
-        chip.reg_v.as_ref().lock().unwrap()[1] = 0xA5;
+        chip.load_rom(rom).unwrap();
+        chip.reg_v[0] = 0x14;
+        chip.reg_v[1] = 0x15;
         chip.cycle();
 
-        assert_eq!(chip.program_counter, 0x0202);
-        assert_eq!(chip.reg_v.as_ref().lock().unwrap()[0], 0x4A);
-        assert_eq!(chip.reg_v.as_ref().lock().unwrap()[0xF], 0x01);
+        assert_eq!(chip.program_counter, 0x0204);
     }
 
     #[test]
-    fn instruction_add_no_carry() {
-        let rom = vec![0x80, 0x14];
+    fn instruction_not_jump_not_equal_regs() {
+        let rom = vec![0x90, 0x10];
 
         let mut chip = Chip8::new();
         chip.initialize();
-        chip.load_rom(rom);
-        chip.reg_v.as_ref().lock().unwrap()[0] = 0x15;
-        chip.reg_v.as_ref().lock().unwrap()[1] = 0x10;
+        chip.load_rom(rom).unwrap();
+        chip.reg_v[0] = 0x14;
+        chip.reg_v[1] = 0x14;
         chip.cycle();
 
         assert_eq!(chip.program_counter, 0x0202);
-        assert_eq!(chip.reg_v.as_ref().lock().unwrap()[0], 0x25);
-        assert_eq!(chip.reg_v.as_ref().lock().unwrap()[0xF], 0x0);
     }
 
     #[test]
-    fn instruction_sub_no_carry() {
-        let rom = vec![0x80, 0x15];
+    fn quirk_shift_vy() {
+        let rom = vec![0x80, 0x16, 0x82, 0x3E];
 
         let mut chip = Chip8::new();
+        chip.set_quirks(Quirks::vip());
         chip.initialize();
-        chip.load_rom(rom);
-        chip.reg_v.as_ref().lock().unwrap()[0] = 0x10;
-        chip.reg_v.as_ref().lock().unwrap()[1] = 0x15;
+        chip.load_rom(rom).unwrap();
+        chip.reg_v[1] = 0x05;
+        chip.reg_v[3] = 0x81;
+        chip.cycle();
+        assert_eq!(chip.reg_v[0], 0x02);
+        assert_eq!(chip.reg_v[0xF], 0x01);
         chip.cycle();
+        assert_eq!(chip.reg_v[2], 0x02);
+        assert_eq!(chip.reg_v[0xF], 0x01);
+    }
 
-        assert_eq!(chip.program_counter, 0x0202);
-        assert_eq!(chip.reg_v.as_ref().lock().unwrap()[0], 0xFB);
-        assert_eq!(chip.reg_v.as_ref().lock().unwrap()[0xF], 0x00);
+    #[test]
+    fn quirk_logic_resets_vf() {
+        let rom = vec![0x80, 0x11, 0x80, 0x12, 0x80, 0x13];
+
+        let mut chip = Chip8::new();
+        chip.set_quirks(Quirks::vip());
+        chip.initialize();
+        chip.load_rom(rom).unwrap();
+        for _ in 0..3 {
+            chip.reg_v[0xF] = 0x01;
+            chip.cycle();
+            assert_eq!(chip.reg_v[0xF], 0x00);
+        }
     }
 
     #[test]
-    fn instruction_sub_carry() {
-        let rom = vec![0x80, 0x15];
+    fn quirk_jump_vx() {
+        let rom = vec![0xB3, 0x00];
 
         let mut chip = Chip8::new();
+        chip.set_quirks(Quirks::schip());
         chip.initialize();
-        chip.load_rom(rom);
-        chip.reg_v.as_ref().lock().unwrap()[0] = 0x15;
-        chip.reg_v.as_ref().lock().unwrap()[1] = 0x10;
+        chip.load_rom(rom).unwrap();
+        chip.reg_v[0] = 0x01;
+        chip.reg_v[3] = 0x10;
         chip.cycle();
 
-        assert_eq!(chip.program_counter, 0x0202);
-        assert_eq!(chip.reg_v.as_ref().lock().unwrap()[0], 0x05);
-        assert_eq!(chip.reg_v.as_ref().lock().unwrap()[0xF], 0x1);
+        assert_eq!(chip.program_counter, 0x0310);
+    }
+
+    #[test]
+    fn quirk_load_store_advances_i() {
+        let rom = vec![0xF2, 0x55, 0xF1, 0x65];
+
+        let mut chip = Chip8::new();
+        chip.set_quirks(Quirks::vip());
+        chip.initialize();
+        chip.load_rom(rom).unwrap();
+        chip.reg_v.reg_i = 0x500;
+        chip.cycle();
+        assert_eq!(chip.reg_v.reg_i, 0x503);
+        chip.cycle();
+        assert_eq!(chip.reg_v.reg_i, 0x505);
     }
 
     #[test]
-    fn instruction_rshift_carry() {
-        let rom = vec![0x80, 0x06];
+    fn instruction_set_i() {
+        let rom = vec![0xA1, 0x23];
 
         let mut chip = Chip8::new();
         chip.initialize();
-        chip.load_rom(rom);
-        chip.reg_v.as_ref().lock().unwrap()[0] = 0x01;
+        chip.load_rom(rom).unwrap();
         chip.cycle();
 
         assert_eq!(chip.program_counter, 0x0202);
-        assert_eq!(chip.reg_v.as_ref().lock().unwrap()[0], 0x00);
-        assert_eq!(chip.reg_v.as_ref().lock().unwrap()[0xF], 0x01);
+        assert_eq!(chip.reg_v.reg_i, 0x0123);
     }
 
     #[test]
-    fn instruction_rshift_no_carry() {
-        let rom = vec![0x80, 0x06];
+    fn instruction_jump_reg() {
+        let rom = vec![0xB1, 0x23];
 
         let mut chip = Chip8::new();
         chip.initialize();
-        chip.load_rom(rom);
-        chip.reg_v.as_ref().lock().unwrap()[0] = 0x02;
+        chip.load_rom(rom).unwrap();
+        chip.reg_v[0] = 0x10;
         chip.cycle();
 
-        assert_eq!(chip.program_counter, 0x0202);
-        assert_eq!(chip.reg_v.as_ref().lock().unwrap()[0], 0x01);
-        assert_eq!(chip.reg_v.as_ref().lock().unwrap()[0xF], 0x0);
+        assert_eq!(chip.program_counter, 0x0133);
     }
 
     #[test]
-    fn instruction_sub_regs_no_carry() {
-        let rom = vec![0x80, 0x17];
+    fn instruction_key_equal() {
+        let rom = vec![0xE0, 0x9E];
 
         let mut chip = Chip8::new();
         chip.initialize();
-        chip.load_rom(rom);
-        chip.reg_v.as_ref().lock().unwrap()[0] = 0x15;
-        chip.reg_v.as_ref().lock().unwrap()[1] = 0x10;
+        chip.load_rom(rom).unwrap();
+        chip.reg_v[0] = 0x3;
+        chip.keys[3] = 0x1;
         chip.cycle();
 
-        assert_eq!(chip.program_counter, 0x0202);
-        assert_eq!(chip.reg_v.as_ref().lock().unwrap()[0], 0xFB);
-        assert_eq!(chip.reg_v.as_ref().lock().unwrap()[0xF], 0x00);
+        assert_eq!(chip.program_counter, 0x0204);
     }
 
     #[test]
-    fn instruction_sub_regs_carry() {
-        let rom = vec![0x80, 0x17];
+    fn instruction_not_key_equal() {
+        let rom = vec![0xE0, 0x9E];
 
         let mut chip = Chip8::new();
         chip.initialize();
-        chip.load_rom(rom);
-        chip.reg_v.as_ref().lock().unwrap()[0] = 0x10;
-        chip.reg_v.as_ref().lock().unwrap()[1] = 0x15;
+        chip.load_rom(rom).unwrap();
+        chip.reg_v[0] = 0x3;
         chip.cycle();
 
         assert_eq!(chip.program_counter, 0x0202);
-        assert_eq!(chip.reg_v.as_ref().lock().unwrap()[0], 0x05);
-        assert_eq!(chip.reg_v.as_ref().lock().unwrap()[0xF], 0x01);
     }
 
     #[test]
-    fn instruction_lshift_carry() {
-        let rom = vec![0x80, 0x0E];
+    fn instruction_key_not_equal() {
+        let rom = vec![0xE0, 0xA1];
 
         let mut chip = Chip8::new();
         chip.initialize();
-        chip.load_rom(rom);
-        chip.reg_v.as_ref().lock().unwrap()[0] = 0x80;
+        chip.load_rom(rom).unwrap();
+        chip.reg_v[0] = 0x3;
         chip.cycle();
 
-        assert_eq!(chip.program_counter, 0x0202);
-        assert_eq!(chip.reg_v.as_ref().lock().unwrap()[0], 0x00);
-        assert_eq!(chip.reg_v.as_ref().lock().unwrap()[0xF], 0x01);
+        assert_eq!(chip.program_counter, 0x0204);
     }
 
     #[test]
-    fn instruction_lshift_no_carry() {
-        let rom = vec![0x80, 0x0E];
+    fn instruction_not_key_not_equal() {
+        let rom = vec![0xE0, 0xA1];
 
         let mut chip = Chip8::new();
         chip.initialize();
-        chip.load_rom(rom);
-        chip.reg_v.as_ref().lock().unwrap()[0] = 0x01;
+        chip.load_rom(rom).unwrap();
+        chip.reg_v[0] = 0x3;
+        chip.keys[3] = 0x1;
         chip.cycle();
 
         assert_eq!(chip.program_counter, 0x0202);
-        assert_eq!(chip.reg_v.as_ref().lock().unwrap()[0], 0x02);
-        assert_eq!(chip.reg_v.as_ref().lock().unwrap()[0xF], 0x0);
     }
 
     #[test]
-    fn instruction_jump_not_equal_regs() {
-        let rom = vec![0x90, 0x10];
+    fn instruction_get_timer() {
+        let rom = vec![0xF0, 0x07];
 
         let mut chip = Chip8::new();
         chip.initialize();
-        chip.load_rom(rom);
-        chip.reg_v.as_ref().lock().unwrap()[0] = 0x14;
-        chip.reg_v.as_ref().lock().unwrap()[1] = 0x15;
+        chip.load_rom(rom).unwrap();
+        chip.delay_timer = 0x12;
         chip.cycle();
 
-        assert_eq!(chip.program_counter, 0x0204);
+        assert_eq!(chip.reg_v[0], 0x12);
+        assert_eq!(chip.program_counter, 0x0202);
     }
 
     #[test]
-    fn instruction_not_jump_not_equal_regs() {
-        let rom = vec![0x90, 0x10];
+    fn instruction_key_pressed() {
+        let rom = vec![0xF0, 0x0A];
 
         let mut chip = Chip8::new();
         chip.initialize();
-        chip.load_rom(rom);
-        chip.reg_v.as_ref().lock().unwrap()[0] = 0x14;
-        chip.reg_v.as_ref().lock().unwrap()[1] = 0x14;
+        chip.load_rom(rom).unwrap();
+
+        chip.cycle();
+        assert_eq!(chip.program_counter, 0x0200);
+
+        chip.keys[5] = 0x1;
         chip.cycle();
 
+        assert_eq!(chip.reg_v[0], 0x05);
         assert_eq!(chip.program_counter, 0x0202);
     }
 
     #[test]
-    fn instruction_set_i() {
-        let rom = vec![0xA1, 0x23];
+    fn instruction_key_pressed_ignores_held_key() {
+        let rom = vec![0xF0, 0x0A];
 
         let mut chip = Chip8::new();
         chip.initialize();
-        chip.load_rom(rom);
+        chip.load_rom(rom).unwrap();
+        chip.keys[5] = 0x1;
+
+        chip.cycle();
+        chip.cycle();
+        assert_eq!(chip.program_counter, 0x0200);
+
+        chip.keys[5] = 0x0;
+        chip.cycle();
+        chip.keys[5] = 0x1;
         chip.cycle();
 
+        assert_eq!(chip.reg_v[0], 0x05);
+        assert_eq!(chip.state(), State::Running);
         assert_eq!(chip.program_counter, 0x0202);
-        assert_eq!(chip.reg_v.as_ref().lock().unwrap().reg_i, 0x0123);
     }
 
     #[test]
-    fn instruction_jump_reg() {
-        let rom = vec![0xB1, 0x23];
+    fn instruction_key_released() {
+        let rom = vec![0xF3, 0x0A];
 
         let mut chip = Chip8::new();
         chip.initialize();
-        chip.load_rom(rom);
-        chip.reg_v.as_ref().lock().unwrap()[0] = 0x10;
+        chip.set_quirks(Quirks::vip());
+        chip.load_rom(rom).unwrap();
+
+        chip.cycle();
+        chip.keys[0xA] = 0x1;
         chip.cycle();
+        assert_eq!(chip.state(),
+                   State::WaitingForKey {
+                       register: 3,
+                       pressed: Some(0xA),
+                   });
+        assert_eq!(chip.program_counter, 0x0200);
 
-        assert_eq!(chip.program_counter, 0x0133);
+        chip.keys[0xA] = 0x0;
+        chip.cycle();
+
+        assert_eq!(chip.reg_v[3], 0x0A);
+        assert_eq!(chip.state(), State::Running);
+        assert_eq!(chip.program_counter, 0x0202);
     }
 
     #[test]
-    fn instruction_key_equal() {
-        let rom = vec![0xE0, 0x9E];
+    fn instruction_key_wait_runs_timers() {
+        let rom = vec![0xF0, 0x0A];
 
         let mut chip = Chip8::new();
         chip.initialize();
-        chip.load_rom(rom);
-        chip.reg_v.as_ref().lock().unwrap()[0] = 0x3;
-        chip.keys.as_ref().lock().unwrap()[3] = 0x1;
-        chip.cycle();
+        chip.load_rom(rom).unwrap();
+        chip.delay_timer = 0x10;
+        chip.sound_timer = 0x10;
 
-        assert_eq!(chip.program_counter, 0x0204);
+        chip.step_frame();
+
+        assert_eq!(chip.program_counter, 0x0200);
+        assert!(chip.delay_timer < 0x10);
+        assert!(chip.sound_timer < 0x10);
     }
 
     #[test]
-    fn instruction_not_key_equal() {
-        let rom = vec![0xE0, 0x9E];
+    fn instruction_set_timer() {
+        let rom = vec![0xF0, 0x15];
 
         let mut chip = Chip8::new();
         chip.initialize();
-        chip.load_rom(rom);
-        chip.reg_v.as_ref().lock().unwrap()[0] = 0x3;
+        chip.load_rom(rom).unwrap();
+        chip.reg_v[0] = 0x12;
         chip.cycle();
 
+        assert_eq!(chip.delay_timer, 0x11);
         assert_eq!(chip.program_counter, 0x0202);
     }
 
     #[test]
-    fn instruction_key_not_equal() {
-        let rom = vec![0xE0, 0xA1];
+    fn instruction_set_sound() {
+        let rom = vec![0xF0, 0x18];
 
         let mut chip = Chip8::new();
         chip.initialize();
-        chip.load_rom(rom);
-        chip.reg_v.as_ref().lock().unwrap()[0] = 0x3;
+        chip.load_rom(rom).unwrap();
+        chip.reg_v[0] = 0x12;
         chip.cycle();
 
-        assert_eq!(chip.program_counter, 0x0204);
+        assert_eq!(chip.sound_timer, 0x11);
+        assert_eq!(chip.program_counter, 0x0202);
+    }
+
+    // Returns VX and VF after 8XYN for N = 4, 5, 6, 7 or E.
+    fn arithmetic(n: u8, vx: u8, vy: u8, shift: bool) -> (u8, u8) {
+        let source = if shift { vx } else { vy };
+        match n {
+            0x4 => (vx.wrapping_add(vy), (vx as u16 + vy as u16 > 0xFF) as u8),
+            0x5 => (vx.wrapping_sub(vy), (vx >= vy) as u8),
+            0x6 => (source >> 1, source & 0x01),
+            0x7 => (vy.wrapping_sub(vx), (vy >= vx) as u8),
+            _ => (source << 1, source >> 7),
+        }
+    }
+
+    fn preset() -> impl Strategy<Value = &'static str> {
+        prop::sample::select(::quirks::PRESETS.to_vec())
+    }
+
+    // Loads `rom` with the quirks of `preset` and the registers set to `registers`.
+    fn chip_with_registers(preset: &str, rom: Vec<u8>, registers: [u8; 16]) -> Chip8 {
+        let mut chip = Chip8::new();
+        chip.initialize();
+        chip.load_rom(rom).unwrap();
+        chip.set_quirks(Quirks::preset(preset).unwrap());
+        for (index, &value) in registers.iter().enumerate() {
+            chip.reg_v[index] = value;
+        }
+        chip
+    }
+
+    proptest! {
+        #[test]
+        fn instruction_arithmetic_flags(preset in preset(),
+                                        n in prop::sample::select(vec![0x4, 0x5, 0x6, 0x7, 0xE]),
+                                        x in 0..16u8,
+                                        y in 0..16u8,
+                                        registers in any::<[u8; 16]>()) {
+            let mut chip = chip_with_registers(preset, vec![0x80 | x, y << 4 | n], registers);
+            chip.cycle();
+
+            let shift = Quirks::preset(preset).unwrap().shift;
+            let (result, flag) =
+                arithmetic(n, registers[x as usize], registers[y as usize], shift);
+            let mut expected = registers;
+            expected[x as usize] = result;
+            // VF is written after the result, so with X = F only the flag remains.
+            expected[0xF] = flag;
+            for (index, &value) in expected.iter().enumerate() {
+                prop_assert_eq!(chip.reg_v[index], value, "V{:X}", index);
+            }
+            prop_assert_eq!(chip.program_counter, 0x0202);
+        }
+
+        #[test]
+        fn instruction_add_i_flag(preset in preset(),
+                                  x in 0..16u8,
+                                  i in 0..0x1000u16,
+                                  registers in any::<[u8; 16]>()) {
+            let mut chip = chip_with_registers(preset, vec![0xF0 | x, 0x1E], registers);
+            chip.reg_v.reg_i = i;
+            chip.cycle();
+
+            // VX is read before VF is overwritten with the overflow past 0xFFF, which only the
+            // `modern` preset reports.
+            let sum = i + registers[x as usize] as u16;
+            let flag = match preset {
+                "modern" => (sum > 0xFFF) as u8,
+                _ => registers[0xF],
+            };
+            prop_assert_eq!(chip.reg_v.reg_i, sum);
+            prop_assert_eq!(chip.reg_v[0xF], flag);
+            for (index, &value) in registers[..0xF].iter().enumerate() {
+                prop_assert_eq!(chip.reg_v[index], value, "V{:X}", index);
+            }
+        }
     }
 
     #[test]
-    fn instruction_not_key_not_equal() {
-        let rom = vec![0xE0, 0xA1];
+    fn instruction_add_i() {
+        let rom = vec![0xF0, 0x1E];
 
         let mut chip = Chip8::new();
         chip.initialize();
-        chip.load_rom(rom);
-        chip.reg_v.as_ref().lock().unwrap()[0] = 0x3;
-        chip.keys.as_ref().lock().unwrap()[3] = 0x1;
+        chip.load_rom(rom).unwrap();
+        chip.reg_v[0] = 0x12;
         chip.cycle();
 
+        assert_eq!(chip.reg_v.reg_i, 0x0012);
         assert_eq!(chip.program_counter, 0x0202);
     }
 
     #[test]
-    fn instruction_get_timer() {
-        let rom = vec![0xF0, 0x07];
+    fn instruction_sprite_addr() {
+        let rom = vec![0xF0, 0x29];
 
         let mut chip = Chip8::new();
         chip.initialize();
-        chip.load_rom(rom);
-        chip.delay_timer = 0x12;
+        chip.load_rom(rom).unwrap();
+        chip.reg_v[0] = 0x1;
         chip.cycle();
 
-        assert_eq!(chip.reg_v.as_ref().lock().unwrap()[0], 0x12);
+        assert_eq!(chip.reg_v.reg_i, 0x0005);
         assert_eq!(chip.program_counter, 0x0202);
     }
 
     #[test]
-    fn instruction_key_pressed() {
-        let rom = vec![0xF0, 0x0A];
+    fn quirk_display_wait() {
+        let rom = vec![0x60, 0x01, 0xD0, 0x05, 0xD0, 0x05];
 
         let mut chip = Chip8::new();
+        chip.set_quirks(Quirks::vip());
         chip.initialize();
-        chip.load_rom(rom);
+        chip.load_rom(rom.clone()).unwrap();
+        chip.step_frame();
+        assert_eq!(chip.state(), State::WaitingForVblank);
+        assert_eq!(chip.program_counter, 0x0202);
 
-        chip.cycle();
-        assert_eq!(chip.program_counter, 0x0200);
+        // The draw is the first instruction after the vertical blank, the next one waits.
+        chip.step_frame();
+        assert_eq!(chip.state(), State::WaitingForVblank);
+        assert_eq!(chip.program_counter, 0x0204);
+        assert_eq!(chip.reg_gfx[DISPLAY_WIDTH + 1], 0x01);
 
-        chip.keys.as_ref().lock().unwrap()[5] = 0x1;
-        chip.cycle();
+        let mut chip = Chip8::new();
+        chip.initialize();
+        chip.load_rom(rom).unwrap();
+        chip.step_frame();
+        assert_eq!(chip.state(), State::Running);
+        assert_eq!(chip.reg_gfx[DISPLAY_WIDTH + 1], 0x00);
+    }
 
-        assert_eq!(chip.reg_v.as_ref().lock().unwrap()[0], 0x05);
-        assert_eq!(chip.program_counter, 0x0202);
+    #[test]
+    fn vip_timing_spends_frame_budget() {
+        // 7001 and 1200 take 50 and 52 machine cycles.
+        let rom = vec![0x70, 0x01, 0x12, 0x00];
+
+        let mut chip = Chip8::new();
+        chip.set_timing(Timing::Vip);
+        chip.initialize();
+        chip.load_rom(rom).unwrap();
+        chip.delay_timer = 10;
+        chip.step_frame();
+
+        // 25 loops take 2550 cycles, the next 7001 runs 2 cycles over the budget.
+        assert_eq!(timing::VIP_FRAME_BUDGET, 2598);
+        assert_eq!(chip.reg_v[0], 26);
+        assert_eq!(chip.cycle_budget, -2);
+        assert_eq!(chip.delay_timer, 9);
     }
 
     #[test]
-    fn instruction_set_timer() {
-        let rom = vec![0xF0, 0x15];
+    fn vip_timing_carries_over_long_instructions() {
+        let rom = vec![0x00, 0xE0, 0x70, 0x01, 0x12, 0x02];
 
         let mut chip = Chip8::new();
+        chip.set_timing(Timing::Vip);
         chip.initialize();
-        chip.load_rom(rom);
-        chip.reg_v.as_ref().lock().unwrap()[0] = 0x12;
-        chip.cycle();
+        chip.load_rom(rom).unwrap();
+        chip.step_frame();
 
-        assert_eq!(chip.delay_timer, 0x11);
+        // Clearing the screen takes longer than a frame.
         assert_eq!(chip.program_counter, 0x0202);
+        assert!(chip.cycle_budget < 0);
+        chip.step_frame();
+        assert!(chip.reg_v[0] > 0 && chip.reg_v[0] < 26);
     }
 
     #[test]
-    fn instruction_set_sound() {
-        let rom = vec![0xF0, 0x18];
+    fn vip_timing_charges_skips() {
+        // 3000 skips and takes 4 cycles more than 4000, which does not.
+        let rom = vec![0x30, 0x00, 0x00, 0x00, 0x40, 0x00];
 
         let mut chip = Chip8::new();
+        chip.set_timing(Timing::Vip);
         chip.initialize();
-        chip.load_rom(rom);
-        chip.reg_v.as_ref().lock().unwrap()[0] = 0x12;
-        chip.cycle();
+        chip.load_rom(rom).unwrap();
 
-        assert_eq!(chip.sound_timer, 0x11);
-        assert_eq!(chip.program_counter, 0x0202);
+        assert_eq!(chip.cycle(), 54);
+        assert_eq!(chip.program_counter, 0x0204);
+        assert_eq!(chip.cycle(), 50);
+        assert_eq!(chip.program_counter, 0x0206);
     }
 
     #[test]
-    fn instruction_add_i() {
-        let rom = vec![0xF0, 0x1E];
+    fn vip_timing_waits_for_key() {
+        let rom = vec![0xF0, 0x0A];
 
         let mut chip = Chip8::new();
+        chip.set_timing(Timing::Vip);
         chip.initialize();
-        chip.load_rom(rom);
-        chip.reg_v.as_ref().lock().unwrap()[0] = 0x12;
-        chip.cycle();
+        chip.load_rom(rom).unwrap();
+        chip.step_frame();
+        chip.step_frame();
 
-        assert_eq!(chip.reg_v.as_ref().lock().unwrap().reg_i, 0x0012);
-        assert_eq!(chip.program_counter, 0x0202);
+        assert_eq!(chip.program_counter, 0x0200);
+        assert_eq!(chip.cycle_budget, 0);
+        assert_eq!(chip.instructions(), 1);
     }
 
     #[test]
-    fn instruction_sprite_addr() {
-        let rom = vec![0xF0, 0x29];
+    fn snapshot_font_digits() {
+        // Draws the digits 0-F in two rows of eight.
+        let mut rom = Vec::new();
+        for digit in 0..16u8 {
+            rom.extend_from_slice(&[0x60, digit, 0xF0, 0x29, 0x61, digit % 8 * 8, 0x62,
+                                    digit / 8 * 6, 0xD1, 0x25]);
+        }
 
         let mut chip = Chip8::new();
         chip.initialize();
-        chip.load_rom(rom);
-        chip.reg_v.as_ref().lock().unwrap()[0] = 0x1;
+        chip.load_rom(rom).unwrap();
+        for _ in 0..16 * 5 {
+            chip.cycle();
+        }
+        snapshot::assert_snapshot(concat!(env!("CARGO_MANIFEST_DIR"),
+                                          "/tests/snapshots/font_digits.txt"),
+                                  chip.gfx());
+        snapshot::assert_png_snapshot(concat!(env!("CARGO_MANIFEST_DIR"),
+                                              "/tests/snapshots/font_digits.png"),
+                                      chip.gfx(),
+                                      1);
+    }
+
+    #[test]
+    fn instruction_large_sprite_addr() {
+        let rom = vec![0xF0, 0x30];
+        let font = Font::preset("vip").unwrap();
+
+        let mut chip = Chip8::new();
+        chip.set_font(font.clone());
+        chip.initialize();
+        chip.load_rom(rom).unwrap();
+        chip.reg_v[0] = 0x2;
         chip.cycle();
 
-        assert_eq!(chip.reg_v.as_ref().lock().unwrap().reg_i, 0x0005);
+        assert_eq!(chip.reg_v.reg_i, 0x0050 + 20);
+        assert_eq!(chip.memory.load8(0x0004 * 5), font.small[20]);
+        assert_eq!(chip.memory.load8(0x0050 + 20), font.large[20]);
         assert_eq!(chip.program_counter, 0x0202);
     }
 
@@ -959,9 +1850,9 @@ mod tests {
 
         let mut chip = Chip8::new();
         chip.initialize();
-        chip.load_rom(rom);
-        chip.reg_v.as_ref().lock().unwrap()[0] = 0xF3;
-        chip.reg_v.as_ref().lock().unwrap().reg_i = 0x0500;
+        chip.load_rom(rom).unwrap();
+        chip.reg_v[0] = 0xF3;
+        chip.reg_v.reg_i = 0x0500;
         chip.cycle();
 
         assert_eq!(chip.memory.load8(0x0500), 2);
@@ -976,11 +1867,11 @@ mod tests {
 
         let mut chip = Chip8::new();
         chip.initialize();
-        chip.load_rom(rom);
-        chip.reg_v.as_ref().lock().unwrap()[0] = 0x12;
-        chip.reg_v.as_ref().lock().unwrap()[1] = 0x34;
-        chip.reg_v.as_ref().lock().unwrap()[2] = 0x56;
-        chip.reg_v.as_ref().lock().unwrap().reg_i = 0x500;
+        chip.load_rom(rom).unwrap();
+        chip.reg_v[0] = 0x12;
+        chip.reg_v[1] = 0x34;
+        chip.reg_v[2] = 0x56;
+        chip.reg_v.reg_i = 0x500;
         chip.cycle();
 
         assert_eq!(chip.memory.load8(0x0500), 0x12);
@@ -995,47 +1886,147 @@ mod tests {
 
         let mut chip = Chip8::new();
         chip.initialize();
-        chip.load_rom(rom);
+        chip.load_rom(rom).unwrap();
         chip.memory.store8(0x0500, 0x12);
         chip.memory.store8(0x0501, 0x34);
         chip.memory.store8(0x0502, 0x56);
-        chip.reg_v.as_ref().lock().unwrap().reg_i = 0x500;
+        chip.reg_v.reg_i = 0x500;
         chip.cycle();
 
-        assert_eq!(chip.reg_v.as_ref().lock().unwrap()[0], 0x12);
-        assert_eq!(chip.reg_v.as_ref().lock().unwrap()[1], 0x34);
-        assert_eq!(chip.reg_v.as_ref().lock().unwrap()[2], 0x56);
+        assert_eq!(chip.reg_v[0], 0x12);
+        assert_eq!(chip.reg_v[1], 0x34);
+        assert_eq!(chip.reg_v[2], 0x56);
         assert_eq!(chip.program_counter, 0x0202);
     }
 
+    #[test]
+    fn decode_cache_invalidated_on_store() {
+        let rom = vec![0xA2, 0x0C, 0x22, 0x0C, 0x60, 0x63, 0x61, 0x05, 0xF1, 0x55, 0x22, 0x0C,
+                       0x62, 0x01, 0x00, 0xEE];
+
+        let mut chip = Chip8::new();
+        chip.initialize();
+        chip.load_rom(rom).unwrap();
+        for _ in 0..9 {
+            chip.cycle();
+        }
+
+        assert_eq!(chip.reg_v[2], 0x01);
+        assert_eq!(chip.reg_v[3], 0x05);
+        assert_eq!(chip.program_counter, 0x020E);
+    }
+
+    #[test]
+    fn wraps_around_memory() {
+        // Stores 6A at 0xFFF and 42 at address 0, then jumps to 0xFFF.
+        let rom = vec![0xAF, 0xFF, 0x60, 0x6A, 0x61, 0x42, 0xF1, 0x55, 0x1F, 0xFF];
+
+        let mut chip = Chip8::new();
+        chip.initialize();
+        chip.load_rom(rom).unwrap();
+        for _ in 0..6 {
+            chip.cycle();
+        }
+
+        assert_eq!(chip.memory().load16(0xFFF), 0x6A42);
+        assert_eq!(chip.reg_v[0xA], 0x42);
+        assert_eq!(chip.program_counter(), 0x0001);
+
+        // Unknown instructions are ignored.
+        let mut chip = Chip8::new();
+        chip.initialize();
+        chip.load_rom(vec![0xE0, 0x00, 0x00, 0xEE]).unwrap();
+        chip.cycle();
+        assert_eq!(chip.program_counter(), 0x0200);
+    }
+
+    #[test]
+    fn unsupported_instruction_halts() {
+        // 00FF switches SUPER-CHIP to high resolution.
+        let mut chip = Chip8::new();
+        chip.initialize();
+        chip.load_rom(vec![0x60, 0x01, 0x00, 0xFF]).unwrap();
+        chip.cycle();
+        chip.cycle();
+        assert_eq!(chip.state(),
+                   State::Halted {
+                       fault: Fault::Unsupported(Instruction::High),
+                       address: 0x202,
+                   });
+        chip.cycle();
+        assert_eq!(chip.program_counter(), 0x202);
+        assert_eq!(chip.instructions(), 2);
+
+        let mut frontend = headless::Headless::new(Some(10));
+        let err = chip.run(&mut frontend).unwrap_err();
+        assert_eq!(err.to_string(), "Unsupported instruction HIGH at 0x202");
+    }
+
+    #[test]
+    fn stack_overflow_underflow_halt() {
+        // Returning with an empty stack.
+        let mut chip = Chip8::new();
+        chip.initialize();
+        chip.load_rom(vec![0x00, 0xEE]).unwrap();
+        chip.cycle();
+        assert_eq!(chip.state(),
+                   State::Halted {
+                       fault: Fault::Stack(StackError::Underflow),
+                       address: 0x200,
+                   });
+        chip.cycle();
+        assert_eq!(chip.program_counter(), 0x200);
+
+        // A subroutine calling itself overflows the stack on the 17th nested call.
+        let mut chip = Chip8::new();
+        chip.initialize();
+        chip.load_rom(vec![0x22, 0x02, 0x22, 0x02]).unwrap();
+        for _ in 0..16 {
+            chip.cycle();
+        }
+        assert_eq!(chip.state(), State::Running);
+        assert_eq!(chip.stack_depth(), 16);
+        chip.cycle();
+        assert_eq!(chip.state(),
+                   State::Halted {
+                       fault: Fault::Stack(StackError::Overflow),
+                       address: 0x202,
+                   });
+        assert_eq!(chip.stack_depth(), 16);
+
+        let mut frontend = headless::Headless::new(Some(10));
+        let err = chip.run(&mut frontend).unwrap_err();
+        assert_eq!(err.to_string(), "Stack overflow at 0x202");
+    }
+
     #[test]
     fn instruction_display() {
         let rom = vec![0xD0, 0x05];
 
         let mut chip = Chip8::new();
         chip.initialize();
-        chip.load_rom(rom);
-        chip.reg_v.as_ref().lock().unwrap().reg_i = 0x0000;
+        chip.load_rom(rom).unwrap();
+        chip.reg_v.reg_i = 0x0000;
         chip.cycle();
 
         assert_eq!(chip.program_counter, 0x0202);
-        assert_eq!(chip.reg_gfx.as_ref().lock().unwrap()[0], 0xFF);
-        assert_eq!(chip.reg_gfx.as_ref().lock().unwrap()[1], 0xFF);
-        assert_eq!(chip.reg_gfx.as_ref().lock().unwrap()[2], 0xFF);
-        assert_eq!(chip.reg_gfx.as_ref().lock().unwrap()[3], 0xFF);
-        assert_eq!(chip.reg_gfx.as_ref().lock().unwrap()[4], 0x00);
-        assert_eq!(chip.reg_gfx.as_ref().lock().unwrap()[5], 0x00);
-        assert_eq!(chip.reg_gfx.as_ref().lock().unwrap()[6], 0x00);
-        assert_eq!(chip.reg_gfx.as_ref().lock().unwrap()[7], 0x00);
-
-        assert_eq!(chip.reg_gfx.as_ref().lock().unwrap()[64 + 0], 0xFF);
-        assert_eq!(chip.reg_gfx.as_ref().lock().unwrap()[64 + 1], 0x00);
-        assert_eq!(chip.reg_gfx.as_ref().lock().unwrap()[64 + 2], 0x00);
-        assert_eq!(chip.reg_gfx.as_ref().lock().unwrap()[64 + 3], 0xFF);
-        assert_eq!(chip.reg_gfx.as_ref().lock().unwrap()[64 + 4], 0x00);
-        assert_eq!(chip.reg_gfx.as_ref().lock().unwrap()[64 + 5], 0x00);
-        assert_eq!(chip.reg_gfx.as_ref().lock().unwrap()[64 + 6], 0x00);
-        assert_eq!(chip.reg_gfx.as_ref().lock().unwrap()[64 + 7], 0x00);
+        assert_eq!(chip.reg_gfx[0], 0x01);
+        assert_eq!(chip.reg_gfx[1], 0x01);
+        assert_eq!(chip.reg_gfx[2], 0x01);
+        assert_eq!(chip.reg_gfx[3], 0x01);
+        assert_eq!(chip.reg_gfx[4], 0x00);
+        assert_eq!(chip.reg_gfx[5], 0x00);
+        assert_eq!(chip.reg_gfx[6], 0x00);
+        assert_eq!(chip.reg_gfx[7], 0x00);
+
+        assert_eq!(chip.reg_gfx[64], 0x01);
+        assert_eq!(chip.reg_gfx[64 + 1], 0x00);
+        assert_eq!(chip.reg_gfx[64 + 2], 0x00);
+        assert_eq!(chip.reg_gfx[64 + 3], 0x01);
+        assert_eq!(chip.reg_gfx[64 + 4], 0x00);
+        assert_eq!(chip.reg_gfx[64 + 5], 0x00);
+        assert_eq!(chip.reg_gfx[64 + 6], 0x00);
+        assert_eq!(chip.reg_gfx[64 + 7], 0x00);
     }
 }
 
@@ -0,0 +1,213 @@
+use std::collections::HashMap;
+
+pub const USAGE: &str = "\
+Usage: chip8 [run] <rom> [options]
+       chip8 disasm <rom> [--start <address>] [--layout <profile>]
+       chip8 asm <source> [-o <file>] [--start <address>] [--layout <profile>]
+       chip8 info <rom> [--database <dir>]
+       chip8 test <rom> [--frames <n>] [options]
+       chip8 bench [<rom>...] [--frames <n>] [options]
+       chip8 profile <rom> [--frames <n>] [--folded <file>] [options]
+
+Commands:
+    run       Run a ROM (default)
+    disasm    Print the disassembly of a ROM
+    asm       Assemble a source file into a ROM
+    info      Print size and instruction statistics of a ROM
+    test      Run a ROM without output and print the final display
+    bench     Run ROMs, or built-in workloads, as fast as possible and report their speed
+    profile   Run a ROM without output and print where its machine cycles go
+
+Options:
+    --scale <n>             Window pixels per CHIP-8 pixel, 1 - 64 (default 20)
+    --palette <name>        classic, amber, green, lcd or RRGGBB,RRGGBB
+    --speed <n>             Instructions per frame (default 10)
+    --timing <model>        instructions (default) or vip machine cycles per frame
+    --quirks <preset>       vip, schip or modern (default)
+    --seed <n>              Seed for random numbers
+    --mute                  Turn off the buzzer
+    --keys <layout>         qwerty, qwertz, azerty, numpad or a key binding file
+    --controller <file>     Game controller mapping file
+    --frontend <name>       sdl (default), terminal or headless
+    --machine <name>        chip8 (default) or vip to run the original interpreter
+    --monitor <file>        COSMAC VIP monitor ROM for --machine vip
+    --interpreter <file>    COSMAC VIP CHIP-8 interpreter for --machine vip
+    --headless              Same as --frontend headless
+    --frames <n>            Stop after n frames
+    --trace <file>          Write every executed instruction to a file
+    --folded <file>         Write the call stacks of profile for flame graphs to a file
+    --coverage <file>       Write the disassembly annotated with the coverage to a file
+    --lcov <file>           Write the coverage as LCOV tracefile to a file
+    --start <address>       Load and start address below 0x1000 (default 0x200)
+    --layout <profile>      Memory layout: vip, eti660 or modern (default)
+    --font-address <addr>   Address of the font in memory (default 0x000)
+    --font <name>           vip, dream6800, eti660, fish, schip (default) or a font file
+    --record <file>         Record key presses
+    --replay <file>         Replay recorded key presses
+    --database <dir>        ROM database with sha1-hashes.json and programs.json
+    --config <file>         Configuration file instead of ~/.config/chip8/config.toml
+    -o, --output <file>     Output file of asm
+    -h, --help              Print this help
+";
+
+pub const COMMANDS: [&str; 7] = ["run", "disasm", "asm", "info", "test", "bench", "profile"];
+
+const VALUE_OPTIONS: [&str; 26] = ["scale", "palette", "speed", "timing", "quirks", "seed",
+                                   "keys", "controller", "frontend", "machine", "monitor",
+                                   "interpreter", "frames", "trace", "folded", "coverage",
+                                   "lcov", "start", "layout", "font-address", "font", "record",
+                                   "replay", "config", "database", "output"];
+const FLAGS: [&str; 3] = ["headless", "mute", "help"];
+
+/// Parsed command line.
+#[derive(Debug, Default)]
+pub struct Args {
+    pub command: String,
+    pub paths: Vec<String>,
+    options: HashMap<String, String>,
+}
+
+impl Args {
+    /// Parses the arguments following the program name. Without a known command the
+    /// arguments are those of `run`.
+    pub fn parse(args: &[String]) -> Result<Args, String> {
+        let mut parsed = Args::default();
+        let mut args = args.iter();
+        let mut command = None;
+
+        while let Some(arg) = args.next() {
+            let name = match arg.as_str() {
+                "-h" => "help",
+                "-o" => "output",
+                _ if arg.starts_with("--") => &arg[2..],
+                _ => {
+                    if command.is_none() && parsed.paths.is_empty() &&
+                       COMMANDS.contains(&arg.as_str()) {
+                        command = Some(arg.clone());
+                    } else {
+                        parsed.paths.push(arg.clone());
+                    }
+                    continue;
+                }
+            };
+
+            if FLAGS.contains(&name) {
+                parsed.options.insert(name.to_string(), String::new());
+            } else if VALUE_OPTIONS.contains(&name) {
+                let value = args.next()
+                                .ok_or_else(|| format!("Missing value for --{}", name))?;
+                parsed.options.insert(name.to_string(), value.clone());
+            } else {
+                return Err(format!("Unknown option: {}", arg));
+            }
+        }
+
+        parsed.command = command.unwrap_or_else(|| "run".to_string());
+        Ok(parsed)
+    }
+
+    pub fn flag(&self, name: &str) -> bool {
+        self.options.contains_key(name)
+    }
+
+    pub fn value(&self, name: &str) -> Option<&str> {
+        self.options.get(name).map(String::as_str)
+    }
+
+    /// Returns the value of a numeric option. Numbers starting with `0x` are hexadecimal.
+    pub fn number(&self, name: &str) -> Result<Option<u64>, String> {
+        match self.value(name) {
+            Some(value) => {
+                parse_number(value)
+                    .map(Some)
+                    .ok_or_else(|| format!("Invalid number for --{}: {}", name, value))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Returns the value of a numeric option, which has to lie within `min` and `max`.
+    pub fn number_within(&self, name: &str, min: u64, max: u64) -> Result<Option<u64>, String> {
+        match self.number(name)? {
+            Some(number) if number < min || number > max => {
+                Err(format!("--{} {} is not within {} and {}",
+                            name,
+                            self.value(name).unwrap_or_default(),
+                            min,
+                            max))
+            }
+            number => Ok(number),
+        }
+    }
+
+    /// Returns the single path argument of the command.
+    pub fn path(&self) -> Result<&str, String> {
+        match self.paths.as_slice() {
+            [path] => Ok(path),
+            [] => Err(format!("Missing file argument for {}", self.command)),
+            _ => Err(format!("Too many arguments for {}", self.command)),
+        }
+    }
+}
+
+fn parse_number(text: &str) -> Option<u64> {
+    if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        u64::from_str_radix(hex, 16).ok()
+    } else {
+        text.parse().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(args: &[&str]) -> Result<Args, String> {
+        let args: Vec<String> = args.iter().map(|arg| arg.to_string()).collect();
+        Args::parse(&args)
+    }
+
+    #[test]
+    fn parse_default_command() {
+        let args = parse(&["pong.ch8", "--headless", "--speed", "20"]).unwrap();
+        assert_eq!(args.command, "run");
+        assert_eq!(args.path(), Ok("pong.ch8"));
+        assert!(args.flag("headless"));
+        assert_eq!(args.number("speed"), Ok(Some(20)));
+        assert_eq!(args.number("seed"), Ok(None));
+    }
+
+    #[test]
+    fn parse_subcommand() {
+        let args = parse(&["asm", "game.s", "-o", "game.ch8", "--start", "0x600"]).unwrap();
+        assert_eq!(args.command, "asm");
+        assert_eq!(args.value("output"), Some("game.ch8"));
+        assert_eq!(args.number("start"), Ok(Some(0x600)));
+
+        let args = parse(&["run", "info"]).unwrap();
+        assert_eq!(args.command, "run");
+        assert_eq!(args.path(), Ok("info"));
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert!(parse(&["--fast"]).is_err());
+        assert!(parse(&["pong.ch8", "--scale"]).is_err());
+        assert!(parse(&["--speed", "fast"]).unwrap().number("speed").is_err());
+        assert!(parse(&["info"]).unwrap().path().is_err());
+        assert!(parse(&["info", "a", "b"]).unwrap().path().is_err());
+    }
+
+    #[test]
+    fn parse_number_within() {
+        let args = parse(&["pong.ch8", "--start", "0xFFF", "--scale", "0"]).unwrap();
+        assert_eq!(args.number_within("start", 0, 0xFFF), Ok(Some(0xFFF)));
+        assert_eq!(args.number_within("scale", 1, 64),
+                   Err("--scale 0 is not within 1 and 64".to_string()));
+        assert_eq!(args.number_within("speed", 1, 64), Ok(None));
+
+        let args = parse(&["pong.ch8", "--start", "0x1000"]).unwrap();
+        assert_eq!(args.number_within("start", 0, 0xFFF),
+                   Err("--start 0x1000 is not within 0 and 4095".to_string()));
+    }
+}
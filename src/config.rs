@@ -0,0 +1,286 @@
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use sha1::{Digest, Sha1};
+use toml;
+
+use error::Chip8Error;
+use font::Font;
+use frontend::Audio;
+use keymap::KeyMap;
+use layout::{self, Layout};
+use palette::Palette;
+use quirks::Quirks;
+use timing::Timing;
+
+/// Audio settings of a configuration file.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AudioSettings {
+    pub frequency: Option<f32>,
+    pub volume: Option<f32>,
+    pub muted: Option<bool>,
+}
+
+/// Emulator settings. Unset values fall back to the next less specific source.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Settings {
+    pub speed: Option<usize>,
+    /// Timing model: `instructions` per frame or `vip` machine cycles.
+    pub timing: Option<String>,
+    pub quirks: Option<String>,
+    /// Memory layout preset.
+    pub layout: Option<String>,
+    pub font_address: Option<usize>,
+    /// Font preset or font file.
+    pub font: Option<String>,
+    pub palette: Option<String>,
+    pub scale: Option<usize>,
+    /// Key layout preset, key binding file or inline key binding table.
+    pub keys: Option<toml::Value>,
+    #[serde(default)]
+    pub audio: AudioSettings,
+}
+
+impl Settings {
+    /// Overrides the values set in `other`.
+    pub fn merge(&mut self, other: &Settings) {
+        fn merge<T: Clone>(value: &mut Option<T>, other: &Option<T>) {
+            if other.is_some() {
+                *value = other.clone();
+            }
+        }
+
+        merge(&mut self.speed, &other.speed);
+        merge(&mut self.timing, &other.timing);
+        merge(&mut self.quirks, &other.quirks);
+        merge(&mut self.layout, &other.layout);
+        merge(&mut self.font_address, &other.font_address);
+        merge(&mut self.font, &other.font);
+        merge(&mut self.palette, &other.palette);
+        merge(&mut self.scale, &other.scale);
+        merge(&mut self.keys, &other.keys);
+        merge(&mut self.audio.frequency, &other.audio.frequency);
+        merge(&mut self.audio.volume, &other.audio.volume);
+        merge(&mut self.audio.muted, &other.audio.muted);
+    }
+
+    pub fn quirks(&self) -> Result<Option<Quirks>, Chip8Error> {
+        match self.quirks {
+            Some(ref name) => {
+                Quirks::preset(name)
+                    .map(Some)
+                    .ok_or_else(|| Chip8Error::Message(format!("Unknown quirks preset: {}", name)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    pub fn timing(&self) -> Result<Option<Timing>, Chip8Error> {
+        match self.timing {
+            Some(ref name) => {
+                Timing::preset(name)
+                    .map(Some)
+                    .ok_or_else(|| Chip8Error::Message(format!("Unknown timing: {}", name)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    pub fn layout(&self) -> Result<Option<Layout>, Chip8Error> {
+        if self.layout.is_none() && self.font_address.is_none() {
+            return Ok(None);
+        }
+        layout::resolve(self.layout.as_deref(), self.font_address).map(Some)
+    }
+
+    pub fn font(&self) -> Result<Option<Font>, Chip8Error> {
+        match self.font {
+            Some(ref font) => Font::resolve(font).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    pub fn palette(&self) -> Result<Option<Palette>, Chip8Error> {
+        match self.palette {
+            Some(ref palette) => Palette::resolve(palette).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    pub fn keymap(&self) -> Result<Option<KeyMap>, Chip8Error> {
+        match self.keys {
+            Some(toml::Value::String(ref layout)) => KeyMap::resolve(layout).map(Some),
+            Some(ref table @ toml::Value::Table(_)) => {
+                let text = toml::to_string(table).map_err(|err| err.to_string())?;
+                KeyMap::from_toml(&text).map(Some)
+            }
+            Some(_) => Err(Chip8Error::Message("Invalid key bindings".to_string())),
+            None => Ok(None),
+        }
+    }
+
+    pub fn audio(&self) -> Audio {
+        let defaults = Audio::default();
+        Audio {
+            frequency: self.audio.frequency.unwrap_or(defaults.frequency),
+            volume: self.audio.volume.unwrap_or(defaults.volume),
+            muted: self.audio.muted.unwrap_or(defaults.muted),
+        }
+    }
+}
+
+/// Configuration file with default settings and per-ROM overrides keyed by the SHA-1 of the
+/// ROM.
+///
+/// ```toml
+/// speed = 10
+/// palette = "amber"
+/// layout = "vip"
+///
+/// [audio]
+/// volume = 0.1
+///
+/// [rom.0123456789abcdef0123456789abcdef01234567]
+/// speed = 30
+/// quirks = "schip"
+/// keys = { preset = "qwerty", keys = { 5 = ["Up"], 8 = ["Down"] } }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Config {
+    pub defaults: Settings,
+    pub rom: HashMap<String, Settings>,
+}
+
+impl Config {
+    /// Parses a configuration file. Unknown keys are errors, so a misspelled setting is not
+    /// silently ignored.
+    pub fn from_toml(text: &str) -> Result<Config, Chip8Error> {
+        let mut table: toml::Table = toml::from_str(text).map_err(|err| err.to_string())?;
+        let rom = match table.remove("rom") {
+            Some(rom) => rom.try_into().map_err(|err| format!("Invalid [rom] section: {}", err))?,
+            None => HashMap::new(),
+        };
+        let defaults = toml::Value::Table(table).try_into().map_err(|err| err.to_string())?;
+        Ok(Config { defaults, rom })
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Config, Chip8Error> {
+        let text = fs::read_to_string(path)?;
+        Config::from_toml(&text)
+    }
+
+    /// Loads the user configuration file, if there is one.
+    pub fn load_user() -> Result<Config, Chip8Error> {
+        match user_path() {
+            Some(ref path) if path.exists() => Config::load(path),
+            _ => Ok(Config::default()),
+        }
+    }
+
+    /// Returns the defaults merged with the section of the ROM with the SHA-1 `hash`.
+    pub fn settings(&self, hash: &str) -> Settings {
+        let mut settings = self.defaults.clone();
+        if let Some(rom) = self.rom.get(&hash.to_lowercase()) {
+            settings.merge(rom);
+        }
+        settings
+    }
+}
+
+/// Returns `$XDG_CONFIG_HOME/chip8/config.toml`, falling back to `~/.config`.
+pub fn user_path() -> Option<PathBuf> {
+    env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+        .map(|dir| dir.join("chip8").join("config.toml"))
+}
+
+/// Returns the SHA-1 of `rom` as lowercase hex string.
+pub fn rom_hash(rom: &[u8]) -> String {
+    Sha1::digest(rom)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = "speed = 10\npalette = \"amber\"\n[audio]\nvolume = 0.1\n\
+                          [rom.da39a3ee5e6b4b0d3255bfef95601890afd80709]\nspeed = 30\n\
+                          quirks = \"vip\"\ntiming = \"vip\"\nlayout = \"eti660\"\n\
+                          font = \"fish\"\n\
+                          keys = { keys = { 5 = [\"Up\"] } }\n";
+
+    #[test]
+    fn config_rom_hash() {
+        assert_eq!(rom_hash(&[]), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+    }
+
+    #[test]
+    fn config_defaults() {
+        let config = Config::from_toml(CONFIG).unwrap();
+        let settings = config.settings(&rom_hash(&[0x00, 0xE0]));
+        assert_eq!(settings.speed, Some(10));
+        assert_eq!(settings.quirks().unwrap(), None);
+        assert_eq!(settings.timing().unwrap(), None);
+        assert_eq!(settings.layout().unwrap(), None);
+        assert_eq!(settings.font().unwrap(), None);
+        assert_eq!(settings.palette().unwrap(), Palette::preset("amber"));
+        assert_eq!(settings.audio().volume, 0.1);
+        assert_eq!(settings.audio().frequency, Audio::default().frequency);
+    }
+
+    #[test]
+    fn config_rom_section() {
+        use keymap::HostKey;
+
+        let config = Config::from_toml(CONFIG).unwrap();
+        let settings = config.settings("DA39A3EE5E6B4B0D3255BFEF95601890AFD80709");
+        assert_eq!(settings.speed, Some(30));
+        assert_eq!(settings.quirks().unwrap(), Some(Quirks::vip()));
+        assert_eq!(settings.timing().unwrap(), Some(Timing::Vip));
+        assert_eq!(settings.layout().unwrap(), Some(Layout::eti660()));
+        assert_eq!(settings.font().unwrap(), Font::preset("fish"));
+        assert_eq!(settings.palette().unwrap(), Palette::preset("amber"));
+        let keymap = settings.keymap().unwrap().unwrap();
+        assert_eq!(keymap.lookup(&HostKey::keycode("Up")), Some(0x5));
+    }
+
+    #[test]
+    fn config_merge() {
+        let mut settings = Config::from_toml(CONFIG).unwrap().defaults;
+        settings.merge(&Settings {
+                           speed: Some(20),
+                           ..Settings::default()
+                       });
+        assert_eq!(settings.speed, Some(20));
+        assert_eq!(settings.palette, Some("amber".to_string()));
+    }
+
+    #[test]
+    fn config_errors() {
+        assert!(Config::from_toml("speed = \"fast\"").is_err());
+        let settings = Config::from_toml("quirks = \"eti\"\ntiming = \"x\"\nkeys = 5")
+            .unwrap()
+            .defaults;
+        assert!(settings.quirks().is_err());
+        assert!(settings.timing().is_err());
+        assert!(settings.keymap().is_err());
+    }
+
+    #[test]
+    fn config_unknown_keys() {
+        let err = Config::from_toml("sclae = 4").unwrap_err();
+        assert!(err.to_string().contains("sclae"), "{}", err);
+        assert!(Config::from_toml("quirk = \"vip\"").is_err());
+        assert!(Config::from_toml("[audio]\nvolum = 0.5").is_err());
+        assert!(Config::from_toml("[rom.da39a3ee5e6b4b0d3255bfef95601890afd80709]\nsped = 3")
+                    .is_err());
+    }
+}
@@ -0,0 +1,184 @@
+//! Code coverage of CHIP-8 programs.
+//!
+//! A `Coverage` counts which addresses were executed as instructions, read as data (sprites
+//! and `FX65`) and written (`FX33` and `FX55`). It is reported on the disassembly of the ROM,
+//! either annotated with the counts or as LCOV tracefile whose line numbers are those of the
+//! annotated disassembly.
+
+use disasm::{self, Line};
+use instruction::Instruction;
+use memory::MEMORY_SIZE;
+
+pub struct Coverage {
+    executed: Vec<u64>,
+    loaded: Vec<u64>,
+    stored: Vec<u64>,
+}
+
+impl Coverage {
+    pub fn new() -> Coverage {
+        Coverage {
+            executed: vec![0; MEMORY_SIZE],
+            loaded: vec![0; MEMORY_SIZE],
+            stored: vec![0; MEMORY_SIZE],
+        }
+    }
+
+    /// Records the execution of the instruction at `address`.
+    pub fn execute(&mut self, address: usize) {
+        self.executed[address % MEMORY_SIZE] += 1;
+    }
+
+    /// Records reading the byte at `address` as data.
+    pub fn load(&mut self, address: usize) {
+        self.loaded[address % MEMORY_SIZE] += 1;
+    }
+
+    /// Records writing the byte at `address`.
+    pub fn store(&mut self, address: usize) {
+        self.stored[address % MEMORY_SIZE] += 1;
+    }
+
+    pub fn executions(&self, address: usize) -> u64 {
+        self.executed[address % MEMORY_SIZE]
+    }
+
+    pub fn loads(&self, address: usize) -> u64 {
+        self.loaded[address % MEMORY_SIZE]
+    }
+
+    pub fn stores(&self, address: usize) -> u64 {
+        self.stored[address % MEMORY_SIZE]
+    }
+
+    // Returns how often an instruction starting at one of the bytes of `line` executed.
+    fn hits(&self, line: &Line) -> u64 {
+        (0..line.bytes.len()).map(|offset| self.executions(line.address + offset)).sum()
+    }
+
+    // Returns `x`, `r` and `w` for the bytes of `line` that were executed, read and written.
+    fn marks(&self, line: &Line) -> String {
+        let addresses = line.address..line.address + line.bytes.len();
+        let loaded = addresses.clone().any(|address| self.loads(address) > 0);
+        let stored = addresses.clone().any(|address| self.stores(address) > 0);
+        [(self.hits(line) > 0, 'x'), (loaded, 'r'), (stored, 'w')]
+            .iter()
+            .map(|&(set, mark)| if set { mark } else { '-' })
+            .collect()
+    }
+
+    /// Returns the disassembly of `rom`, loaded at `start`, with the number of executions of
+    /// each line and marks for lines that were executed (`x`), read (`r`) and written (`w`).
+    pub fn annotate(&self, rom: &[u8], start: usize) -> String {
+        let lines = disasm::disassemble(rom, start);
+        let mut text = String::new();
+        for (index, line) in lines.iter().enumerate() {
+            text += &format!("{:>8} {} {}",
+                             self.hits(line),
+                             self.marks(line),
+                             disasm::listing(&lines[index..index + 1]));
+        }
+        text
+    }
+
+    /// Returns the coverage as LCOV tracefile for the annotated disassembly saved as `source`.
+    /// Subroutines are the lines called by `2NNN`.
+    pub fn lcov(&self, rom: &[u8], start: usize, source: &str) -> String {
+        let lines = disasm::disassemble(rom, start);
+        let mut text = format!("TN:\nSF:{}\n", source);
+
+        let mut functions = Vec::new();
+        for line in &lines {
+            if let Instruction::Call(target) = line.instruction {
+                let target = target as usize;
+                if let Some(number) = lines.iter().position(|line| line.address == target) {
+                    if !functions.contains(&number) {
+                        functions.push(number);
+                    }
+                }
+            }
+        }
+        functions.sort();
+        for &number in &functions {
+            text += &format!("FN:{},sub_{:03X}\n", number + 1, lines[number].address);
+        }
+        for &number in &functions {
+            text += &format!("FNDA:{},sub_{:03X}\n",
+                             self.hits(&lines[number]),
+                             lines[number].address);
+        }
+        let called = functions.iter().filter(|&&number| self.hits(&lines[number]) > 0).count();
+        text += &format!("FNF:{}\nFNH:{}\n", functions.len(), called);
+
+        for (number, line) in lines.iter().enumerate() {
+            text += &format!("DA:{},{}\n", number + 1, self.hits(line));
+        }
+        let hit = lines.iter().filter(|line| self.hits(line) > 0).count();
+        text += &format!("LF:{}\nLH:{}\nend_of_record\n", lines.len(), hit);
+        text
+    }
+
+    /// Describes how much of `rom`, loaded at `start`, was executed, read and written.
+    pub fn summary(&self, rom: &[u8], start: usize) -> String {
+        let lines = disasm::disassemble(rom, start);
+        let hit = lines.iter().filter(|line| self.hits(line) > 0).count();
+        let addresses = start..start + rom.len();
+        let loaded = addresses.clone().filter(|&address| self.loads(address) > 0).count();
+        let stored = addresses.filter(|&address| self.stores(address) > 0).count();
+        format!("Executed {} of {} instructions ({:.1}%), read {} and wrote {} of {} bytes",
+                hit,
+                lines.len(),
+                if lines.is_empty() { 0.0 } else { hit as f64 * 100.0 / lines.len() as f64 },
+                loaded,
+                stored,
+                rom.len())
+    }
+}
+
+impl Default for Coverage {
+    fn default() -> Coverage {
+        Coverage::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Calls a subroutine at 0x206 that draws the sprite at 0x20A, and loops.
+    const ROM: [u8; 11] = [0x22, 0x06, 0x12, 0x02, 0x00, 0x00, 0xD0, 0x01, 0x00, 0xEE, 0xF0];
+
+    fn coverage() -> Coverage {
+        let mut coverage = Coverage::new();
+        for &address in [0x200, 0x206, 0x208, 0x202, 0x202].iter() {
+            coverage.execute(address);
+        }
+        coverage.load(0x20A);
+        coverage.store(0x205);
+        coverage
+    }
+
+    #[test]
+    fn coverage_annotate() {
+        let text = coverage().annotate(&ROM, 0x200);
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines[0], "       1 x-- 200: 22 06       CALL 0x206");
+        assert_eq!(lines[1], "       2 x-- 202: 12 02       JP 0x202");
+        assert_eq!(lines[2], "       0 --w 204: 00 00       SYS 0x000");
+        assert_eq!(lines[5], "       0 -r- 20A: F0          DB 0xF0");
+    }
+
+    #[test]
+    fn coverage_lcov() {
+        let text = coverage().lcov(&ROM, 0x200, "game.lst");
+        assert_eq!(text,
+                   "TN:\nSF:game.lst\nFN:4,sub_206\nFNDA:1,sub_206\nFNF:1\nFNH:1\nDA:1,1\n\
+                    DA:2,2\nDA:3,0\nDA:4,1\nDA:5,1\nDA:6,0\nLF:6\nLH:4\nend_of_record\n");
+    }
+
+    #[test]
+    fn coverage_summary() {
+        assert_eq!(coverage().summary(&ROM, 0x200),
+                   "Executed 4 of 6 instructions (66.7%), read 1 and wrote 1 of 11 bytes");
+    }
+}
@@ -0,0 +1,233 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, OnceLock};
+
+use serde_json;
+
+use error::Chip8Error;
+use instruction::Platform;
+use keymap::KeyMap;
+use palette::Palette;
+use quirks::Quirks;
+
+const BUNDLED_HASHES: &str = include_str!("../database/sha1-hashes.json");
+const BUNDLED_PROGRAMS: &str = include_str!("../database/programs.json");
+
+#[derive(Deserialize)]
+struct Program {
+    title: String,
+    #[serde(default)]
+    authors: Vec<String>,
+    #[serde(default)]
+    roms: HashMap<String, Rom>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Rom {
+    #[serde(default)]
+    platforms: Vec<String>,
+    tickrate: Option<usize>,
+    start_address: Option<usize>,
+    #[serde(default)]
+    keys: HashMap<String, u8>,
+    colors: Option<Colors>,
+}
+
+#[derive(Deserialize)]
+struct Colors {
+    #[serde(default)]
+    pixels: Vec<String>,
+}
+
+/// What the database knows about a ROM.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RomInfo {
+    pub title: String,
+    pub authors: Vec<String>,
+    /// Identifier of the preferred platform, e.g. `originalChip8` or `superchip`.
+    pub platform_id: String,
+    pub platform: Platform,
+    pub quirks: Quirks,
+    /// Instructions per frame.
+    pub tickrate: Option<usize>,
+    pub start_address: Option<usize>,
+    pub palette: Option<Palette>,
+    /// Game actions such as `up` or `a` and the CHIP-8 keys they are played with.
+    pub keys: Vec<(String, u8)>,
+    /// Host key bindings chosen by the ROM.
+    pub keymap: Option<KeyMap>,
+}
+
+/// ROM compatibility database in the format of the CHIP-8 community database
+/// (`sha1-hashes.json` and `programs.json`).
+pub struct Database {
+    hashes: HashMap<String, usize>,
+    programs: Vec<Program>,
+}
+
+impl Database {
+    pub fn from_json(hashes: &str, programs: &str) -> Result<Database, Chip8Error> {
+        let hashes = serde_json::from_str(hashes).map_err(|err| err.to_string())?;
+        let programs = serde_json::from_str(programs).map_err(|err| err.to_string())?;
+        Ok(Database {
+               hashes,
+               programs,
+           })
+    }
+
+    /// Loads `sha1-hashes.json` and `programs.json` from `dir`.
+    pub fn load<P: AsRef<Path>>(dir: P) -> Result<Database, Chip8Error> {
+        let hashes = fs::read_to_string(dir.as_ref().join("sha1-hashes.json"))?;
+        let programs = fs::read_to_string(dir.as_ref().join("programs.json"))?;
+        Database::from_json(&hashes, &programs)
+    }
+
+    /// Returns the database compiled into the emulator.
+    pub fn bundled() -> Arc<Database> {
+        static BUNDLED: OnceLock<Arc<Database>> = OnceLock::new();
+        BUNDLED.get_or_init(|| {
+                                Arc::new(Database::from_json(BUNDLED_HASHES, BUNDLED_PROGRAMS)
+                                             .expect("Invalid bundled ROM database"))
+                            })
+            .clone()
+    }
+
+    pub fn len(&self) -> usize {
+        self.hashes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hashes.is_empty()
+    }
+
+    /// Looks up the ROM with the SHA-1 `hash`.
+    pub fn lookup(&self, hash: &str) -> Option<RomInfo> {
+        let hash = hash.to_lowercase();
+        self.hashes
+            .get(&hash)
+            .and_then(|&index| self.programs.get(index))
+            .and_then(|program| program.roms.get(&hash).map(|rom| rom_info(program, rom)))
+    }
+}
+
+fn rom_info(program: &Program, rom: &Rom) -> RomInfo {
+    let platform_id = rom.platforms.first().cloned().unwrap_or_else(|| "modernChip8".into());
+    let palette = match rom.colors.as_ref().map(|colors| colors.pixels.as_slice()) {
+        Some([background, foreground, ..]) => {
+            Palette::resolve(&format!("{},{}", foreground, background)).ok()
+        }
+        _ => None,
+    };
+    let mut keys: Vec<(String, u8)> =
+        rom.keys.iter().map(|(action, &key)| (action.clone(), key)).collect();
+    keys.sort_by_key(|&(_, key)| key);
+
+    RomInfo {
+        title: program.title.clone(),
+        authors: program.authors.clone(),
+        platform: platform(&platform_id),
+        quirks: quirks(&platform_id),
+        platform_id,
+        tickrate: rom.tickrate,
+        start_address: rom.start_address,
+        palette,
+        keys,
+        keymap: None,
+    }
+}
+
+fn platform(id: &str) -> Platform {
+    match id {
+        "chip48" | "superchip1" | "superchip" => Platform::Schip,
+        "xochip" => Platform::XoChip,
+        _ => Platform::Chip8,
+    }
+}
+
+fn quirks(id: &str) -> Quirks {
+    match id {
+        "originalChip8" | "hybridVIP" => Quirks::vip(),
+        "chip48" | "superchip1" | "superchip" => Quirks::schip(),
+        _ => Quirks::default(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HASHES: &str = r#"{"0123456789abcdef0123456789abcdef01234567": 0,
+                             "89abcdef0123456789abcdef0123456789abcdef": 1}"#;
+    const PROGRAMS: &str = r##"[
+        {
+            "title": "Breakout",
+            "authors": ["Someone"],
+            "roms": {
+                "0123456789abcdef0123456789abcdef01234567": {
+                    "file": "breakout.ch8",
+                    "platforms": ["originalChip8"],
+                    "tickrate": 15,
+                    "keys": {"left": 4, "right": 6},
+                    "colors": {"pixels": ["#000000", "#ffaa00"]}
+                }
+            }
+        },
+        {
+            "title": "Racer",
+            "roms": {
+                "89abcdef0123456789abcdef0123456789abcdef": {
+                    "platforms": ["superchip", "xochip"],
+                    "startAddress": 512
+                }
+            }
+        }
+    ]"##;
+
+    #[test]
+    fn database_lookup() {
+        let database = Database::from_json(HASHES, PROGRAMS).unwrap();
+        assert_eq!(database.len(), 2);
+
+        let info = database.lookup("0123456789ABCDEF0123456789ABCDEF01234567").unwrap();
+        assert_eq!(info.title, "Breakout");
+        assert_eq!(info.authors, vec!["Someone".to_string()]);
+        assert_eq!(info.platform, Platform::Chip8);
+        assert_eq!(info.quirks, Quirks::vip());
+        assert_eq!(info.tickrate, Some(15));
+        assert_eq!(info.palette.unwrap().foreground, (0xFF, 0xAA, 0x00));
+        assert_eq!(info.keys, vec![("left".to_string(), 4), ("right".to_string(), 6)]);
+
+        let info = database.lookup("89abcdef0123456789abcdef0123456789abcdef").unwrap();
+        assert_eq!(info.platform, Platform::Schip);
+        assert_eq!(info.start_address, Some(0x200));
+        assert_eq!(info.palette, None);
+
+        assert!(database.lookup("da39a3ee5e6b4b0d3255bfef95601890afd80709").is_none());
+    }
+
+    #[test]
+    fn database_invalid() {
+        assert!(Database::from_json("[]", PROGRAMS).is_err());
+        assert!(Database::from_json(HASHES, "{").is_err());
+    }
+
+    #[test]
+    fn database_bundled() {
+        use std::io::{self, Write};
+
+        // Every hash of the bundled database must lead to a ROM of its program. The database is
+        // empty until database/update.sh has been run, which the test reports past the output
+        // capture of the test harness.
+        let database = Database::bundled();
+        if database.is_empty() {
+            writeln!(io::stderr(),
+                     "database_bundled: skipped, the bundled ROM database is empty")
+                .unwrap();
+        }
+        for hash in database.hashes.keys() {
+            assert!(database.lookup(hash).is_some(), "{}", hash);
+        }
+    }
+}
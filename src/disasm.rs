@@ -0,0 +1,89 @@
+use instruction::{self, Instruction};
+
+/// A single disassembled instruction.
+pub struct Line {
+    pub address: usize,
+    pub bytes: Vec<u8>,
+    pub instruction: Instruction,
+}
+
+/// Disassembles `rom` as if it was loaded at `origin`.
+///
+/// A trailing odd byte is reported as an unknown instruction.
+pub fn disassemble(rom: &[u8], origin: usize) -> Vec<Line> {
+    let mut lines = Vec::new();
+    let mut offset = 0;
+    while offset < rom.len() {
+        let high = rom[offset] as u16;
+        let low = rom.get(offset + 1).cloned().unwrap_or(0) as u16;
+        let decoded = instruction::decode((high << 8) | low);
+        let size = if offset + decoded.size() <= rom.len() {
+            decoded.size()
+        } else {
+            rom.len() - offset
+        };
+
+        lines.push(Line {
+                       address: origin + offset,
+                       bytes: rom[offset..offset + size].to_vec(),
+                       instruction: decoded,
+                   });
+        offset += size;
+    }
+    lines
+}
+
+/// Formats `lines` as an assembly listing with addresses and raw bytes.
+pub fn listing(lines: &[Line]) -> String {
+    let mut output = String::new();
+    for line in lines {
+        let bytes: Vec<String> = line.bytes.iter().map(|byte| format!("{:02X}", byte)).collect();
+        let text = match (line.instruction, line.bytes.len()) {
+            (Instruction::LdILong, 4) => {
+                format!("LD I, LONG 0x{:04X}",
+                        ((line.bytes[2] as u16) << 8) | line.bytes[3] as u16)
+            }
+            (_, 1) => format!("DB 0x{:02X}", line.bytes[0]),
+            (instruction, _) => instruction.to_string(),
+        };
+        output.push_str(&format!("{:03X}: {:<11} {}\n", line.address, bytes.join(" "), text));
+    }
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn disassemble_instructions() {
+        let lines = disassemble(&[0x00, 0xE0, 0x12, 0x00], 0x200);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0].address, 0x200);
+        assert_eq!(lines[0].instruction, Instruction::Cls);
+        assert_eq!(lines[1].address, 0x202);
+        assert_eq!(lines[1].instruction, Instruction::Jp(0x200));
+    }
+
+    #[test]
+    fn disassemble_long_load() {
+        let lines = disassemble(&[0xF0, 0x00, 0x12, 0x34, 0x00, 0xE0], 0x200);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1].address, 0x204);
+        assert!(listing(&lines).contains("LD I, LONG 0x1234"));
+    }
+
+    #[test]
+    fn disassemble_odd_length() {
+        let lines = disassemble(&[0x00, 0xE0, 0xAB], 0x200);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1].bytes, vec![0xAB]);
+        assert!(listing(&lines).contains("DB 0xAB"));
+    }
+
+    #[test]
+    fn listing_format() {
+        let lines = disassemble(&[0x6A, 0x1F], 0x200);
+        assert_eq!(listing(&lines), "200: 6A 1F       LD VA, 0x1F\n");
+    }
+}
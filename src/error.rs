@@ -7,12 +7,27 @@ pub enum Chip8Error {
     Message(String),
 }
 
+impl std::fmt::Display for Chip8Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            Chip8Error::Message(ref msg) => f.write_str(msg),
+            ref err => write!(f, "{:?}", err),
+        }
+    }
+}
+
 impl From<String> for Chip8Error {
     fn from(msg: String) -> Chip8Error {
         Chip8Error::Message(msg)
     }
 }
 
+impl From<std::io::Error> for Chip8Error {
+    fn from(err: std::io::Error) -> Chip8Error {
+        Chip8Error::Message(err.to_string())
+    }
+}
+
 impl From<sdl2::IntegerOrSdlError> for Chip8Error {
     fn from(_: sdl2::IntegerOrSdlError) -> Chip8Error {
         Chip8Error::IntegerOrSdlError
@@ -25,8 +40,8 @@ impl From<sdl2::video::WindowBuildError> for Chip8Error {
     }
 }
 
-impl From<std::boxed::Box<std::any::Any + std::marker::Send>> for Chip8Error {
-    fn from(err: std::boxed::Box<std::any::Any + std::marker::Send>) -> Chip8Error {
+impl From<std::boxed::Box<dyn std::any::Any + std::marker::Send>> for Chip8Error {
+    fn from(err: std::boxed::Box<dyn std::any::Any + std::marker::Send>) -> Chip8Error {
         Chip8Error::Message(format!("{:?}", err))
     }
 }
\ No newline at end of file
@@ -0,0 +1,173 @@
+use std::fs;
+use std::path::Path;
+
+use error::Chip8Error;
+
+/// Bytes of the small font: 16 hexadecimal digits of 5 rows.
+pub const SMALL_SIZE: usize = 16 * 5;
+/// Bytes per digit of the large font, which has 10 rows.
+pub const LARGE_DIGIT_SIZE: usize = 10;
+/// Largest font: small digits followed by 16 large digits.
+pub const MAX_SIZE: usize = SMALL_SIZE + 16 * LARGE_DIGIT_SIZE;
+
+pub const PRESETS: [&str; 5] = ["vip", "dream6800", "eti660", "fish", "schip"];
+
+const VIP: [u8; SMALL_SIZE] =
+    [0xF0, 0x90, 0x90, 0x90, 0xF0, 0x60, 0x20, 0x20, 0x20, 0x70, 0xF0, 0x10, 0xF0, 0x80, 0xF0,
+     0xF0, 0x10, 0xF0, 0x10, 0xF0, 0xA0, 0xA0, 0xF0, 0x20, 0x20, 0xF0, 0x80, 0xF0, 0x10, 0xF0,
+     0xF0, 0x80, 0xF0, 0x90, 0xF0, 0xF0, 0x10, 0x10, 0x10, 0x10, 0xF0, 0x90, 0xF0, 0x90, 0xF0,
+     0xF0, 0x90, 0xF0, 0x10, 0xF0, 0xF0, 0x90, 0xF0, 0x90, 0x90, 0xF0, 0x50, 0x70, 0x50, 0xF0,
+     0xF0, 0x80, 0x80, 0x80, 0xF0, 0xF0, 0x50, 0x50, 0x50, 0xF0, 0xF0, 0x80, 0xF0, 0x80, 0xF0,
+     0xF0, 0x80, 0xF0, 0x80, 0x80];
+
+const DREAM6800: [u8; SMALL_SIZE] =
+    [0xE0, 0xA0, 0xA0, 0xA0, 0xE0, 0x40, 0x40, 0x40, 0x40, 0x40, 0xE0, 0x20, 0xE0, 0x80, 0xE0,
+     0xE0, 0x20, 0xE0, 0x20, 0xE0, 0x80, 0xA0, 0xA0, 0xE0, 0x20, 0xE0, 0x80, 0xE0, 0x20, 0xE0,
+     0xE0, 0x80, 0xE0, 0xA0, 0xE0, 0xE0, 0x20, 0x20, 0x20, 0x20, 0xE0, 0xA0, 0xE0, 0xA0, 0xE0,
+     0xE0, 0xA0, 0xE0, 0x20, 0xE0, 0xE0, 0xA0, 0xE0, 0xA0, 0xA0, 0xC0, 0xA0, 0xE0, 0xA0, 0xC0,
+     0xE0, 0x80, 0x80, 0x80, 0xE0, 0xC0, 0xA0, 0xA0, 0xA0, 0xC0, 0xE0, 0x80, 0xE0, 0x80, 0xE0,
+     0xE0, 0x80, 0xC0, 0x80, 0x80];
+
+const ETI660: [u8; SMALL_SIZE] =
+    [0xE0, 0xA0, 0xA0, 0xA0, 0xE0, 0x20, 0x20, 0x20, 0x20, 0x20, 0xE0, 0x20, 0xE0, 0x80, 0xE0,
+     0xE0, 0x20, 0xE0, 0x20, 0xE0, 0xA0, 0xA0, 0xE0, 0x20, 0x20, 0xE0, 0x80, 0xE0, 0x20, 0xE0,
+     0xE0, 0x80, 0xE0, 0xA0, 0xE0, 0xE0, 0x20, 0x20, 0x20, 0x20, 0xE0, 0xA0, 0xE0, 0xA0, 0xE0,
+     0xE0, 0xA0, 0xE0, 0x20, 0xE0, 0xE0, 0xA0, 0xE0, 0xA0, 0xA0, 0x80, 0x80, 0xE0, 0xA0, 0xE0,
+     0xE0, 0x80, 0x80, 0x80, 0xE0, 0x20, 0x20, 0xE0, 0xA0, 0xE0, 0xE0, 0x80, 0xE0, 0x80, 0xE0,
+     0xE0, 0x80, 0xE0, 0x80, 0x80];
+
+const FISH: [u8; SMALL_SIZE] =
+    [0x60, 0xA0, 0xA0, 0xA0, 0xC0, 0x40, 0xC0, 0x40, 0x40, 0xE0, 0xC0, 0x20, 0x40, 0x80, 0xE0,
+     0xC0, 0x20, 0x40, 0x20, 0xC0, 0x20, 0xA0, 0xE0, 0x20, 0x20, 0xE0, 0x80, 0xC0, 0x20, 0xC0,
+     0x40, 0x80, 0xC0, 0xA0, 0x40, 0xE0, 0x20, 0x60, 0x40, 0x40, 0x40, 0xA0, 0x40, 0xA0, 0x40,
+     0x40, 0xA0, 0x60, 0x20, 0x40, 0x40, 0xA0, 0xE0, 0xA0, 0xA0, 0xC0, 0xA0, 0xC0, 0xA0, 0xC0,
+     0x60, 0x80, 0x80, 0x80, 0x60, 0xC0, 0xA0, 0xA0, 0xA0, 0xC0, 0xE0, 0x80, 0xC0, 0x80, 0xE0,
+     0xE0, 0x80, 0xC0, 0x80, 0x80];
+
+const SCHIP: [u8; SMALL_SIZE] =
+    [0xF0, 0x90, 0x90, 0x90, 0xF0, 0x20, 0x60, 0x20, 0x20, 0x70, 0xF0, 0x10, 0xF0, 0x80, 0xF0,
+     0xF0, 0x10, 0xF0, 0x10, 0xF0, 0x90, 0x90, 0xF0, 0x10, 0x10, 0xF0, 0x80, 0xF0, 0x10, 0xF0,
+     0xF0, 0x80, 0xF0, 0x90, 0xF0, 0xF0, 0x10, 0x20, 0x40, 0x40, 0xF0, 0x90, 0xF0, 0x90, 0xF0,
+     0xF0, 0x90, 0xF0, 0x10, 0xF0, 0xF0, 0x90, 0xF0, 0x90, 0x90, 0xE0, 0x90, 0xE0, 0x90, 0xE0,
+     0xF0, 0x80, 0x80, 0x80, 0xF0, 0xE0, 0x90, 0x90, 0x90, 0xE0, 0xF0, 0x80, 0xF0, 0x80, 0xF0,
+     0xF0, 0x80, 0xF0, 0x80, 0x80];
+
+// SUPER-CHIP 1.1 only has large digits 0-9.
+const SCHIP_LARGE: [u8; 10 * LARGE_DIGIT_SIZE] =
+    [0x3C, 0x7E, 0xE7, 0xC3, 0xC3, 0xC3, 0xC3, 0xE7, 0x7E, 0x3C, 0x18, 0x38, 0x58, 0x18, 0x18,
+     0x18, 0x18, 0x18, 0x18, 0x3C, 0x3E, 0x7F, 0xC3, 0x06, 0x0C, 0x18, 0x30, 0x60, 0xFF, 0xFF,
+     0x3C, 0x7E, 0xC3, 0x03, 0x0E, 0x0E, 0x03, 0xC3, 0x7E, 0x3C, 0x06, 0x0E, 0x1E, 0x36, 0x66,
+     0xC6, 0xFF, 0xFF, 0x06, 0x06, 0xFF, 0xFF, 0xC0, 0xC0, 0xFC, 0xFE, 0x03, 0xC3, 0x7E, 0x3C,
+     0x3E, 0x7C, 0xE0, 0xC0, 0xFC, 0xFE, 0xC3, 0xC3, 0x7E, 0x3C, 0xFF, 0xFF, 0x03, 0x06, 0x0C,
+     0x18, 0x30, 0x60, 0x60, 0x60, 0x3C, 0x7E, 0xC3, 0xC3, 0x7E, 0x7E, 0xC3, 0xC3, 0x7E, 0x3C,
+     0x3C, 0x7E, 0xC3, 0xC3, 0x7F, 0x3F, 0x03, 0x03, 0x3E, 0x7C];
+
+/// Hexadecimal digits drawn by `FX29` and the large decimal digits drawn by `FX30`.
+///
+/// Both are stored together at the font address of the memory layout, the large digits right
+/// after the small ones.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Font {
+    pub small: Vec<u8>,
+    pub large: Vec<u8>,
+}
+
+impl Font {
+    /// Returns one of the `PRESETS`. Machines without large digits get those of SUPER-CHIP.
+    pub fn preset(name: &str) -> Option<Font> {
+        let small = match name.to_lowercase().as_str() {
+            "vip" => VIP,
+            "dream6800" | "dream-6800" => DREAM6800,
+            "eti660" | "eti-660" => ETI660,
+            "fish" | "fish-n-chips" => FISH,
+            "schip" => SCHIP,
+            _ => return None,
+        };
+        Some(Font {
+                 small: small.to_vec(),
+                 large: SCHIP_LARGE.to_vec(),
+             })
+    }
+
+    /// Reads a font file: the 80 bytes of the small font, optionally followed by 10 or 16
+    /// large digits.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Font, Chip8Error> {
+        let data = fs::read(path)?;
+        Font::from_bytes(&data)
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Font, Chip8Error> {
+        let large = match data.len() {
+            SMALL_SIZE => SCHIP_LARGE.to_vec(),
+            size if size == SMALL_SIZE + 10 * LARGE_DIGIT_SIZE || size == MAX_SIZE => {
+                data[SMALL_SIZE..].to_vec()
+            }
+            size => return Err(Chip8Error::Message(format!("Invalid font size: {} bytes", size))),
+        };
+        Ok(Font {
+               small: data[..SMALL_SIZE].to_vec(),
+               large,
+           })
+    }
+
+    /// Resolves a preset name or the path of a font file.
+    pub fn resolve(name_or_path: &str) -> Result<Font, Chip8Error> {
+        match Font::preset(name_or_path) {
+            Some(font) => Ok(font),
+            None => Font::load(name_or_path),
+        }
+    }
+
+    /// Returns the address of the small glyph of `digit` when the font is stored at `address`.
+    pub fn small_digit(&self, address: usize, digit: u8) -> usize {
+        address + (digit & 0x0F) as usize * 5
+    }
+
+    /// Returns the address of the large glyph of `digit` when the font is stored at `address`.
+    pub fn large_digit(&self, address: usize, digit: u8) -> usize {
+        address + self.small.len() + (digit & 0x0F) as usize * LARGE_DIGIT_SIZE
+    }
+}
+
+impl Default for Font {
+    fn default() -> Font {
+        Font::preset("schip").unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn font_presets() {
+        for name in PRESETS.iter() {
+            let font = Font::preset(name).unwrap();
+            assert_eq!(font.small.len(), SMALL_SIZE);
+            assert_eq!(font.large.len(), 10 * LARGE_DIGIT_SIZE);
+        }
+        assert_eq!(Font::preset("FISH-N-CHIPS"), Font::preset("fish"));
+        assert!(Font::preset("c64").is_none());
+    }
+
+    #[test]
+    fn font_digit_addresses() {
+        let font = Font::default();
+        assert_eq!(font.small_digit(0x50, 0xA), 0x50 + 50);
+        assert_eq!(font.large_digit(0x50, 3), 0x50 + SMALL_SIZE + 30);
+    }
+
+    #[test]
+    fn font_from_bytes() {
+        let font = Font::from_bytes(&[0x11; SMALL_SIZE]).unwrap();
+        assert_eq!(font.small, vec![0x11; SMALL_SIZE]);
+        assert_eq!(font.large, SCHIP_LARGE.to_vec());
+
+        let font = Font::from_bytes(&[0x22; MAX_SIZE]).unwrap();
+        assert_eq!(font.large.len(), 16 * LARGE_DIGIT_SIZE);
+
+        assert!(Font::from_bytes(&[0; 40]).is_err());
+        assert!(Font::from_bytes(&[0; SMALL_SIZE + 7]).is_err());
+        assert!(Font::resolve("/nonexistent/font.bin").is_err());
+    }
+}
@@ -0,0 +1,132 @@
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender, SyncSender, TrySendError};
+use std::time::Duration;
+
+use gfx::GfxMemory;
+
+/// Creates a double-buffered channel for handing completed frames to a renderer thread.
+///
+/// Buffers travel back and forth between both ends, so sending never blocks or allocates.
+/// When the renderer falls behind, the sender drops frames instead of waiting for it.
+pub fn channel() -> (FrameSender, FrameReceiver) {
+    let (frame_tx, frame_rx) = mpsc::sync_channel(1);
+    let (spare_tx, spare_rx) = mpsc::channel();
+    spare_tx.send(Box::new(GfxMemory::new())).unwrap();
+    spare_tx.send(Box::new(GfxMemory::new())).unwrap();
+
+    (FrameSender {
+         frames: frame_tx,
+         spare: spare_rx,
+         unsent: None,
+     },
+     FrameReceiver {
+         frames: frame_rx,
+         spare: spare_tx,
+         current: Box::new(GfxMemory::new()),
+     })
+}
+
+pub struct FrameSender {
+    frames: SyncSender<Box<GfxMemory>>,
+    spare: Receiver<Box<GfxMemory>>,
+    unsent: Option<Box<GfxMemory>>,
+}
+
+impl FrameSender {
+    /// Publishes a copy of `gfx`. Returns false if the frame was dropped.
+    pub fn send(&mut self, gfx: &GfxMemory) -> bool {
+        let buffer = match self.unsent.take() {
+            Some(buffer) => Some(buffer),
+            None => self.spare.try_recv().ok(),
+        };
+
+        if let Some(mut buffer) = buffer {
+            buffer.copy_from(gfx);
+            match self.frames.try_send(buffer) {
+                Ok(()) => return true,
+                Err(TrySendError::Full(buffer)) |
+                Err(TrySendError::Disconnected(buffer)) => self.unsent = Some(buffer),
+            }
+        }
+
+        false
+    }
+}
+
+pub struct FrameReceiver {
+    frames: Receiver<Box<GfxMemory>>,
+    spare: Sender<Box<GfxMemory>>,
+    current: Box<GfxMemory>,
+}
+
+impl FrameReceiver {
+    /// Waits for the next frame. Returns `None` once the sender is gone.
+    pub fn recv(&mut self) -> Option<&GfxMemory> {
+        match self.frames.recv() {
+            Ok(buffer) => {
+                self.swap(buffer);
+                Some(&self.current)
+            }
+            Err(_) => None,
+        }
+    }
+
+    /// Waits up to `timeout` for the next frame, for renderers that have to keep handling
+    /// events while no frame arrives.
+    pub fn recv_timeout(&mut self, timeout: Duration) -> Result<&GfxMemory, RecvTimeoutError> {
+        let buffer = self.frames.recv_timeout(timeout)?;
+        self.swap(buffer);
+        Ok(&self.current)
+    }
+
+    fn swap(&mut self, buffer: Box<GfxMemory>) {
+        let previous = ::std::mem::replace(&mut self.current, buffer);
+        let _ = self.spare.send(previous);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_send_receive() {
+        let (mut sender, mut receiver) = channel();
+        let mut gfx = GfxMemory::new();
+        gfx[0] = 0x01;
+        assert!(sender.send(&gfx));
+        assert_eq!(receiver.recv().unwrap()[0], 0x01);
+    }
+
+    #[test]
+    fn frame_dropped_when_behind() {
+        let (mut sender, mut receiver) = channel();
+        let mut gfx = GfxMemory::new();
+        assert!(sender.send(&gfx));
+        gfx[0] = 0x01;
+        assert!(!sender.send(&gfx));
+        assert_eq!(receiver.recv().unwrap()[0], 0x00);
+        assert!(sender.send(&gfx));
+        assert_eq!(receiver.recv().unwrap()[0], 0x01);
+    }
+
+    #[test]
+    fn frame_disconnected() {
+        let (sender, mut receiver) = channel();
+        drop(sender);
+        assert!(receiver.recv().is_none());
+    }
+
+    #[test]
+    fn frame_recv_timeout() {
+        let (mut sender, mut receiver) = channel();
+        let timeout = Duration::from_millis(1);
+        assert_eq!(receiver.recv_timeout(timeout).err(), Some(RecvTimeoutError::Timeout));
+        let mut gfx = GfxMemory::new();
+        gfx[0] = 0x01;
+        assert!(sender.send(&gfx));
+        assert_eq!(receiver.recv_timeout(timeout).unwrap()[0], 0x01);
+        drop(sender);
+        assert_eq!(receiver.recv_timeout(timeout).err(),
+                   Some(RecvTimeoutError::Disconnected));
+    }
+}
@@ -0,0 +1,130 @@
+use super::*;
+
+use std::thread;
+use std::time::{Duration, Instant};
+
+use gfx::GfxMemory;
+use input::InputQueue;
+use keymap::KeyMap;
+use padmap::PadMap;
+use palette::Palette;
+use error::Chip8Error;
+
+const FRAMES_PER_SECOND: u64 = 60;
+pub const DEFAULT_SCALE: usize = 20;
+
+/// Video, audio and input backend driven by `Chip8::run` once per frame.
+pub trait Frontend {
+    /// Shows the completed frame.
+    fn present(&mut self, gfx: &GfxMemory) -> Result<(), Chip8Error>;
+
+    /// Queues the key changes of the host input devices since the last call.
+    fn poll_input(&mut self, input: &mut InputQueue);
+
+    /// Starts or stops the buzzer.
+    fn play_audio(&mut self, playing: bool);
+
+    /// Tells whether the program is halted on `FX0A` waiting for a key.
+    fn wait_for_key(&mut self, _waiting: bool) {}
+
+    /// Returns true once the user asked to stop the emulator.
+    fn quit_requested(&self) -> bool;
+}
+
+/// Buzzer settings.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Audio {
+    /// Tone frequency in Hz.
+    pub frequency: f32,
+    /// Amplitude between 0 and 1.
+    pub volume: f32,
+    pub muted: bool,
+}
+
+impl Default for Audio {
+    fn default() -> Audio {
+        Audio {
+            frequency: 440.0,
+            volume: 0.25,
+            muted: false,
+        }
+    }
+}
+
+/// Settings shared by all frontends. Each frontend uses the ones it supports.
+pub struct Options {
+    pub keymap: KeyMap,
+    pub padmap: PadMap,
+    /// Window pixels per CHIP-8 pixel.
+    pub scale: usize,
+    pub palette: Palette,
+    pub audio: Audio,
+    /// Number of frames after which the headless frontend quits.
+    pub max_frames: Option<u64>,
+}
+
+impl Default for Options {
+    fn default() -> Options {
+        Options {
+            keymap: KeyMap::default(),
+            padmap: PadMap::default(),
+            scale: DEFAULT_SCALE,
+            palette: Palette::default(),
+            audio: Audio::default(),
+            max_frames: None,
+        }
+    }
+}
+
+/// Creates the frontend registered under `name`.
+pub fn create(name: &str, options: Options) -> Result<Box<dyn Frontend>, Chip8Error> {
+    match name {
+        #[cfg(not(test))]
+        "sdl" => Ok(Box::new(renderer::Renderer::new(options)?)),
+        "terminal" => Ok(Box::new(terminal::Terminal::new())),
+        "headless" => Ok(Box::new(headless::Headless::new(options.max_frames))),
+        _ => Err(Chip8Error::Message(format!("Unknown frontend: {}", name))),
+    }
+}
+
+/// Paces interactive frontends to 60 frames per second.
+pub struct FrameLimiter {
+    next_frame: Instant,
+}
+
+impl FrameLimiter {
+    pub fn new() -> FrameLimiter {
+        FrameLimiter { next_frame: Instant::now() }
+    }
+
+    pub fn wait(&mut self) {
+        self.next_frame += Duration::from_millis(1000 / FRAMES_PER_SECOND);
+        let now = Instant::now();
+        if self.next_frame > now {
+            thread::sleep(self.next_frame - now);
+        } else {
+            self.next_frame = now;
+        }
+    }
+}
+
+impl Default for FrameLimiter {
+    fn default() -> FrameLimiter {
+        FrameLimiter::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn create_headless() {
+        assert!(create("headless", Options::default()).is_ok());
+    }
+
+    #[test]
+    fn create_unknown() {
+        assert!(create("vga", Options::default()).is_err());
+    }
+}
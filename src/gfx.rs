@@ -1,7 +1,10 @@
 use std::ops::{Index, IndexMut};
 
+use super::{DISPLAY_HEIGHT, DISPLAY_WIDTH};
+
 pub const GFX_MEMORY_SIZE: usize = super::DISPLAY_HEIGHT * super::DISPLAY_WIDTH;
 
+/// Display pixels, 1 when lit and 0 when dark.
 pub struct GfxMemory {
     memory: [u8; GFX_MEMORY_SIZE],
 }
@@ -28,16 +31,185 @@ impl GfxMemory {
         self.memory = [0; GFX_MEMORY_SIZE];
     }
 
+    pub fn copy_from(&mut self, other: &GfxMemory) {
+        self.memory = other.memory;
+    }
+
+    /// XORs the 8 pixel wide `sprite` onto the display with its top left corner at (`x`, `y`)
+    /// and returns the value of VF.
+    ///
+    /// The position wraps around the display. Parts of the sprite beyond the edges are cut off
+    /// with `clip` and continue on the opposite side otherwise. VF is 1 if any lit pixel was
+    /// turned off. With `count_rows` it is the number of rows that turned off a pixel or were
+    /// cut off at the bottom instead.
+    pub fn draw(&mut self, x: u8, y: u8, sprite: &[u8], clip: bool, count_rows: bool) -> u8 {
+        let x = x as usize % DISPLAY_WIDTH;
+        let y = y as usize % DISPLAY_HEIGHT;
+        let mut collision = false;
+        let mut rows = 0;
+
+        for (row, &bits) in sprite.iter().enumerate() {
+            let mut py = y + row;
+            if py >= DISPLAY_HEIGHT {
+                if clip {
+                    rows += 1;
+                    continue;
+                }
+                py %= DISPLAY_HEIGHT;
+            }
+
+            let mut row_collision = false;
+            for column in 0..8 {
+                if bits & (0x80 >> column) == 0 {
+                    continue;
+                }
+                let mut px = x + column;
+                if px >= DISPLAY_WIDTH {
+                    if clip {
+                        break;
+                    }
+                    px %= DISPLAY_WIDTH;
+                }
+
+                let pixel = &mut self.memory[py * DISPLAY_WIDTH + px];
+                row_collision |= *pixel == 1;
+                *pixel ^= 1;
+            }
+            if row_collision {
+                collision = true;
+                rows += 1;
+            }
+        }
+
+        if count_rows { rows } else { collision as u8 }
+    }
+
     #[cfg(test)]
     pub fn get(&self) -> &[u8] {
         &self.memory
     }
 }
 
+impl Default for GfxMemory {
+    fn default() -> GfxMemory {
+        GfxMemory::new()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    // Draws `sprite` onto `gfx` and returns VF with the top left 12x6 pixels as text.
+    fn draw(gfx: &mut GfxMemory,
+            position: (u8, u8),
+            sprite: &[u8],
+            clip: bool,
+            count_rows: bool)
+            -> (u8, String) {
+        let vf = gfx.draw(position.0, position.1, sprite, clip, count_rows);
+        (vf, picture(gfx, 0, 0))
+    }
+
+    fn picture(gfx: &GfxMemory, left: usize, top: usize) -> String {
+        let mut text = String::new();
+        for y in top..top + 6 {
+            for x in left..left + 12 {
+                text.push(if gfx[y * DISPLAY_WIDTH + x] == 1 { '#' } else { '.' });
+            }
+            text.push('\n');
+        }
+        text
+    }
+
+    const BOX: [u8; 3] = [0xE0, 0xA0, 0xE0];
+
+    #[test]
+    fn gfx_draw_xor() {
+        let mut gfx = GfxMemory::new();
+        assert_eq!(draw(&mut gfx, (1, 1), &BOX, false, false),
+                   (0,
+                    "............\n\
+                     .###........\n\
+                     .#.#........\n\
+                     .###........\n\
+                     ............\n\
+                     ............\n"
+                        .to_string()));
+        assert_eq!(draw(&mut gfx, (2, 2), &BOX, false, false),
+                   (1,
+                    "............\n\
+                     .###........\n\
+                     .##.#.......\n\
+                     .#.##.......\n\
+                     ..###.......\n\
+                     ............\n"
+                        .to_string()));
+        assert_eq!(draw(&mut gfx, (2, 2), &BOX, false, false).0, 1);
+        assert_eq!(draw(&mut gfx, (1, 1), &BOX, false, false),
+                   (1, ".".repeat(12) + "\n" + &(".".repeat(12) + "\n").repeat(5)));
+    }
+
+    #[test]
+    fn gfx_draw_collision_accumulates() {
+        let mut gfx = GfxMemory::new();
+        gfx[0] = 1;
+        // The collision in the first row is kept although later rows do not collide.
+        assert_eq!(gfx.draw(0, 0, &[0x80, 0x40, 0x20], false, false), 1);
+        assert_eq!(gfx[0], 0);
+    }
+
+    #[test]
+    fn gfx_draw_wrap() {
+        let mut gfx = GfxMemory::new();
+        let (vf, _) = draw(&mut gfx, (62, 30), &BOX, false, false);
+        assert_eq!(vf, 0);
+        assert_eq!(picture(&gfx, 0, 0),
+                   "#...........\n\
+                     ............\n\
+                     ............\n\
+                     ............\n\
+                     ............\n\
+                     ............\n");
+        assert_eq!(picture(&gfx, 52, 26),
+                   "............\n\
+                     ............\n\
+                     ............\n\
+                     ............\n\
+                     ..........##\n\
+                     ..........#.\n");
+        // The start position wraps as well.
+        let mut gfx = GfxMemory::new();
+        gfx.draw(64 + 1, 32 + 1, &BOX, false, false);
+        assert_eq!(gfx[DISPLAY_WIDTH + 1], 1);
+    }
+
+    #[test]
+    fn gfx_draw_clip() {
+        let mut gfx = GfxMemory::new();
+        assert_eq!(gfx.draw(62, 30, &BOX, true, false), 0);
+        assert_eq!(picture(&gfx, 52, 26),
+                   "............\n\
+                     ............\n\
+                     ............\n\
+                     ............\n\
+                     ..........##\n\
+                     ..........#.\n");
+        assert_eq!(picture(&gfx, 0, 0), (".".repeat(12) + "\n").repeat(6));
+    }
+
+    #[test]
+    fn gfx_draw_count_rows() {
+        let mut gfx = GfxMemory::new();
+        gfx.draw(0, 0, &BOX, true, true);
+        // Rows 0 and 2 collide; row 1 does not.
+        assert_eq!(gfx.draw(0, 0, &[0x80, 0x40, 0x80], true, true), 2);
+        // One row is cut off at the bottom, nothing collides.
+        assert_eq!(gfx.draw(8, 30, &BOX, true, true), 1);
+        assert_eq!(gfx.draw(8, 30, &BOX, true, false), 1);
+        assert_eq!(gfx.draw(16, 30, &BOX, true, false), 0);
+    }
+
     #[test]
     fn gfx_clear() {
         let mut gfx_memory = GfxMemory::new();
@@ -45,6 +217,15 @@ mod tests {
         assert!(gfx_memory.get().iter().all(|&x| x == 0));
     }
 
+    #[test]
+    fn gfx_copy_from() {
+        let mut source = GfxMemory::new();
+        source[3] = 0x01;
+        let mut gfx_memory = GfxMemory::new();
+        gfx_memory.copy_from(&source);
+        assert_eq!(gfx_memory[3], 0x01);
+    }
+
     #[test]
     fn gfx_get_set_pixel() {
         let mut gfx_memory = GfxMemory::new();
@@ -0,0 +1,53 @@
+use gfx::GfxMemory;
+use input::InputQueue;
+use error::Chip8Error;
+use frontend::Frontend;
+
+/// Frontend without video, audio or input, optionally stopping after a number of frames.
+pub struct Headless {
+    frames: u64,
+    max_frames: Option<u64>,
+}
+
+impl Headless {
+    pub fn new(max_frames: Option<u64>) -> Headless {
+        Headless {
+            frames: 0,
+            max_frames,
+        }
+    }
+}
+
+impl Frontend for Headless {
+    fn present(&mut self, _: &GfxMemory) -> Result<(), Chip8Error> {
+        self.frames += 1;
+        Ok(())
+    }
+
+    fn poll_input(&mut self, _: &mut InputQueue) {}
+
+    fn play_audio(&mut self, _: bool) {}
+
+    fn quit_requested(&self) -> bool {
+        match self.max_frames {
+            Some(max_frames) => self.frames >= max_frames,
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn headless_quit_after_frames() {
+        let gfx = GfxMemory::new();
+        let mut headless = Headless::new(Some(2));
+        assert!(!headless.quit_requested());
+        headless.present(&gfx).unwrap();
+        assert!(!headless.quit_requested());
+        headless.present(&gfx).unwrap();
+        assert!(headless.quit_requested());
+    }
+}
@@ -0,0 +1,350 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use gfx::GfxMemory;
+use keyboard::{self, Keyboard};
+use error::Chip8Error;
+use frontend::Frontend;
+
+/// A CHIP-8 key going down or up, stamped with the frame it belongs to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KeyEvent {
+    pub frame: u64,
+    pub key: u8,
+    pub pressed: bool,
+}
+
+/// Key events collected by a frontend and applied to the keyboard at frame boundaries.
+pub struct InputQueue {
+    frame: u64,
+    pending: VecDeque<KeyEvent>,
+}
+
+impl InputQueue {
+    pub fn new() -> InputQueue {
+        InputQueue {
+            frame: 0,
+            pending: VecDeque::new(),
+        }
+    }
+
+    /// Returns the number of the frame currently collecting events.
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+
+    /// Queues a key change for the current frame.
+    pub fn push(&mut self, key: u8, pressed: bool) {
+        let frame = self.frame;
+        self.push_event(KeyEvent {
+                            frame,
+                            key,
+                            pressed,
+                        });
+    }
+
+    /// Queues an event for the frame it is stamped with. Events of keys beyond the 16 keys
+    /// of the keypad are ignored.
+    pub fn push_event(&mut self, event: KeyEvent) {
+        if (event.key as usize) < keyboard::NUM_KEYS {
+            self.pending.push_back(event);
+        }
+    }
+
+    /// Applies the events due in the current frame to `keys` and starts the next frame.
+    ///
+    /// An event undoing a change of the same key in the same frame is postponed to the next
+    /// frame, together with the later events of that key, so every press and every release is
+    /// visible for at least one frame. Returns the changes made to `keys`.
+    pub fn apply(&mut self, keys: &mut Keyboard) -> Vec<KeyEvent> {
+        let mut applied = Vec::new();
+        let mut deferred = VecDeque::new();
+        let mut changed = [false; keyboard::NUM_KEYS];
+        let mut postponed = [false; keyboard::NUM_KEYS];
+
+        while let Some(mut event) = self.pending.pop_front() {
+            let key = event.key as usize;
+            if event.frame > self.frame {
+                deferred.push_back(event);
+                continue;
+            }
+            let change = (keys[key] != 0) != event.pressed;
+            if postponed[key] || (change && changed[key]) {
+                postponed[key] = true;
+                event.frame = self.frame + 1;
+                deferred.push_back(event);
+                continue;
+            }
+
+            if change {
+                changed[key] = true;
+                keys[key] = event.pressed as u8;
+                event.frame = self.frame;
+                applied.push(event);
+            }
+        }
+
+        self.pending = deferred;
+        self.frame += 1;
+        applied
+    }
+}
+
+impl Default for InputQueue {
+    fn default() -> InputQueue {
+        InputQueue::new()
+    }
+}
+
+/// Key changes of a session, as returned by `InputQueue::apply`.
+///
+/// The text form holds one event per line: the frame, the key in hex and `down` or `up`.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Recording {
+    pub events: Vec<KeyEvent>,
+}
+
+impl Recording {
+    pub fn new() -> Recording {
+        Recording { events: Vec::new() }
+    }
+
+    pub fn parse(text: &str) -> Result<Recording, Chip8Error> {
+        let mut recording = Recording::new();
+        for (number, line) in text.lines().enumerate() {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.is_empty() {
+                continue;
+            }
+
+            let event = match fields.as_slice() {
+                [frame, key, state] => {
+                    let frame = frame.parse().ok();
+                    let key = u8::from_str_radix(key, 16)
+                        .ok()
+                        .filter(|&key| (key as usize) < keyboard::NUM_KEYS);
+                    let pressed = match *state {
+                        "down" => Some(true),
+                        "up" => Some(false),
+                        _ => None,
+                    };
+                    match (frame, key, pressed) {
+                        (Some(frame), Some(key), Some(pressed)) => {
+                            Some(KeyEvent {
+                                     frame,
+                                     key,
+                                     pressed,
+                                 })
+                        }
+                        _ => None,
+                    }
+                }
+                _ => None,
+            };
+            match event {
+                Some(event) => recording.events.push(event),
+                None => {
+                    return Err(Chip8Error::Message(format!("Invalid input event on line {}",
+                                                           number + 1)))
+                }
+            }
+        }
+        Ok(recording)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Recording, Chip8Error> {
+        let text = fs::read_to_string(path)?;
+        Recording::parse(&text)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Chip8Error> {
+        fs::write(path, self.to_string())?;
+        Ok(())
+    }
+}
+
+impl fmt::Display for Recording {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for event in &self.events {
+            writeln!(f,
+                     "{} {:X} {}",
+                     event.frame,
+                     event.key,
+                     if event.pressed { "down" } else { "up" })?;
+        }
+        Ok(())
+    }
+}
+
+/// Frontend feeding a recording instead of the input of the wrapped frontend.
+///
+/// The wrapped frontend is still polled so it can handle requests to quit.
+pub struct Replay {
+    frontend: Box<dyn Frontend>,
+    events: VecDeque<KeyEvent>,
+}
+
+impl Replay {
+    pub fn new(frontend: Box<dyn Frontend>, recording: Recording) -> Replay {
+        Replay {
+            frontend,
+            events: recording.events.into_iter().collect(),
+        }
+    }
+}
+
+impl Frontend for Replay {
+    fn present(&mut self, gfx: &GfxMemory) -> Result<(), Chip8Error> {
+        self.frontend.present(gfx)
+    }
+
+    fn poll_input(&mut self, input: &mut InputQueue) {
+        self.frontend.poll_input(&mut InputQueue::new());
+        while self.events.front().is_some_and(|event| event.frame <= input.frame()) {
+            let event = self.events.pop_front().unwrap();
+            input.push_event(event);
+        }
+    }
+
+    fn play_audio(&mut self, playing: bool) {
+        self.frontend.play_audio(playing)
+    }
+
+    fn wait_for_key(&mut self, waiting: bool) {
+        self.frontend.wait_for_key(waiting)
+    }
+
+    fn quit_requested(&self) -> bool {
+        self.frontend.quit_requested()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use headless::Headless;
+
+    #[test]
+    fn input_applied_at_frame_boundary() {
+        let mut keys = Keyboard::new();
+        let mut input = InputQueue::new();
+        input.push(0x5, true);
+        assert_eq!(keys[0x5], 0);
+
+        let applied = input.apply(&mut keys);
+        assert_eq!(keys[0x5], 1);
+        assert_eq!(applied,
+                   vec![KeyEvent {
+                            frame: 0,
+                            key: 0x5,
+                            pressed: true,
+                        }]);
+        assert_eq!(input.frame(), 1);
+    }
+
+    #[test]
+    fn input_short_tap_visible_for_a_frame() {
+        let mut keys = Keyboard::new();
+        let mut input = InputQueue::new();
+        input.push(0xA, true);
+        input.push(0xA, false);
+
+        input.apply(&mut keys);
+        assert_eq!(keys[0xA], 1);
+        let applied = input.apply(&mut keys);
+        assert_eq!(keys[0xA], 0);
+        assert_eq!(applied.len(), 1);
+    }
+
+    #[test]
+    fn input_double_tap_not_merged() {
+        let mut keys = Keyboard::new();
+        let mut input = InputQueue::new();
+        input.push(0xA, true);
+        input.push(0xA, false);
+        input.push(0xA, true);
+        input.push(0xA, false);
+
+        let mut states = Vec::new();
+        for _ in 0..5 {
+            input.apply(&mut keys);
+            states.push(keys[0xA]);
+        }
+        assert_eq!(states, vec![1, 0, 1, 0, 0]);
+    }
+
+    #[test]
+    fn input_release_then_press_visible_for_a_frame() {
+        let mut keys = Keyboard::new();
+        keys[0x3] = 1;
+        let mut input = InputQueue::new();
+        input.push(0x3, false);
+        input.push(0x3, true);
+
+        input.apply(&mut keys);
+        assert_eq!(keys[0x3], 0);
+        input.apply(&mut keys);
+        assert_eq!(keys[0x3], 1);
+    }
+
+    #[test]
+    fn input_invalid_key_ignored() {
+        let mut keys = Keyboard::new();
+        let mut input = InputQueue::new();
+        input.push(0x10, true);
+        input.push_event(KeyEvent {
+                             frame: 0,
+                             key: 0xFF,
+                             pressed: true,
+                         });
+        assert!(input.apply(&mut keys).is_empty());
+    }
+
+    #[test]
+    fn input_unchanged_keys_not_reported() {
+        let mut keys = Keyboard::new();
+        let mut input = InputQueue::new();
+        input.push(0x1, false);
+        assert!(input.apply(&mut keys).is_empty());
+    }
+
+    #[test]
+    fn recording_round_trip() {
+        let text = "3 A down\n5 A up\n";
+        let recording = Recording::parse(text).unwrap();
+        assert_eq!(recording.events.len(), 2);
+        assert_eq!(recording.events[0],
+                   KeyEvent {
+                       frame: 3,
+                       key: 0xA,
+                       pressed: true,
+                   });
+        assert_eq!(recording.to_string(), text);
+    }
+
+    #[test]
+    fn recording_invalid() {
+        assert!(Recording::parse("3 G down").is_err());
+        assert!(Recording::parse("3 A pressed").is_err());
+        assert!(Recording::parse("A down").is_err());
+    }
+
+    #[test]
+    fn replay_feeds_events_at_their_frame() {
+        let recording = Recording::parse("1 7 down\n").unwrap();
+        let mut replay = Replay::new(Box::new(Headless::new(None)), recording);
+        let mut keys = Keyboard::new();
+        let mut input = InputQueue::new();
+
+        replay.poll_input(&mut input);
+        input.apply(&mut keys);
+        assert_eq!(keys[0x7], 0);
+
+        replay.poll_input(&mut input);
+        input.apply(&mut keys);
+        assert_eq!(keys[0x7], 1);
+    }
+}
@@ -0,0 +1,463 @@
+use std::fmt;
+
+use opcode::Opcode;
+
+/// Strongly-typed CHIP-8, SCHIP and XO-CHIP instruction.
+///
+/// Register operands are register numbers (0x0 - 0xF), not register values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Instruction {
+    /// 0NNN: call machine code routine
+    Sys(u16),
+    /// 00E0
+    Cls,
+    /// 00EE
+    Ret,
+    /// 1NNN
+    Jp(u16),
+    /// 2NNN
+    Call(u16),
+    /// 3XNN
+    SeByte(u8, u8),
+    /// 4XNN
+    SneByte(u8, u8),
+    /// 5XY0
+    SeReg(u8, u8),
+    /// 6XNN
+    LdByte(u8, u8),
+    /// 7XNN
+    AddByte(u8, u8),
+    /// 8XY0
+    LdReg(u8, u8),
+    /// 8XY1
+    Or(u8, u8),
+    /// 8XY2
+    And(u8, u8),
+    /// 8XY3
+    Xor(u8, u8),
+    /// 8XY4
+    AddReg(u8, u8),
+    /// 8XY5
+    Sub(u8, u8),
+    /// 8XY6
+    Shr(u8, u8),
+    /// 8XY7
+    Subn(u8, u8),
+    /// 8XYE
+    Shl(u8, u8),
+    /// 9XY0
+    SneReg(u8, u8),
+    /// ANNN
+    LdI(u16),
+    /// BNNN
+    JpV0(u16),
+    /// CXNN
+    Rnd(u8, u8),
+    /// DXYN
+    Drw { x: u8, y: u8, n: u8 },
+    /// EX9E
+    Skp(u8),
+    /// EXA1
+    Sknp(u8),
+    /// FX07
+    LdVxDt(u8),
+    /// FX0A
+    LdVxK(u8),
+    /// FX15
+    LdDt(u8),
+    /// FX18
+    LdSt(u8),
+    /// FX1E
+    AddI(u8),
+    /// FX29
+    LdF(u8),
+    /// FX33
+    LdB(u8),
+    /// FX55
+    LdIVx(u8),
+    /// FX65
+    LdVxI(u8),
+
+    /// SCHIP 00CN
+    ScrollDown(u8),
+    /// SCHIP 00FB
+    ScrollRight,
+    /// SCHIP 00FC
+    ScrollLeft,
+    /// SCHIP 00FD
+    Exit,
+    /// SCHIP 00FE
+    Low,
+    /// SCHIP 00FF
+    High,
+    /// SCHIP FX30
+    LdHf(u8),
+    /// SCHIP FX75
+    LdRVx(u8),
+    /// SCHIP FX85
+    LdVxR(u8),
+
+    /// XO-CHIP 00DN
+    ScrollUp(u8),
+    /// XO-CHIP 5XY2
+    SaveRange(u8, u8),
+    /// XO-CHIP 5XY3
+    LoadRange(u8, u8),
+    /// XO-CHIP F000 NNNN: the address is stored in the word following the instruction
+    LdILong,
+    /// XO-CHIP FN01
+    Plane(u8),
+    /// XO-CHIP F002
+    Audio,
+    /// XO-CHIP FX3A
+    Pitch(u8),
+
+    /// Any word that is not a known instruction.
+    Unknown(u16),
+}
+
+/// Interpreter family an instruction was introduced with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Platform {
+    Chip8,
+    Schip,
+    XoChip,
+}
+
+impl fmt::Display for Platform {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match *self {
+                        Platform::Chip8 => "CHIP-8",
+                        Platform::Schip => "SCHIP",
+                        Platform::XoChip => "XO-CHIP",
+                    })
+    }
+}
+
+/// Decodes a single instruction word.
+pub fn decode(word: u16) -> Instruction {
+    use self::Instruction::*;
+
+    let opcode = Opcode::new(word);
+    let x = opcode.x as u8;
+    let y = opcode.y as u8;
+    match opcode.category {
+        0 => {
+            match word {
+                0x00E0 => Cls,
+                0x00EE => Ret,
+                0x00FB => ScrollRight,
+                0x00FC => ScrollLeft,
+                0x00FD => Exit,
+                0x00FE => Low,
+                0x00FF => High,
+                _ if word & 0xFFF0 == 0x00C0 => ScrollDown(opcode.nibble),
+                _ if word & 0xFFF0 == 0x00D0 => ScrollUp(opcode.nibble),
+                _ => Sys(opcode.address),
+            }
+        }
+        1 => Jp(opcode.address),
+        2 => Call(opcode.address),
+        3 => SeByte(x, opcode.byte),
+        4 => SneByte(x, opcode.byte),
+        5 => {
+            match opcode.nibble {
+                0 => SeReg(x, y),
+                2 => SaveRange(x, y),
+                3 => LoadRange(x, y),
+                _ => Unknown(word),
+            }
+        }
+        6 => LdByte(x, opcode.byte),
+        7 => AddByte(x, opcode.byte),
+        8 => {
+            match opcode.nibble {
+                0 => LdReg(x, y),
+                1 => Or(x, y),
+                2 => And(x, y),
+                3 => Xor(x, y),
+                4 => AddReg(x, y),
+                5 => Sub(x, y),
+                6 => Shr(x, y),
+                7 => Subn(x, y),
+                0xE => Shl(x, y),
+                _ => Unknown(word),
+            }
+        }
+        9 if opcode.nibble == 0 => SneReg(x, y),
+        0xA => LdI(opcode.address),
+        0xB => JpV0(opcode.address),
+        0xC => Rnd(x, opcode.byte),
+        0xD => {
+            Drw {
+                x,
+                y,
+                n: opcode.nibble,
+            }
+        }
+        0xE => {
+            match opcode.byte {
+                0x9E => Skp(x),
+                0xA1 => Sknp(x),
+                _ => Unknown(word),
+            }
+        }
+        0xF => {
+            match opcode.byte {
+                0x00 if x == 0 => LdILong,
+                0x01 => Plane(x),
+                0x02 if x == 0 => Audio,
+                0x07 => LdVxDt(x),
+                0x0A => LdVxK(x),
+                0x15 => LdDt(x),
+                0x18 => LdSt(x),
+                0x1E => AddI(x),
+                0x29 => LdF(x),
+                0x30 => LdHf(x),
+                0x33 => LdB(x),
+                0x3A => Pitch(x),
+                0x55 => LdIVx(x),
+                0x65 => LdVxI(x),
+                0x75 => LdRVx(x),
+                0x85 => LdVxR(x),
+                _ => Unknown(word),
+            }
+        }
+        _ => Unknown(word),
+    }
+}
+
+fn xnn(category: u16, x: u8, byte: u8) -> u16 {
+    (category << 12) | ((x as u16 & 0xF) << 8) | byte as u16
+}
+
+fn xyn(category: u16, x: u8, y: u8, nibble: u8) -> u16 {
+    (category << 12) | ((x as u16 & 0xF) << 8) | ((y as u16 & 0xF) << 4) | (nibble as u16 & 0xF)
+}
+
+impl Instruction {
+    /// Encodes the instruction back into its instruction word.
+    pub fn encode(&self) -> u16 {
+        use self::Instruction::*;
+
+        match *self {
+            Sys(address) => address & 0x0FFF,
+            Cls => 0x00E0,
+            Ret => 0x00EE,
+            Jp(address) => 0x1000 | (address & 0x0FFF),
+            Call(address) => 0x2000 | (address & 0x0FFF),
+            SeByte(x, byte) => xnn(0x3, x, byte),
+            SneByte(x, byte) => xnn(0x4, x, byte),
+            SeReg(x, y) => xyn(0x5, x, y, 0x0),
+            LdByte(x, byte) => xnn(0x6, x, byte),
+            AddByte(x, byte) => xnn(0x7, x, byte),
+            LdReg(x, y) => xyn(0x8, x, y, 0x0),
+            Or(x, y) => xyn(0x8, x, y, 0x1),
+            And(x, y) => xyn(0x8, x, y, 0x2),
+            Xor(x, y) => xyn(0x8, x, y, 0x3),
+            AddReg(x, y) => xyn(0x8, x, y, 0x4),
+            Sub(x, y) => xyn(0x8, x, y, 0x5),
+            Shr(x, y) => xyn(0x8, x, y, 0x6),
+            Subn(x, y) => xyn(0x8, x, y, 0x7),
+            Shl(x, y) => xyn(0x8, x, y, 0xE),
+            SneReg(x, y) => xyn(0x9, x, y, 0x0),
+            LdI(address) => 0xA000 | (address & 0x0FFF),
+            JpV0(address) => 0xB000 | (address & 0x0FFF),
+            Rnd(x, byte) => xnn(0xC, x, byte),
+            Drw { x, y, n } => xyn(0xD, x, y, n),
+            Skp(x) => xnn(0xE, x, 0x9E),
+            Sknp(x) => xnn(0xE, x, 0xA1),
+            LdVxDt(x) => xnn(0xF, x, 0x07),
+            LdVxK(x) => xnn(0xF, x, 0x0A),
+            LdDt(x) => xnn(0xF, x, 0x15),
+            LdSt(x) => xnn(0xF, x, 0x18),
+            AddI(x) => xnn(0xF, x, 0x1E),
+            LdF(x) => xnn(0xF, x, 0x29),
+            LdB(x) => xnn(0xF, x, 0x33),
+            LdIVx(x) => xnn(0xF, x, 0x55),
+            LdVxI(x) => xnn(0xF, x, 0x65),
+            ScrollDown(n) => 0x00C0 | (n as u16 & 0xF),
+            ScrollRight => 0x00FB,
+            ScrollLeft => 0x00FC,
+            Exit => 0x00FD,
+            Low => 0x00FE,
+            High => 0x00FF,
+            LdHf(x) => xnn(0xF, x, 0x30),
+            LdRVx(x) => xnn(0xF, x, 0x75),
+            LdVxR(x) => xnn(0xF, x, 0x85),
+            ScrollUp(n) => 0x00D0 | (n as u16 & 0xF),
+            SaveRange(x, y) => xyn(0x5, x, y, 0x2),
+            LoadRange(x, y) => xyn(0x5, x, y, 0x3),
+            LdILong => 0xF000,
+            Plane(n) => xnn(0xF, n, 0x01),
+            Audio => 0xF002,
+            Pitch(x) => xnn(0xF, x, 0x3A),
+            Unknown(word) => word,
+        }
+    }
+
+    /// Returns the instruction word as big-endian bytes.
+    pub fn to_bytes(&self) -> [u8; 2] {
+        let word = self.encode();
+        [(word >> 8) as u8, word as u8]
+    }
+
+    /// Returns the interpreter family that introduced the instruction.
+    pub fn platform(&self) -> Platform {
+        use self::Instruction::*;
+
+        match *self {
+            ScrollDown(_) | ScrollRight | ScrollLeft | Exit | Low | High | LdHf(_) |
+            LdRVx(_) | LdVxR(_) => Platform::Schip,
+            ScrollUp(_) | SaveRange(..) | LoadRange(..) | LdILong | Plane(_) | Audio |
+            Pitch(_) => Platform::XoChip,
+            _ => Platform::Chip8,
+        }
+    }
+
+    /// Returns the number of bytes the instruction occupies, including trailing operands.
+    pub fn size(&self) -> usize {
+        match *self {
+            Instruction::LdILong => 4,
+            _ => 2,
+        }
+    }
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use self::Instruction::*;
+
+        match *self {
+            Sys(address) => write!(f, "SYS 0x{:03X}", address),
+            Cls => write!(f, "CLS"),
+            Ret => write!(f, "RET"),
+            Jp(address) => write!(f, "JP 0x{:03X}", address),
+            Call(address) => write!(f, "CALL 0x{:03X}", address),
+            SeByte(x, byte) => write!(f, "SE V{:X}, 0x{:02X}", x, byte),
+            SneByte(x, byte) => write!(f, "SNE V{:X}, 0x{:02X}", x, byte),
+            SeReg(x, y) => write!(f, "SE V{:X}, V{:X}", x, y),
+            LdByte(x, byte) => write!(f, "LD V{:X}, 0x{:02X}", x, byte),
+            AddByte(x, byte) => write!(f, "ADD V{:X}, 0x{:02X}", x, byte),
+            LdReg(x, y) => write!(f, "LD V{:X}, V{:X}", x, y),
+            Or(x, y) => write!(f, "OR V{:X}, V{:X}", x, y),
+            And(x, y) => write!(f, "AND V{:X}, V{:X}", x, y),
+            Xor(x, y) => write!(f, "XOR V{:X}, V{:X}", x, y),
+            AddReg(x, y) => write!(f, "ADD V{:X}, V{:X}", x, y),
+            Sub(x, y) => write!(f, "SUB V{:X}, V{:X}", x, y),
+            Shr(x, y) => write!(f, "SHR V{:X}, V{:X}", x, y),
+            Subn(x, y) => write!(f, "SUBN V{:X}, V{:X}", x, y),
+            Shl(x, y) => write!(f, "SHL V{:X}, V{:X}", x, y),
+            SneReg(x, y) => write!(f, "SNE V{:X}, V{:X}", x, y),
+            LdI(address) => write!(f, "LD I, 0x{:03X}", address),
+            JpV0(address) => write!(f, "JP V0, 0x{:03X}", address),
+            Rnd(x, byte) => write!(f, "RND V{:X}, 0x{:02X}", x, byte),
+            Drw { x, y, n } => write!(f, "DRW V{:X}, V{:X}, {}", x, y, n),
+            Skp(x) => write!(f, "SKP V{:X}", x),
+            Sknp(x) => write!(f, "SKNP V{:X}", x),
+            LdVxDt(x) => write!(f, "LD V{:X}, DT", x),
+            LdVxK(x) => write!(f, "LD V{:X}, K", x),
+            LdDt(x) => write!(f, "LD DT, V{:X}", x),
+            LdSt(x) => write!(f, "LD ST, V{:X}", x),
+            AddI(x) => write!(f, "ADD I, V{:X}", x),
+            LdF(x) => write!(f, "LD F, V{:X}", x),
+            LdB(x) => write!(f, "LD B, V{:X}", x),
+            LdIVx(x) => write!(f, "LD [I], V{:X}", x),
+            LdVxI(x) => write!(f, "LD V{:X}, [I]", x),
+            ScrollDown(n) => write!(f, "SCD {}", n),
+            ScrollRight => write!(f, "SCR"),
+            ScrollLeft => write!(f, "SCL"),
+            Exit => write!(f, "EXIT"),
+            Low => write!(f, "LOW"),
+            High => write!(f, "HIGH"),
+            LdHf(x) => write!(f, "LD HF, V{:X}", x),
+            LdRVx(x) => write!(f, "LD R, V{:X}", x),
+            LdVxR(x) => write!(f, "LD V{:X}, R", x),
+            ScrollUp(n) => write!(f, "SCU {}", n),
+            SaveRange(x, y) => write!(f, "SAVE V{:X}, V{:X}", x, y),
+            LoadRange(x, y) => write!(f, "LOAD V{:X}, V{:X}", x, y),
+            LdILong => write!(f, "LD I, LONG"),
+            Plane(n) => write!(f, "PLANE {}", n),
+            Audio => write!(f, "AUDIO"),
+            Pitch(x) => write!(f, "PITCH V{:X}", x),
+            Unknown(word) => write!(f, "DW 0x{:04X}", word),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn instruction_platform() {
+        assert_eq!(decode(0x00E0).platform(), Platform::Chip8);
+        assert_eq!(decode(0x00FF).platform(), Platform::Schip);
+        assert_eq!(decode(0xF000).platform(), Platform::XoChip);
+        assert_eq!(decode(0xFFFF).platform(), Platform::Chip8);
+    }
+
+    #[test]
+    fn decode_chip8() {
+        assert_eq!(decode(0x00E0), Instruction::Cls);
+        assert_eq!(decode(0x1234), Instruction::Jp(0x234));
+        assert_eq!(decode(0x8AB4), Instruction::AddReg(0xA, 0xB));
+        assert_eq!(decode(0xD125),
+                   Instruction::Drw {
+                       x: 1,
+                       y: 2,
+                       n: 5,
+                   });
+        assert_eq!(decode(0xF365), Instruction::LdVxI(3));
+    }
+
+    #[test]
+    fn decode_schip() {
+        assert_eq!(decode(0x00C4), Instruction::ScrollDown(4));
+        assert_eq!(decode(0x00FF), Instruction::High);
+        assert_eq!(decode(0xF230), Instruction::LdHf(2));
+        assert_eq!(decode(0xF785), Instruction::LdVxR(7));
+    }
+
+    #[test]
+    fn decode_xochip() {
+        assert_eq!(decode(0x00D2), Instruction::ScrollUp(2));
+        assert_eq!(decode(0x5123), Instruction::LoadRange(1, 2));
+        assert_eq!(decode(0xF000), Instruction::LdILong);
+        assert_eq!(decode(0xF201), Instruction::Plane(2));
+        assert_eq!(decode(0xF002), Instruction::Audio);
+    }
+
+    #[test]
+    fn decode_unknown() {
+        assert_eq!(decode(0x5121), Instruction::Unknown(0x5121));
+        assert_eq!(decode(0xE1FF), Instruction::Unknown(0xE1FF));
+        assert_eq!(decode(0xF1FF), Instruction::Unknown(0xF1FF));
+    }
+
+    #[test]
+    fn encode_round_trip() {
+        for word in 0..0x10000 {
+            let word = word as u16;
+            assert_eq!(decode(word).encode(), word);
+        }
+    }
+
+    #[test]
+    fn instruction_size() {
+        assert_eq!(Instruction::LdILong.size(), 4);
+        assert_eq!(Instruction::Cls.size(), 2);
+    }
+
+    #[test]
+    fn instruction_to_bytes() {
+        assert_eq!(Instruction::Call(0x2FC).to_bytes(), [0x22, 0xFC]);
+    }
+
+    #[test]
+    fn instruction_display() {
+        assert_eq!(Instruction::LdByte(0xA, 0x1F).to_string(), "LD VA, 0x1F");
+        assert_eq!(Instruction::LdIVx(2).to_string(), "LD [I], V2");
+        assert_eq!(Instruction::Unknown(0xFFFF).to_string(), "DW 0xFFFF");
+    }
+}
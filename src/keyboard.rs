@@ -12,6 +12,12 @@ impl Keyboard {
     }
 }
 
+impl Default for Keyboard {
+    fn default() -> Keyboard {
+        Keyboard::new()
+    }
+}
+
 impl Index<usize> for Keyboard {
     type Output = u8;
     fn index(&self, i: usize) -> &u8 {
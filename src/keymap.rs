@@ -0,0 +1,232 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use toml;
+
+use error::Chip8Error;
+use keyboard::NUM_KEYS;
+
+/// Host key identified by its SDL key name, either by the character it produces (keycode)
+/// or by its physical position (scancode).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum HostKey {
+    Keycode(String),
+    Scancode(String),
+}
+
+impl HostKey {
+    pub fn keycode(name: &str) -> HostKey {
+        HostKey::Keycode(name.to_uppercase())
+    }
+
+    pub fn scancode(name: &str) -> HostKey {
+        HostKey::Scancode(name.to_uppercase())
+    }
+}
+
+pub const PRESETS: [&str; 4] = ["qwerty", "qwertz", "azerty", "numpad"];
+
+// Host keys for the CHIP-8 keys 0x0 - 0xF.
+const QWERTY: [&[&str]; NUM_KEYS] = [&["X"], &["1"], &["2"], &["3"], &["Q"], &["W"], &["E"],
+                                     &["A"], &["S"], &["D"], &["Z"], &["C"], &["4"], &["R"],
+                                     &["F"], &["V"]];
+const QWERTZ: [&[&str]; NUM_KEYS] = [&["X"], &["1"], &["2"], &["3"], &["Q"], &["W"], &["E"],
+                                     &["A"], &["S"], &["D"], &["Y"], &["C"], &["4"], &["R"],
+                                     &["F"], &["V"]];
+const AZERTY: [&[&str]; NUM_KEYS] = [&["X"], &["1", "&"], &["2", "É"], &["3", "\""], &["A"],
+                                     &["Z"], &["E"], &["Q"], &["S"], &["D"], &["W"], &["C"],
+                                     &["4", "'"], &["R"], &["F"], &["V"]];
+const NUMPAD: [&[&str]; NUM_KEYS] = [&["Keypad 0"], &["Keypad 1"], &["Keypad 2"],
+                                     &["Keypad 3"], &["Keypad 4"], &["Keypad 5"],
+                                     &["Keypad 6"], &["Keypad 7"], &["Keypad 8"],
+                                     &["Keypad 9"], &["Keypad /"], &["Keypad *"],
+                                     &["Keypad -"], &["Keypad +"], &["Keypad Enter"],
+                                     &["Keypad ."]];
+
+#[derive(Deserialize)]
+struct KeyMapFile {
+    preset: Option<String>,
+    #[serde(default)]
+    scancodes: bool,
+    #[serde(default)]
+    keys: HashMap<String, Vec<String>>,
+}
+
+/// Mapping of host keys onto the 16-key hex keypad. Several host keys may share a CHIP-8 key.
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyMap {
+    bindings: HashMap<HostKey, u8>,
+}
+
+impl KeyMap {
+    pub fn new() -> KeyMap {
+        KeyMap { bindings: HashMap::new() }
+    }
+
+    /// Returns one of the built-in `PRESETS`, bound by keycode or by scancode.
+    pub fn preset(name: &str, scancodes: bool) -> Option<KeyMap> {
+        let layout = match name.to_lowercase().as_str() {
+            "qwerty" => QWERTY,
+            "qwertz" => QWERTZ,
+            "azerty" => AZERTY,
+            "numpad" => NUMPAD,
+            _ => return None,
+        };
+
+        let mut keymap = KeyMap::new();
+        for (chip8_key, names) in layout.iter().enumerate() {
+            for name in names.iter() {
+                keymap.bind(KeyMap::host_key(name, scancodes), chip8_key as u8);
+            }
+        }
+        Some(keymap)
+    }
+
+    /// Parses a TOML key binding file.
+    ///
+    /// ```toml
+    /// preset = "qwerty"   # optional starting point
+    /// scancodes = true    # bind physical key positions
+    ///
+    /// [keys]
+    /// A = ["Z", "Keypad 0"]
+    /// ```
+    pub fn from_toml(text: &str) -> Result<KeyMap, Chip8Error> {
+        let file: KeyMapFile = toml::from_str(text).map_err(|err| err.to_string())?;
+        let mut keymap = match file.preset {
+            Some(ref name) => {
+                KeyMap::preset(name, file.scancodes)
+                    .ok_or_else(|| format!("Unknown key layout: {}", name))?
+            }
+            None => KeyMap::new(),
+        };
+
+        for (chip8_key, names) in &file.keys {
+            let chip8_key = match u8::from_str_radix(chip8_key, 16) {
+                Ok(key) if (key as usize) < NUM_KEYS => key,
+                _ => return Err(Chip8Error::Message(format!("Invalid CHIP-8 key: {}", chip8_key))),
+            };
+            keymap.unbind(chip8_key);
+            for name in names {
+                keymap.bind(KeyMap::host_key(name, file.scancodes), chip8_key);
+            }
+        }
+        Ok(keymap)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<KeyMap, Chip8Error> {
+        let text = fs::read_to_string(path)?;
+        KeyMap::from_toml(&text)
+    }
+
+    /// Resolves a preset name or the path of a key binding file. Presets are bound by keycode,
+    /// since the keys of a layout are named by the characters they produce: the QWERTZ `Y` key
+    /// sits where scancodes, which follow US positions, put `Z`.
+    pub fn resolve(name_or_path: &str) -> Result<KeyMap, Chip8Error> {
+        match KeyMap::preset(name_or_path, false) {
+            Some(keymap) => Ok(keymap),
+            None => KeyMap::load(name_or_path),
+        }
+    }
+
+    pub fn bind(&mut self, key: HostKey, chip8_key: u8) {
+        self.bindings.insert(key, chip8_key);
+    }
+
+    /// Removes all host keys bound to `chip8_key`.
+    pub fn unbind(&mut self, chip8_key: u8) {
+        self.bindings.retain(|_, key| *key != chip8_key);
+    }
+
+    pub fn lookup(&self, key: &HostKey) -> Option<u8> {
+        self.bindings.get(key).cloned()
+    }
+
+    fn host_key(name: &str, scancodes: bool) -> HostKey {
+        if scancodes {
+            HostKey::scancode(name)
+        } else {
+            HostKey::keycode(name)
+        }
+    }
+}
+
+impl Default for KeyMap {
+    /// Binds the left side of the keyboard by position, which matches QWERTY, QWERTZ and
+    /// AZERTY keyboards alike.
+    fn default() -> KeyMap {
+        KeyMap::preset("qwerty", true).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keymap_presets() {
+        for name in PRESETS.iter() {
+            assert!(KeyMap::preset(name, false).is_some());
+        }
+        assert!(KeyMap::preset("dvorak", false).is_none());
+    }
+
+    #[test]
+    fn keymap_qwerty_qwertz() {
+        let qwerty = KeyMap::preset("qwerty", false).unwrap();
+        let qwertz = KeyMap::preset("QWERTZ", false).unwrap();
+        assert_eq!(qwerty.lookup(&HostKey::keycode("z")), Some(0xA));
+        assert_eq!(qwerty.lookup(&HostKey::keycode("y")), None);
+        assert_eq!(qwertz.lookup(&HostKey::keycode("y")), Some(0xA));
+        assert_eq!(qwertz.lookup(&HostKey::keycode("x")), Some(0x0));
+    }
+
+    #[test]
+    fn keymap_multiple_host_keys() {
+        let azerty = KeyMap::preset("azerty", false).unwrap();
+        assert_eq!(azerty.lookup(&HostKey::keycode("1")), Some(0x1));
+        assert_eq!(azerty.lookup(&HostKey::keycode("&")), Some(0x1));
+    }
+
+    #[test]
+    fn keymap_resolve_layouts() {
+        let qwertz = KeyMap::resolve("qwertz").unwrap();
+        assert_eq!(qwertz.lookup(&HostKey::keycode("Y")), Some(0xA));
+        assert_eq!(qwertz.lookup(&HostKey::keycode("Z")), None);
+        assert_eq!(qwertz.lookup(&HostKey::scancode("Y")), None);
+
+        let azerty = KeyMap::resolve("azerty").unwrap();
+        assert_eq!(azerty.lookup(&HostKey::keycode("&")), Some(0x1));
+        assert_eq!(azerty.lookup(&HostKey::keycode("é")), Some(0x2));
+        assert_eq!(azerty.lookup(&HostKey::keycode("\"")), Some(0x3));
+        assert_eq!(azerty.lookup(&HostKey::keycode("'")), Some(0xC));
+        assert_eq!(azerty.lookup(&HostKey::keycode("A")), Some(0x4));
+        assert_eq!(azerty.lookup(&HostKey::keycode("W")), Some(0xA));
+        assert_eq!(azerty.lookup(&HostKey::scancode("Q")), None);
+    }
+
+    #[test]
+    fn keymap_scancodes() {
+        let keymap = KeyMap::default();
+        assert_eq!(keymap.lookup(&HostKey::scancode("Z")), Some(0xA));
+        assert_eq!(keymap.lookup(&HostKey::keycode("Z")), None);
+    }
+
+    #[test]
+    fn keymap_from_toml() {
+        let text = "preset = \"qwerty\"\n[keys]\nA = [\"Space\", \"Keypad 0\"]\n";
+        let keymap = KeyMap::from_toml(text).unwrap();
+        assert_eq!(keymap.lookup(&HostKey::keycode("space")), Some(0xA));
+        assert_eq!(keymap.lookup(&HostKey::keycode("Keypad 0")), Some(0xA));
+        assert_eq!(keymap.lookup(&HostKey::keycode("Z")), None);
+        assert_eq!(keymap.lookup(&HostKey::keycode("Q")), Some(0x4));
+    }
+
+    #[test]
+    fn keymap_from_toml_errors() {
+        assert!(KeyMap::from_toml("preset = \"dvorak\"").is_err());
+        assert!(KeyMap::from_toml("[keys]\nG = [\"G\"]").is_err());
+        assert!(KeyMap::from_toml("keys = 1").is_err());
+    }
+}
@@ -0,0 +1,111 @@
+use error::Chip8Error;
+use font;
+use memory::MEMORY_SIZE;
+
+/// Memory layout of an interpreter.
+///
+/// Memory below `program_start` belongs to the interpreter and holds the font. Machines that
+/// keep their stack and display buffer at the top of memory also reserve everything from
+/// `program_end` on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Layout {
+    /// Address programs are loaded at and started from.
+    pub program_start: usize,
+    /// First address after the memory available to programs.
+    pub program_end: usize,
+    /// Address of the hexadecimal font read by `FX29`.
+    pub font_address: usize,
+}
+
+pub const PRESETS: [&str; 3] = ["vip", "eti660", "modern"];
+
+impl Layout {
+    /// COSMAC VIP: the interpreter takes the first 512 bytes, its variables, stack and display
+    /// buffer the last 352.
+    pub fn vip() -> Layout {
+        Layout {
+            program_start: 0x200,
+            program_end: 0xEA0,
+            font_address: 0x000,
+        }
+    }
+
+    /// ETI-660: programs start at 0x600.
+    pub fn eti660() -> Layout {
+        Layout {
+            program_start: 0x600,
+            program_end: MEMORY_SIZE,
+            font_address: 0x000,
+        }
+    }
+
+    /// Returns one of the `PRESETS`. `modern` is the default layout of this emulator.
+    pub fn preset(name: &str) -> Option<Layout> {
+        match name.to_lowercase().as_str() {
+            "vip" => Some(Layout::vip()),
+            "eti660" | "eti-660" => Some(Layout::eti660()),
+            "modern" => Some(Layout::default()),
+            _ => None,
+        }
+    }
+
+    /// Returns the number of bytes a program loaded at `start` may occupy.
+    pub fn capacity(&self, start: usize) -> usize {
+        self.program_end.saturating_sub(start)
+    }
+}
+
+impl Default for Layout {
+    fn default() -> Layout {
+        Layout {
+            program_start: 0x200,
+            program_end: MEMORY_SIZE,
+            font_address: 0x000,
+        }
+    }
+}
+
+/// Resolves a preset name and an optional font address overriding that of the preset.
+pub fn resolve(name: Option<&str>, font_address: Option<usize>) -> Result<Layout, Chip8Error> {
+    let mut layout = match name {
+        Some(name) => {
+            Layout::preset(name).ok_or_else(|| {
+                Chip8Error::Message(format!("Unknown memory layout: {}", name))
+            })?
+        }
+        None => Layout::default(),
+    };
+    if let Some(address) = font_address {
+        if address + font::MAX_SIZE > MEMORY_SIZE {
+            return Err(Chip8Error::Message(format!("Font address 0x{:X} is out of memory",
+                                                   address)));
+        }
+        layout.font_address = address;
+    }
+    Ok(layout)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn layout_presets() {
+        for name in PRESETS.iter() {
+            assert!(Layout::preset(name).is_some());
+        }
+        assert_eq!(Layout::preset("ETI-660").unwrap().program_start, 0x600);
+        assert_eq!(Layout::vip().capacity(0x200), 0xCA0);
+        assert_eq!(Layout::default().capacity(0x1000), 0);
+    }
+
+    #[test]
+    fn layout_resolve() {
+        let layout = resolve(Some("eti660"), Some(0x50)).unwrap();
+        assert_eq!(layout.program_start, 0x600);
+        assert_eq!(layout.font_address, 0x50);
+        assert_eq!(resolve(None, None).unwrap(), Layout::default());
+        assert!(resolve(Some("c64"), None).is_err());
+        assert!(resolve(None, Some(0xFC0)).is_err());
+    }
+}
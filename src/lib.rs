@@ -0,0 +1,56 @@
+#![cfg_attr(feature="clippy", feature(plugin))]
+#![cfg_attr(feature="clippy", plugin(clippy))]
+
+extern crate rand;
+extern crate sdl2;
+extern crate timer;
+extern crate chrono;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate toml;
+extern crate sha1;
+extern crate serde_json;
+extern crate gif;
+#[cfg(test)]
+extern crate proptest;
+
+pub mod opcode;
+pub mod instruction;
+pub mod disasm;
+pub mod asm;
+pub mod octo;
+pub mod chip8;
+pub mod quirks;
+pub mod stack;
+pub mod error;
+pub mod gfx;
+pub mod register;
+pub mod keyboard;
+pub mod input;
+pub mod keymap;
+pub mod config;
+pub mod database;
+pub mod cartridge;
+pub mod palette;
+pub mod padmap;
+pub mod memory;
+pub mod layout;
+pub mod font;
+pub mod timing;
+pub mod cdp1802;
+pub mod vip;
+pub mod cache;
+pub mod frame;
+pub mod frontend;
+pub mod headless;
+pub mod terminal;
+pub mod snapshot;
+pub mod bench;
+pub mod profile;
+pub mod coverage;
+#[cfg(not(test))]
+pub mod renderer;
+
+pub const DISPLAY_HEIGHT: usize = 32;
+pub const DISPLAY_WIDTH: usize = 64;
@@ -1,58 +1,454 @@
 #![cfg_attr(feature="clippy", feature(plugin))]
 #![cfg_attr(feature="clippy", plugin(clippy))]
 
-extern crate rand;
-extern crate sdl2;
-extern crate timer;
-extern crate chrono;
-
-mod opcode;
-mod chip8;
-mod stack;
-mod error;
-mod gfx;
-mod register;
-mod keyboard;
-mod memory;
-#[cfg(not(test))]
-mod renderer;
-
-pub const DISPLAY_HEIGHT: usize = 32;
-pub const DISPLAY_WIDTH: usize = 64;
-
-#[cfg(not(test))]
+extern crate chip8;
+extern crate toml;
+
+mod cli;
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::process;
+use std::sync::Arc;
+use std::time::Instant;
+
+use chip8::asm;
+use chip8::bench;
+use chip8::cartridge::Cartridge;
+use chip8::chip8::{Chip8, ADDRESS_MASK};
+use chip8::config::{self, Config, Settings};
+use chip8::database::{Database, RomInfo};
+use chip8::disasm;
+use chip8::error::Chip8Error;
+use chip8::frontend::{self, Frontend};
+use chip8::headless::Headless;
+use chip8::input::{Recording, Replay};
+use chip8::instruction::{Instruction, Platform};
+use chip8::layout;
+use chip8::padmap::PadMap;
+use chip8::terminal;
+use chip8::vip::Vip;
+
+use cli::Args;
+
+const EXIT_ERROR: i32 = 1;
+const EXIT_USAGE: i32 = 2;
+const TEST_FRAMES: u64 = 300;
+const BENCH_FRAMES: u64 = 1000;
+const PROFILE_FRAMES: u64 = 1000;
+// Window pixels per CHIP-8 pixel.
+const MAX_SCALE: usize = 64;
+
 fn main() {
     use std::env;
 
-    if let Some(rom_file_name) = env::args().nth(1) {
-        println!("Reading ROM: {}...", rom_file_name);
-        let rom = utils::read_binary(rom_file_name).unwrap_or_else(|err| {
-            println!("Could not read ROM: {}!", err);
-            Vec::new()
-        });
-
-        if !rom.is_empty() {
-            println!("Starting CHIP-8 emulator...");
-            let mut chip = chip8::Chip8::new();
-            chip.initialize();
-            chip.load_rom(rom);
-            if let Err(err) = chip.run() {
-                match err {
-                    error::Chip8Error::Message(msg) => {
-                        println!("Error running chip: {}", msg);
-                    }
-                    _ => {
-                        println!("Error running chip: {:?}", err);
-                    }
-                }
-            }
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args = match Args::parse(&args) {
+        Ok(ref args) if args.flag("help") => {
+            print!("{}", cli::USAGE);
+            return;
+        }
+        Ok(args) => args,
+        Err(msg) => usage_error(&msg),
+    };
+
+    let result = match args.command.as_str() {
+        "run" => run(&args),
+        "disasm" => disassemble(&args),
+        "asm" => assemble(&args),
+        "info" => info(&args),
+        "test" => test(&args),
+        "bench" => bench(&args),
+        "profile" => profile(&args),
+        _ => unreachable!(),
+    };
+    if let Err(err) = result {
+        eprintln!("Error: {}", err);
+        process::exit(EXIT_ERROR);
+    }
+}
+
+fn usage_error(msg: &str) -> ! {
+    eprintln!("{}\n\n{}", msg, cli::USAGE);
+    process::exit(EXIT_USAGE);
+}
+
+/// Returns the option value or exits with a usage error.
+fn checked<T>(value: Result<T, String>) -> T {
+    value.unwrap_or_else(|msg| usage_error(&msg))
+}
+
+/// Returns `--start` or the program start of `--layout`.
+fn start_address(args: &Args) -> Result<usize, Chip8Error> {
+    match checked(args.number_within("start", 0, ADDRESS_MASK as u64)) {
+        Some(address) => Ok(address as usize),
+        None => layout::resolve(args.value("layout"), None).map(|layout| layout.program_start),
+    }
+}
+
+/// Merges the configuration file settings for `rom` with the command line options.
+fn settings(args: &Args, rom: &[u8]) -> Result<Settings, Chip8Error> {
+    let config = match args.value("config") {
+        Some(path) => Config::load(path)?,
+        None => Config::load_user()?,
+    };
+    let mut settings = config.settings(&config::rom_hash(rom));
+
+    let mut overrides = Settings {
+        speed: checked(args.number("speed")).map(|speed| speed as usize),
+        timing: args.value("timing").map(String::from),
+        quirks: args.value("quirks").map(String::from),
+        layout: args.value("layout").map(String::from),
+        font_address: checked(args.number("font-address")).map(|address| address as usize),
+        font: args.value("font").map(String::from),
+        palette: args.value("palette").map(String::from),
+        scale: checked(args.number_within("scale", 1, MAX_SCALE as u64))
+            .map(|scale| scale as usize),
+        keys: args.value("keys").map(|keys| toml::Value::String(keys.to_string())),
+        ..Settings::default()
+    };
+    if args.flag("mute") {
+        overrides.audio.muted = Some(true);
+    }
+    settings.merge(&overrides);
+    Ok(settings)
+}
+
+/// Reads a ROM file. Octo cartridges are compiled and described by their options.
+fn read_rom(path: &str) -> Result<(Vec<u8>, Option<RomInfo>), Chip8Error> {
+    let data = utils::read_binary(path)?;
+    if !Cartridge::is_cartridge(&data) {
+        return Ok((data, None));
+    }
+
+    let cartridge = Cartridge::decode(&data)?;
+    let program = cartridge.compile()?;
+    let title = Path::new(path)
+        .file_stem()
+        .map_or(String::new(), |stem| stem.to_string_lossy().into_owned());
+    let info = cartridge.rom_info(&title, &program);
+    Ok((program, Some(info)))
+}
+
+/// Creates an emulator configured by the settings with the ROM loaded.
+fn load_chip(args: &Args) -> Result<(Chip8, Settings), Chip8Error> {
+    let (rom, cartridge) = read_rom(checked(args.path()))?;
+    create_chip(args, rom, cartridge)
+}
+
+/// Creates an emulator configured by the settings for `rom` with `rom` loaded.
+fn create_chip(args: &Args,
+               rom: Vec<u8>,
+               cartridge: Option<RomInfo>)
+               -> Result<(Chip8, Settings), Chip8Error> {
+    let settings = settings(args, &rom)?;
+    let mut chip = Chip8::new();
+    if let Some(address) = checked(args.number_within("start", 0, ADDRESS_MASK as u64)) {
+        chip.set_start_address(address as usize);
+    }
+    if let Some(layout) = settings.layout()? {
+        chip.set_layout(layout);
+    }
+    if let Some(font) = settings.font()? {
+        chip.set_font(font);
+    }
+    if let Some(path) = args.value("database") {
+        chip.set_database(Some(Arc::new(Database::load(path)?)));
+    }
+    if let Some(seed) = checked(args.number("seed")) {
+        chip.set_seed(seed);
+    }
+    if let Some(path) = args.value("trace") {
+        chip.set_trace(Box::new(fs::File::create(path)?));
+    }
+
+    chip.initialize();
+    match cartridge {
+        Some(info) => chip.load_program(rom, Some(info))?,
+        None => chip.load_rom(rom)?,
+    }
+    // The configuration overrides what the ROM database recommends.
+    if let Some(speed) = settings.speed {
+        chip.set_speed(speed);
+    }
+    if let Some(timing) = settings.timing()? {
+        chip.set_timing(timing);
+    }
+    if let Some(quirks) = settings.quirks()? {
+        chip.set_quirks(quirks);
+    }
+    Ok((chip, settings))
+}
+
+fn frontend_options(args: &Args,
+                    rom: &[u8],
+                    settings: &Settings,
+                    rom_info: Option<&RomInfo>)
+                    -> Result<frontend::Options, Chip8Error> {
+    let mut options = frontend::Options::default();
+    if let Some(palette) = rom_info.and_then(|info| info.palette) {
+        options.palette = palette;
+    }
+    if let Some(keymap) = rom_info.and_then(|info| info.keymap.clone()) {
+        options.keymap = keymap;
+    }
+    if let Some(keymap) = settings.keymap()? {
+        options.keymap = keymap;
+    }
+    if let Some(path) = args.value("controller") {
+        options.padmap = PadMap::load(path, Some(&config::rom_hash(rom)))?;
+    }
+    if let Some(scale) = settings.scale {
+        if scale == 0 || scale > MAX_SCALE {
+            let msg = format!("Scale {} is not within 1 and {}", scale, MAX_SCALE);
+            return Err(Chip8Error::Message(msg));
+        }
+        options.scale = scale;
+    }
+    if let Some(palette) = settings.palette()? {
+        options.palette = palette;
+    }
+    options.audio = settings.audio();
+    options.max_frames = checked(args.number("frames"));
+    Ok(options)
+}
+
+fn run(args: &Args) -> Result<(), Chip8Error> {
+    match args.value("machine").unwrap_or("chip8") {
+        "chip8" => {}
+        "vip" => return run_vip(args),
+        name => usage_error(&format!("Unknown machine: {}", name)),
+    }
+
+    let (rom, cartridge) = read_rom(checked(args.path()))?;
+    let (mut chip, settings) = create_chip(args, rom.clone(), cartridge)?;
+    if let Some(info) = chip.rom_info() {
+        println!("{}", describe(info));
+        if !info.keys.is_empty() {
+            let keys: Vec<String> = info.keys
+                .iter()
+                .map(|&(ref action, key)| format!("{} {:X}", action, key))
+                .collect();
+            println!("Keys: {}", keys.join(", "));
         }
+    }
+    let options = frontend_options(args, &rom, &settings, chip.rom_info())?;
+    let mut frontend = create_frontend(args, options)?;
+
+    if args.value("record").is_some() {
+        chip.record();
+    }
+    start_coverage(args, &mut chip);
+    chip.run(&mut *frontend)?;
+    write_coverage(args, &mut chip, &rom)?;
+    match (args.value("record"), chip.take_recording()) {
+        (Some(path), Some(recording)) => recording.save(path),
+        _ => Ok(()),
+    }
+}
+
+/// Runs the ROM on an emulated COSMAC VIP with its monitor and CHIP-8 interpreter.
+fn run_vip(args: &Args) -> Result<(), Chip8Error> {
+    let (monitor, interpreter) = match (args.value("monitor"), args.value("interpreter")) {
+        (Some(monitor), Some(interpreter)) => (monitor, interpreter),
+        _ => usage_error("--machine vip needs --monitor and --interpreter"),
+    };
+    let mut vip = Vip::new(&utils::read_binary(monitor)?, &utils::read_binary(interpreter)?)?;
+    let (rom, _) = read_rom(checked(args.path()))?;
+    vip.load_rom(&rom)?;
+
+    let settings = settings(args, &rom)?;
+    let options = frontend_options(args, &rom, &settings, None)?;
+    let mut frontend = create_frontend(args, options)?;
+    vip.run(&mut *frontend)
+}
+
+/// Creates the frontend selected by `--frontend`, replaying `--replay` if given.
+fn create_frontend(args: &Args,
+                   options: frontend::Options)
+                   -> Result<Box<dyn Frontend>, Chip8Error> {
+    let frontend_name = if args.flag("headless") {
+        "headless"
+    } else {
+        args.value("frontend").unwrap_or("sdl")
+    };
+    let mut frontend = frontend::create(frontend_name, options)?;
+    if let Some(path) = args.value("replay") {
+        let recording = Recording::load(path)?;
+        frontend = Box::new(Replay::new(frontend, recording));
+    }
+    Ok(frontend)
+}
+
+fn describe(info: &RomInfo) -> String {
+    if info.authors.is_empty() {
+        format!("{} ({})", info.title, info.platform)
     } else {
-        println!("Usage: chip8 <rom>");
+        format!("{} by {} ({})",
+                info.title,
+                info.authors.join(", "),
+                info.platform)
+    }
+}
+
+fn disassemble(args: &Args) -> Result<(), Chip8Error> {
+    let (rom, _) = read_rom(checked(args.path()))?;
+    print!("{}", disasm::listing(&disasm::disassemble(&rom, start_address(args)?)));
+    Ok(())
+}
+
+fn assemble(args: &Args) -> Result<(), Chip8Error> {
+    let source_path = checked(args.path());
+    let source = fs::read_to_string(source_path)?;
+    let rom = asm::assemble(&source, start_address(args)?)?;
+    let output = match args.value("output") {
+        Some(path) => path.into(),
+        None => Path::new(source_path).with_extension("ch8"),
+    };
+    fs::write(&output, &rom)?;
+    println!("Wrote {} bytes to {}", rom.len(), output.display());
+    Ok(())
+}
+
+fn info(args: &Args) -> Result<(), Chip8Error> {
+    let (rom, cartridge) = read_rom(checked(args.path()))?;
+    let start = start_address(args)?;
+    let lines = disasm::disassemble(&rom, start);
+    let mut platforms = BTreeMap::new();
+    let mut unknown = 0;
+    for line in &lines {
+        match line.instruction {
+            Instruction::Unknown(_) => unknown += 1,
+            instruction => *platforms.entry(instruction.platform()).or_insert(0) += 1,
+        }
+    }
+    let platform = platforms.keys().last().cloned().unwrap_or(Platform::Chip8);
+
+    println!("Size:         {} bytes", rom.len());
+    println!("SHA-1:        {}", config::rom_hash(&rom));
+    let database = match args.value("database") {
+        Some(path) => Arc::new(Database::load(path)?),
+        None => Database::bundled(),
+    };
+    if let Some(info) = cartridge.or_else(|| database.lookup(&config::rom_hash(&rom))) {
+        println!("Title:        {}", describe(&info));
+        println!("Profile:      {}", info.platform_id);
+        if let Some(tickrate) = info.tickrate {
+            println!("Speed:        {} instructions per frame", tickrate);
+        }
+    } else if database.is_empty() {
+        println!("Title:        unknown, the ROM database is empty (see database/update.sh)");
+    }
+    println!("Address:      0x{:03X} - 0x{:03X}",
+             start,
+             start + rom.len().saturating_sub(1));
+    println!("Platform:     {}", platform);
+    for (platform, count) in &platforms {
+        println!("{:<13} {} instructions", format!("{}:", platform), count);
     }
+    println!("Unknown:      {} words", unknown);
+    Ok(())
+}
+
+/// Returns a headless frontend running `frames` frames, replaying `--replay` if given.
+fn headless(args: &Args, frames: u64) -> Result<Box<dyn Frontend>, Chip8Error> {
+    let mut frontend: Box<dyn Frontend> = Box::new(Headless::new(Some(frames)));
+    if let Some(path) = args.value("replay") {
+        frontend = Box::new(Replay::new(frontend, Recording::load(path)?));
+    }
+    Ok(frontend)
+}
+
+fn test(args: &Args) -> Result<(), Chip8Error> {
+    let (rom, cartridge) = read_rom(checked(args.path()))?;
+    let (mut chip, _) = create_chip(args, rom.clone(), cartridge)?;
+    let frames = checked(args.number("frames")).unwrap_or(TEST_FRAMES);
+    let mut frontend = headless(args, frames)?;
+    start_coverage(args, &mut chip);
+    chip.run(&mut *frontend)?;
+    print!("{}", terminal::render(chip.gfx()));
+    write_coverage(args, &mut chip, &rom)
+}
+
+/// Starts recording the coverage if `--coverage` or `--lcov` is given.
+fn start_coverage(args: &Args, chip: &mut Chip8) {
+    if args.value("coverage").is_some() || args.value("lcov").is_some() {
+        chip.record_coverage();
+    }
+}
+
+/// Writes the coverage of `rom` to `--coverage` and `--lcov` and prints its summary. The
+/// LCOV tracefile refers to the annotated disassembly, or to the ROM without `--coverage`.
+fn write_coverage(args: &Args, chip: &mut Chip8, rom: &[u8]) -> Result<(), Chip8Error> {
+    let coverage = match chip.take_coverage() {
+        Some(coverage) => coverage,
+        None => return Ok(()),
+    };
+    let start = chip.start_address();
+    if let Some(path) = args.value("coverage") {
+        fs::write(path, coverage.annotate(rom, start))?;
+    }
+    if let Some(path) = args.value("lcov") {
+        let source = args.value("coverage").unwrap_or(checked(args.path()));
+        fs::write(path, coverage.lcov(rom, start, source))?;
+    }
+    println!("{}", coverage.summary(rom, start));
+    Ok(())
+}
+
+/// Measures the speed of each ROM, or of the built-in workloads without ROMs.
+fn bench(args: &Args) -> Result<(), Chip8Error> {
+    let frames = checked(args.number("frames")).unwrap_or(BENCH_FRAMES);
+    let mut roms = Vec::new();
+    if args.paths.is_empty() {
+        for workload in bench::WORKLOADS.iter() {
+            roms.push((workload.name.to_string(), workload.rom.to_vec(), None));
+        }
+    } else {
+        for path in &args.paths {
+            let (rom, cartridge) = read_rom(path)?;
+            roms.push((path.clone(), rom, cartridge));
+        }
+    }
+
+    let width = roms.iter().map(|(name, _, _)| name.len()).max().unwrap_or(0);
+    for (name, rom, cartridge) in roms {
+        let (mut chip, _) = create_chip(args, rom, cartridge)?;
+        let mut frontend = headless(args, frames)?;
+        let start = Instant::now();
+        chip.run(&mut *frontend)?;
+        let report = bench::Report {
+            frames,
+            instructions: chip.instructions(),
+            seconds: start.elapsed().as_secs_f64(),
+        };
+        println!("{:<width$}  {}", name, report, width = width);
+    }
+    Ok(())
+}
+
+/// Prints the flat profile and the call graph of the ROM.
+fn profile(args: &Args) -> Result<(), Chip8Error> {
+    let (mut chip, _) = load_chip(args)?;
+    let frames = checked(args.number("frames")).unwrap_or(PROFILE_FRAMES);
+    let mut frontend = headless(args, frames)?;
+    chip.profile();
+    chip.run(&mut *frontend)?;
+    let profile = chip.take_profile().unwrap();
+
+    println!("{} instructions, {} machine cycles in {} frames\n",
+             chip.instructions(),
+             profile.total_cycles(),
+             frames);
+    println!("{}", profile.flat());
+    print!("{}", profile.call_graph());
+    if let Some(path) = args.value("folded") {
+        fs::write(path, profile.folded())?;
+    }
+    Ok(())
 }
 
-#[cfg(not(test))]
 mod utils {
     use std::io;
     use std::path::Path;
@@ -61,9 +457,9 @@ mod utils {
         use std::fs;
         use std::io::Read;
 
-        let mut file = try!(fs::File::open(path));
+        let mut file = fs::File::open(path)?;
         let mut file_buffer = Vec::new();
-        try!(file.read_to_end(&mut file_buffer));
+        file.read_to_end(&mut file_buffer)?;
         Ok(file_buffer)
     }
-}
\ No newline at end of file
+}
@@ -1,5 +1,6 @@
 pub const MEMORY_SIZE: usize = 4096;
 
+/// 4 KB of memory. Addresses wrap around at the end.
 pub struct Memory {
     memory: [u8; MEMORY_SIZE],
 }
@@ -10,21 +11,27 @@ impl Memory {
     }
 
     pub fn load8(&self, index: usize) -> u8 {
-        self.memory[index]
+        self.memory[index % MEMORY_SIZE]
     }
 
     pub fn load16(&self, index: usize) -> u16 {
-        ((self.memory[index] as u16) << 8) + self.memory[index + 1] as u16
+        ((self.load8(index) as u16) << 8) + self.load8(index + 1) as u16
     }
 
     pub fn store(&mut self, start: usize, array: &[u8]) {
-        for (index, element) in array.into_iter().enumerate() {
-            self.memory[start + index] = *element;
+        for (index, element) in array.iter().enumerate() {
+            self.store8(start + index, *element);
         }
     }
 
     pub fn store8(&mut self, index: usize, element: u8) {
-        self.memory[index] = element
+        self.memory[index % MEMORY_SIZE] = element
+    }
+}
+
+impl Default for Memory {
+    fn default() -> Memory {
+        Memory::new()
     }
 }
 
@@ -0,0 +1,977 @@
+use std::collections::HashMap;
+
+/// Address Octo programs are compiled for. A jump to `main` is placed there.
+const START: usize = 0x200;
+const MAX_SIZE: usize = 0x10000;
+
+#[derive(Debug, Clone)]
+struct Token {
+    text: String,
+    line: usize,
+}
+
+#[derive(Clone)]
+struct Macro {
+    params: Vec<String>,
+    body: Vec<Token>,
+}
+
+/// How a label address is written once it is known.
+#[derive(Clone, Copy)]
+enum Patch {
+    /// Low 12 bits of the instruction word at the address.
+    Nnn,
+    /// The whole word at the address.
+    Long,
+    /// The byte at the address: `prefix << 4` plus the high byte of the label.
+    High(u8),
+    /// The low byte of the label into the byte at the address.
+    Low,
+}
+
+enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    Greater,
+    LessEqual,
+    GreaterEqual,
+    Key,
+    NotKey,
+}
+
+#[derive(Clone, Copy)]
+enum Operand {
+    Register(u8),
+    Value(i64),
+}
+
+struct Condition {
+    register: u8,
+    comparison: Comparison,
+    operand: Operand,
+}
+
+enum Block {
+    If { jump: usize },
+    Else { jump: usize },
+    Loop { start: usize, breaks: Vec<usize> },
+}
+
+/// Compiles Octo assembly source into a ROM image starting at `0x200`.
+///
+/// Supports the Octo statements for CHIP-8, SCHIP and XO-CHIP, labels, `:const`, `:alias`,
+/// `:unpack`, `:next`, `:org`, `:byte`, `:call`, `:macro` and `:calc`. `:stringmode` is not
+/// supported; debugger directives are ignored.
+pub fn compile(source: &str) -> Result<Vec<u8>, String> {
+    let mut compiler = Compiler::new(tokenize(source));
+    compiler.compile().map_err(|err| match compiler.line() {
+                                   Some(line) => format!("line {}: {}", line, err),
+                                   None => err,
+                               })
+}
+
+fn tokenize(source: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    for (index, line) in source.lines().enumerate() {
+        let code = line.split('#').next().unwrap_or("");
+        for text in code.split_whitespace() {
+            tokens.push(Token {
+                            text: text.to_string(),
+                            line: index + 1,
+                        });
+        }
+    }
+    tokens
+}
+
+fn parse_number(text: &str) -> Option<i64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+    let hex = digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X"));
+    let binary = digits.strip_prefix("0b").or_else(|| digits.strip_prefix("0B"));
+    let value = if let Some(hex) = hex {
+        i64::from_str_radix(hex, 16).ok()
+    } else if let Some(binary) = binary {
+        i64::from_str_radix(binary, 2).ok()
+    } else if digits.starts_with(|c: char| c.is_ascii_digit()) {
+        digits.parse().ok()
+    } else {
+        None
+    };
+    value.map(|value| if negative { -value } else { value })
+}
+
+fn parse_register(text: &str) -> Option<u8> {
+    let text = text.to_lowercase();
+    match text.strip_prefix('v') {
+        Some(digit) if digit.len() == 1 => u8::from_str_radix(digit, 16).ok(),
+        _ => None,
+    }
+}
+
+struct Compiler {
+    tokens: Vec<Token>,
+    position: usize,
+    rom: Vec<u8>,
+    here: usize,
+    labels: HashMap<String, usize>,
+    constants: HashMap<String, f64>,
+    aliases: HashMap<String, u8>,
+    macros: HashMap<String, Macro>,
+    patches: Vec<(usize, String, Patch)>,
+    next_labels: Vec<String>,
+    blocks: Vec<Block>,
+}
+
+impl Compiler {
+    fn new(tokens: Vec<Token>) -> Compiler {
+        Compiler {
+            tokens,
+            position: 0,
+            rom: Vec::new(),
+            here: START + 2,
+            labels: HashMap::new(),
+            constants: HashMap::new(),
+            aliases: HashMap::new(),
+            macros: HashMap::new(),
+            patches: Vec::new(),
+            next_labels: Vec::new(),
+            blocks: Vec::new(),
+        }
+    }
+
+    fn line(&self) -> Option<usize> {
+        self.tokens
+            .get(self.position.saturating_sub(1))
+            .map(|token| token.line)
+    }
+
+    fn compile(&mut self) -> Result<Vec<u8>, String> {
+        self.patch_at(START, 0x1000, "main", Patch::Nnn);
+        while self.position < self.tokens.len() {
+            self.statement()?;
+        }
+
+        if let Some(block) = self.blocks.last() {
+            return Err(match *block {
+                           Block::Loop { .. } => "'loop' without 'again'".to_string(),
+                           _ => "'begin' without 'end'".to_string(),
+                       });
+        }
+        if !self.next_labels.is_empty() {
+            return Err(format!("':next {}' without a following instruction",
+                               self.next_labels[0]));
+        }
+        self.position = self.tokens.len() + 1;
+        if !self.labels.contains_key("main") {
+            return Err("missing 'main' label".to_string());
+        }
+
+        for &(address, ref name, patch) in &self.patches {
+            let value = match self.labels.get(name) {
+                Some(&value) => value,
+                None => return Err(format!("undefined label '{}'", name)),
+            };
+            match patch {
+                Patch::Nnn => {
+                    if value > 0xFFF {
+                        return Err(format!("label '{}' is out of reach", name));
+                    }
+                    self.rom[address] |= (value >> 8) as u8;
+                    self.rom[address + 1] = value as u8;
+                }
+                Patch::Long => {
+                    self.rom[address] = (value >> 8) as u8;
+                    self.rom[address + 1] = value as u8;
+                }
+                Patch::High(prefix) => self.rom[address] = (prefix << 4) | (value >> 8) as u8,
+                Patch::Low => self.rom[address] = value as u8,
+            }
+        }
+
+        Ok(self.rom.split_off(START))
+    }
+
+    fn next(&mut self) -> Result<String, String> {
+        match self.tokens.get(self.position) {
+            Some(token) => {
+                self.position += 1;
+                Ok(token.text.clone())
+            }
+            None => Err("unexpected end of file".to_string()),
+        }
+    }
+
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.position).map(|token| token.text.as_str())
+    }
+
+    fn expect(&mut self, expected: &str) -> Result<(), String> {
+        let token = self.next()?;
+        if token == expected {
+            Ok(())
+        } else {
+            Err(format!("expected '{}', found '{}'", expected, token))
+        }
+    }
+
+    fn write(&mut self, address: usize, byte: u8) -> Result<(), String> {
+        if address >= MAX_SIZE {
+            return Err("program does not fit into memory".to_string());
+        }
+        if self.rom.len() <= address {
+            self.rom.resize(address + 1, 0);
+        }
+        self.rom[address] = byte;
+        Ok(())
+    }
+
+    fn emit_byte(&mut self, byte: u8) -> Result<(), String> {
+        let here = self.here;
+        self.write(here, byte)?;
+        self.here += 1;
+        Ok(())
+    }
+
+    fn emit(&mut self, word: u16) -> Result<(), String> {
+        for name in self.next_labels.split_off(0) {
+            let address = self.here + 1;
+            self.labels.insert(name, address);
+        }
+        self.emit_byte((word >> 8) as u8)?;
+        self.emit_byte(word as u8)
+    }
+
+    fn patch_at(&mut self, address: usize, word: u16, name: &str, patch: Patch) {
+        self.rom.resize(address + 2, 0);
+        self.rom[address] = (word >> 8) as u8;
+        self.rom[address + 1] = word as u8;
+        self.patches.push((address, name.to_string(), patch));
+    }
+
+    /// Emits an instruction whose operand is the address of `name`.
+    fn emit_address(&mut self, opcode: u16, name_or_value: &str) -> Result<(), String> {
+        if let Some(&address) = self.labels.get(name_or_value) {
+            return self.emit(opcode | address_12(address)?);
+        }
+        match self.constant(name_or_value) {
+            Some(value) => self.emit(opcode | address_12(value as usize)?),
+            None => {
+                let here = self.here;
+                self.emit(opcode)?;
+                self.patches.push((here, name_or_value.to_string(), Patch::Nnn));
+                Ok(())
+            }
+        }
+    }
+
+    fn constant(&self, name: &str) -> Option<f64> {
+        parse_number(name)
+            .map(|value| value as f64)
+            .or_else(|| self.constants.get(name).cloned())
+            .or_else(|| self.labels.get(name).map(|&value| value as f64))
+    }
+
+    fn register(&mut self) -> Result<u8, String> {
+        let token = self.next()?;
+        self.to_register(&token)
+            .ok_or_else(|| format!("expected a register, found '{}'", token))
+    }
+
+    fn to_register(&self, token: &str) -> Option<u8> {
+        parse_register(token).or_else(|| self.aliases.get(token).cloned())
+    }
+
+    /// Reads a number, constant, label or `{ expression }`.
+    fn value(&mut self) -> Result<f64, String> {
+        let token = self.next()?;
+        if token == "{" {
+            return self.expression_block();
+        }
+        self.constant(&token)
+            .ok_or_else(|| format!("undefined name '{}'", token))
+    }
+
+    fn byte(&mut self) -> Result<u8, String> {
+        let value = self.value()? as i64;
+        if !(-128..=255).contains(&value) {
+            return Err(format!("value {} does not fit into a byte", value));
+        }
+        Ok(value as u8)
+    }
+
+    fn nibble(&mut self) -> Result<u8, String> {
+        let value = self.value()? as i64;
+        if !(0..=15).contains(&value) {
+            return Err(format!("value {} does not fit into a nibble", value));
+        }
+        Ok(value as u8)
+    }
+
+    fn statement(&mut self) -> Result<(), String> {
+        let token = self.next()?;
+        if let Some(register) = self.to_register(&token) {
+            return self.register_statement(register);
+        }
+        if let Some(definition) = self.macros.get(&token).cloned() {
+            return self.expand(definition);
+        }
+
+        match token.as_str() {
+            ":" => {
+                let name = self.next()?;
+                if self.labels.insert(name.clone(), self.here).is_some() {
+                    return Err(format!("duplicate label '{}'", name));
+                }
+                Ok(())
+            }
+            ":const" => {
+                let name = self.next()?;
+                let value = self.value()?;
+                self.constants.insert(name, value);
+                Ok(())
+            }
+            ":calc" => {
+                let name = self.next()?;
+                self.expect("{")?;
+                let value = self.expression_block()?;
+                self.constants.insert(name, value);
+                Ok(())
+            }
+            ":alias" => {
+                let name = self.next()?;
+                let register = self.register()?;
+                self.aliases.insert(name, register);
+                Ok(())
+            }
+            ":unpack" => {
+                let prefix = if self.peek() == Some("long") {
+                    self.position += 1;
+                    None
+                } else {
+                    Some(self.nibble()?)
+                };
+                let name = self.next()?;
+                let here = self.here;
+                self.emit(0x6000)?;
+                self.emit(0x6100)?;
+                match self.labels.get(&name).cloned().or_else(|| {
+                    self.constant(&name).map(|value| value as usize)
+                }) {
+                    Some(address) => {
+                        self.rom[here + 1] = match prefix {
+                            Some(prefix) => (prefix << 4) | (address >> 8) as u8,
+                            None => (address >> 8) as u8,
+                        };
+                        self.rom[here + 3] = address as u8;
+                    }
+                    None => {
+                        let high = match prefix {
+                            Some(prefix) => Patch::High(prefix),
+                            None => Patch::High(0),
+                        };
+                        self.patches.push((here + 1, name.clone(), high));
+                        self.patches.push((here + 3, name, Patch::Low));
+                    }
+                }
+                Ok(())
+            }
+            ":next" => {
+                let name = self.next()?;
+                self.next_labels.push(name);
+                Ok(())
+            }
+            ":org" => {
+                self.here = self.value()? as usize;
+                Ok(())
+            }
+            ":byte" => {
+                let byte = self.byte()?;
+                self.emit_byte(byte)
+            }
+            ":call" => {
+                let name = self.next()?;
+                self.emit_address(0x2000, &name)
+            }
+            ":macro" => self.define_macro(),
+            ":breakpoint" => self.next().map(|_| ()),
+            ":monitor" => {
+                self.next()?;
+                if self.peek() == Some("{") {
+                    self.position += 1;
+                    self.expression_block()?;
+                } else {
+                    self.next()?;
+                }
+                Ok(())
+            }
+            ":stringmode" | ":assert" | ":pointer" => {
+                Err(format!("'{}' is not supported", token))
+            }
+            "return" | ";" => self.emit(0x00EE),
+            "clear" => self.emit(0x00E0),
+            "hires" => self.emit(0x00FF),
+            "lores" => self.emit(0x00FE),
+            "exit" => self.emit(0x00FD),
+            "scroll-left" => self.emit(0x00FC),
+            "scroll-right" => self.emit(0x00FB),
+            "scroll-down" => {
+                let n = self.nibble()?;
+                self.emit(0x00C0 | n as u16)
+            }
+            "scroll-up" => {
+                let n = self.nibble()?;
+                self.emit(0x00D0 | n as u16)
+            }
+            "audio" => self.emit(0xF002),
+            "plane" => {
+                let n = self.nibble()?;
+                self.emit(0xF001 | (n as u16) << 8)
+            }
+            "bcd" => self.register_op(0xF033),
+            "saveflags" => self.register_op(0xF075),
+            "loadflags" => self.register_op(0xF085),
+            "save" | "load" => {
+                let x = self.register()?;
+                if self.peek() == Some("-") {
+                    self.position += 1;
+                    let y = self.register()?;
+                    let low = if token == "save" { 0x2 } else { 0x3 };
+                    self.emit(0x5000 | (x as u16) << 8 | (y as u16) << 4 | low)
+                } else {
+                    let low = if token == "save" { 0x55 } else { 0x65 };
+                    self.emit(0xF000 | (x as u16) << 8 | low)
+                }
+            }
+            "sprite" => {
+                let x = self.register()?;
+                let y = self.register()?;
+                let n = self.nibble()?;
+                self.emit(0xD000 | (x as u16) << 8 | (y as u16) << 4 | n as u16)
+            }
+            "jump" => {
+                let name = self.next()?;
+                self.emit_address(0x1000, &name)
+            }
+            "jump0" => {
+                let name = self.next()?;
+                self.emit_address(0xB000, &name)
+            }
+            "native" => {
+                let name = self.next()?;
+                self.emit_address(0x0000, &name)
+            }
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let x = self.register()? as u16;
+                self.emit(match token.as_str() {
+                              "delay" => 0xF015 | x << 8,
+                              "buzzer" => 0xF018 | x << 8,
+                              _ => 0xF03A | x << 8,
+                          })
+            }
+            "i" => self.index_statement(),
+            "if" => self.conditional(),
+            "else" => {
+                let jump = match self.blocks.pop() {
+                    Some(Block::If { jump }) => jump,
+                    _ => return Err("'else' without 'begin'".to_string()),
+                };
+                let here = self.here;
+                self.emit(0x1000)?;
+                self.blocks.push(Block::Else { jump: here });
+                self.resolve_jump(jump)
+            }
+            "end" => {
+                match self.blocks.pop() {
+                    Some(Block::If { jump }) |
+                    Some(Block::Else { jump }) => self.resolve_jump(jump),
+                    _ => Err("'end' without 'begin'".to_string()),
+                }
+            }
+            "loop" => {
+                self.blocks.push(Block::Loop {
+                                     start: self.here,
+                                     breaks: Vec::new(),
+                                 });
+                Ok(())
+            }
+            "while" => {
+                let condition = self.condition()?;
+                self.emit_skip(&condition, true)?;
+                let here = self.here;
+                self.emit(0x1000)?;
+                for block in self.blocks.iter_mut().rev() {
+                    if let Block::Loop { ref mut breaks, .. } = *block {
+                        breaks.push(here);
+                        return Ok(());
+                    }
+                }
+                Err("'while' without 'loop'".to_string())
+            }
+            "again" => {
+                let (start, breaks) = match self.blocks.pop() {
+                    Some(Block::Loop { start, breaks }) => (start, breaks),
+                    _ => return Err("'again' without 'loop'".to_string()),
+                };
+                self.emit(0x1000 | address_12(start)?)?;
+                for jump in breaks {
+                    self.resolve_jump(jump)?;
+                }
+                Ok(())
+            }
+            _ => {
+                if let Some(value) = parse_number(&token) {
+                    if !(-128..=255).contains(&value) {
+                        return Err(format!("value {} does not fit into a byte", value));
+                    }
+                    return self.emit_byte(value as u8);
+                }
+                if self.constants.contains_key(&token) {
+                    let value = self.constants[&token] as i64;
+                    return self.emit_byte(value as u8);
+                }
+                if is_identifier(&token) {
+                    return self.emit_address(0x2000, &token);
+                }
+                Err(format!("unexpected '{}'", token))
+            }
+        }
+    }
+
+    fn register_op(&mut self, opcode: u16) -> Result<(), String> {
+        let x = self.register()?;
+        self.emit(opcode | (x as u16) << 8)
+    }
+
+    fn resolve_jump(&mut self, address: usize) -> Result<(), String> {
+        let target = address_12(self.here)?;
+        self.rom[address] |= (target >> 8) as u8;
+        self.rom[address + 1] = target as u8;
+        Ok(())
+    }
+
+    fn register_statement(&mut self, x: u8) -> Result<(), String> {
+        let x16 = (x as u16) << 8;
+        let operator = self.next()?;
+        let operand = self.next()?;
+        if let Some(y) = self.to_register(&operand) {
+            let y16 = (y as u16) << 4;
+            let low = match operator.as_str() {
+                ":=" => 0x0,
+                "|=" => 0x1,
+                "&=" => 0x2,
+                "^=" => 0x3,
+                "+=" => 0x4,
+                "-=" => 0x5,
+                ">>=" => 0x6,
+                "=-" => 0x7,
+                "<<=" => 0xE,
+                _ => return Err(format!("unknown operator '{}'", operator)),
+            };
+            return self.emit(0x8000 | x16 | y16 | low);
+        }
+
+        match (operator.as_str(), operand.as_str()) {
+            (":=", "key") => self.emit(0xF00A | x16),
+            (":=", "delay") => self.emit(0xF007 | x16),
+            (":=", "random") => {
+                let mask = self.byte()?;
+                self.emit(0xC000 | x16 | mask as u16)
+            }
+            (":=", _) | ("+=", _) | ("-=", _) => {
+                self.position -= 1;
+                let value = self.byte()?;
+                match operator.as_str() {
+                    ":=" => self.emit(0x6000 | x16 | value as u16),
+                    "+=" => self.emit(0x7000 | x16 | value as u16),
+                    _ => self.emit(0x7000 | x16 | value.wrapping_neg() as u16),
+                }
+            }
+            _ => Err(format!("'{}' needs a register operand", operator)),
+        }
+    }
+
+    fn index_statement(&mut self) -> Result<(), String> {
+        let operator = self.next()?;
+        let operand = self.next()?;
+        match (operator.as_str(), operand.as_str()) {
+            ("+=", _) => {
+                self.position -= 1;
+                self.register_op(0xF01E)
+            }
+            (":=", "hex") => self.register_op(0xF029),
+            (":=", "bighex") => self.register_op(0xF030),
+            (":=", "long") => {
+                let name = self.next()?;
+                self.emit(0xF000)?;
+                match self.constant(&name) {
+                    Some(value) => self.emit(value as u16),
+                    None => {
+                        let here = self.here;
+                        self.emit(0x0000)?;
+                        self.patches.push((here, name, Patch::Long));
+                        Ok(())
+                    }
+                }
+            }
+            (":=", "{") => {
+                let value = self.expression_block()?;
+                self.emit(0xA000 | address_12(value as usize)?)
+            }
+            (":=", _) => self.emit_address(0xA000, &operand),
+            _ => Err(format!("unknown operator '{}' for i", operator)),
+        }
+    }
+
+    fn condition(&mut self) -> Result<Condition, String> {
+        let register = self.register()?;
+        let comparison = match self.next()?.as_str() {
+            "==" => Comparison::Equal,
+            "!=" => Comparison::NotEqual,
+            "<" => Comparison::Less,
+            ">" => Comparison::Greater,
+            "<=" => Comparison::LessEqual,
+            ">=" => Comparison::GreaterEqual,
+            "key" => Comparison::Key,
+            "-key" => Comparison::NotKey,
+            other => return Err(format!("unknown comparison '{}'", other)),
+        };
+        let operand = match comparison {
+            Comparison::Key | Comparison::NotKey => Operand::Value(0),
+            _ => {
+                match self.peek().and_then(|token| self.to_register(token)) {
+                    Some(y) => {
+                        self.position += 1;
+                        Operand::Register(y)
+                    }
+                    None => Operand::Value(self.byte()? as i64),
+                }
+            }
+        };
+        Ok(Condition {
+               register,
+               comparison,
+               operand,
+           })
+    }
+
+    fn conditional(&mut self) -> Result<(), String> {
+        let condition = self.condition()?;
+        match self.next()?.as_str() {
+            "then" => self.emit_skip(&condition, false),
+            "begin" => {
+                self.emit_skip(&condition, true)?;
+                let here = self.here;
+                self.emit(0x1000)?;
+                self.blocks.push(Block::If { jump: here });
+                Ok(())
+            }
+            other => Err(format!("expected 'then' or 'begin', found '{}'", other)),
+        }
+    }
+
+    /// Emits instructions skipping the next one when the condition is `when`.
+    fn emit_skip(&mut self, condition: &Condition, when: bool) -> Result<(), String> {
+        let x = (condition.register as u16) << 8;
+        let (equal, operand) = match condition.comparison {
+            Comparison::Equal => (when, &condition.operand),
+            Comparison::NotEqual => (!when, &condition.operand),
+            Comparison::Key => return self.emit(if when { 0xE09E } else { 0xE0A1 } | x),
+            Comparison::NotKey => return self.emit(if when { 0xE0A1 } else { 0xE09E } | x),
+            _ => {
+                // VF := operand, then subtract so the borrow flag answers the comparison.
+                self.emit(match condition.operand {
+                              Operand::Register(y) => 0x8F00 | (y as u16) << 4,
+                              Operand::Value(value) => 0x6F00 | (value as u16 & 0xFF),
+                          })?;
+                let (subtract, flag) = match condition.comparison {
+                    Comparison::Less => (0x8F07, 0),
+                    Comparison::GreaterEqual => (0x8F07, 1),
+                    Comparison::Greater => (0x8F05, 0),
+                    _ => (0x8F05, 1),
+                };
+                self.emit(subtract | (condition.register as u16) << 4)?;
+                return self.emit(if when { 0x3F00 } else { 0x4F00 } | flag);
+            }
+        };
+        self.emit(match (*operand, equal) {
+                      (Operand::Register(y), true) => 0x5000 | x | (y as u16) << 4,
+                      (Operand::Register(y), false) => 0x9000 | x | (y as u16) << 4,
+                      (Operand::Value(value), true) => 0x3000 | x | (value as u16 & 0xFF),
+                      (Operand::Value(value), false) => 0x4000 | x | (value as u16 & 0xFF),
+                  })
+    }
+
+    fn define_macro(&mut self) -> Result<(), String> {
+        let name = self.next()?;
+        let mut params = Vec::new();
+        loop {
+            let token = self.next()?;
+            if token == "{" {
+                break;
+            }
+            params.push(token);
+        }
+
+        let mut body = Vec::new();
+        let mut depth = 1;
+        loop {
+            let token = self.tokens
+                            .get(self.position)
+                            .cloned()
+                            .ok_or_else(|| "unterminated macro".to_string())?;
+            self.position += 1;
+            match token.text.as_str() {
+                "{" => depth += 1,
+                "}" => depth -= 1,
+                _ => {}
+            }
+            if depth == 0 {
+                break;
+            }
+            body.push(token);
+        }
+        self.macros.insert(name,
+                           Macro {
+                               params,
+                               body,
+                           });
+        Ok(())
+    }
+
+    fn expand(&mut self, definition: Macro) -> Result<(), String> {
+        let mut arguments = HashMap::new();
+        for param in &definition.params {
+            arguments.insert(param.clone(), self.next()?);
+        }
+        let line = self.line().unwrap_or(0);
+        let body: Vec<Token> = definition.body
+            .iter()
+            .map(|token| {
+                     Token {
+                         text: arguments.get(&token.text).cloned().unwrap_or_else(|| {
+                             token.text.clone()
+                         }),
+                         line,
+                     }
+                 })
+            .collect();
+        let position = self.position;
+        self.tokens.splice(position..position, body);
+        Ok(())
+    }
+
+    /// Evaluates the tokens up to the closing `}`.
+    fn expression_block(&mut self) -> Result<f64, String> {
+        let mut tokens = Vec::new();
+        loop {
+            let token = self.next()?;
+            if token == "}" {
+                break;
+            }
+            tokens.push(token);
+        }
+        let mut position = 0;
+        let value = self.expression(&tokens, &mut position)?;
+        if position != tokens.len() {
+            return Err(format!("unexpected '{}' in expression", tokens[position]));
+        }
+        Ok(value)
+    }
+
+    /// Octo evaluates expressions right to left without operator precedence.
+    fn expression(&self, tokens: &[String], position: &mut usize) -> Result<f64, String> {
+        let left = self.term(tokens, position)?;
+        let operator = match tokens.get(*position) {
+            Some(operator) if operator != ")" => operator.clone(),
+            _ => return Ok(left),
+        };
+        *position += 1;
+        let right = self.expression(tokens, position)?;
+        let (a, b) = (left as i64, right as i64);
+        Ok(match operator.as_str() {
+               "+" => left + right,
+               "-" => left - right,
+               "*" => left * right,
+               "/" => left / right,
+               "%" => (a % b) as f64,
+               "&" => (a & b) as f64,
+               "|" => (a | b) as f64,
+               "^" => (a ^ b) as f64,
+               "<<" => (a << b) as f64,
+               ">>" => (a >> b) as f64,
+               "pow" => left.powf(right),
+               "min" => left.min(right),
+               "max" => left.max(right),
+               "<" => (left < right) as i64 as f64,
+               ">" => (left > right) as i64 as f64,
+               "<=" => (left <= right) as i64 as f64,
+               ">=" => (left >= right) as i64 as f64,
+               "==" => (left == right) as i64 as f64,
+               "!=" => (left != right) as i64 as f64,
+               _ => return Err(format!("unknown operator '{}'", operator)),
+           })
+    }
+
+    fn term(&self, tokens: &[String], position: &mut usize) -> Result<f64, String> {
+        let token = tokens.get(*position).ok_or_else(|| "incomplete expression".to_string())?;
+        *position += 1;
+        match token.as_str() {
+            "(" => {
+                let value = self.expression(tokens, position)?;
+                match tokens.get(*position) {
+                    Some(close) if close == ")" => {
+                        *position += 1;
+                        Ok(value)
+                    }
+                    _ => Err("missing ')'".to_string()),
+                }
+            }
+            "-" => self.term(tokens, position).map(|value| -value),
+            "~" => self.term(tokens, position).map(|value| !(value as i64) as f64),
+            "!" => self.term(tokens, position).map(|value| (value == 0.0) as i64 as f64),
+            "floor" => self.term(tokens, position).map(f64::floor),
+            "HERE" => Ok(self.here as f64),
+            _ => {
+                self.constant(token)
+                    .ok_or_else(|| format!("undefined name '{}'", token))
+            }
+        }
+    }
+}
+
+fn address_12(address: usize) -> Result<u16, String> {
+    if address > 0xFFF {
+        Err(format!("address 0x{:X} is out of reach", address))
+    } else {
+        Ok(address as u16)
+    }
+}
+
+fn is_identifier(text: &str) -> bool {
+    text.starts_with(|c: char| c.is_ascii_alphabetic() || c == '_') &&
+    text.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(rom: &[u8]) -> Vec<u16> {
+        rom.chunks(2)
+            .map(|pair| (pair[0] as u16) << 8 | pair.get(1).cloned().unwrap_or(0) as u16)
+            .collect()
+    }
+
+    #[test]
+    fn octo_main_jump() {
+        let rom = compile(": main\n  clear\n  loop again").unwrap();
+        assert_eq!(words(&rom), vec![0x1202, 0x00E0, 0x1204]);
+    }
+
+    #[test]
+    fn octo_statements() {
+        let source = "
+            :alias counter v3
+            :const SPEED 2
+            : main
+                counter := 5        # 6305
+                v0 += SPEED         # 7002
+                v1 -= 1             # 71FF
+                v2 := v1            # 8210
+                v2 =- v1            # 8217
+                v2 >>= v2           # 8226
+                v4 := random 0x0F   # C40F
+                v5 := key           # F50A
+                delay := v5         # F515
+                i := sprite         # A2??
+                i := hex v1         # F129
+                i += v2             # F21E
+                sprite v0 v1 4      # D014
+                save v3             # F355
+                load v1 - v2        # 5123
+                bcd v0              # F033
+                draw                # 2???
+            : draw
+                return
+            : sprite
+                0xF0 0x90
+        ";
+        let rom = compile(source).unwrap();
+        let draw = 0x202 + 17 * 2;
+        let sprite = draw + 2;
+        assert_eq!(words(&rom[2..draw - 0x200]),
+                   vec![0x6305,
+                        0x7002,
+                        0x71FF,
+                        0x8210,
+                        0x8217,
+                        0x8226,
+                        0xC40F,
+                        0xF50A,
+                        0xF515,
+                        0xA000 | sprite as u16,
+                        0xF129,
+                        0xF21E,
+                        0xD014,
+                        0xF355,
+                        0x5123,
+                        0xF033,
+                        0x2000 | draw as u16]);
+        assert_eq!(&rom[sprite - 0x200..], &[0xF0, 0x90]);
+    }
+
+    #[test]
+    fn octo_conditionals() {
+        let source = ": main
+            if v0 == 5 then v1 := 1
+            if v0 key then v1 := 2
+            if v0 != v2 begin v1 := 3 else v1 := 4 end
+            if v0 < 10 then v1 := 5";
+        let rom = compile(source).unwrap();
+        assert_eq!(words(&rom[2..]),
+                   vec![0x4005, 0x6101, 0xE0A1, 0x6102, 0x9020, 0x1212, 0x6103, 0x1214, 0x6104,
+                        0x6F0A, 0x8F07, 0x4F00, 0x6105]);
+    }
+
+    #[test]
+    fn octo_loop_while() {
+        let rom = compile(": main loop while v0 != 3 v0 += 1 again").unwrap();
+        assert_eq!(words(&rom[2..]), vec![0x4003, 0x120A, 0x7001, 0x1202]);
+    }
+
+    #[test]
+    fn octo_macro_calc_unpack() {
+        let source = "
+            :macro add-both a b { v0 += a v1 += b }
+            :calc HALF { 32 / 2 }
+            : main
+                add-both 1 HALF
+                :unpack 0xA data
+                i := long data
+            :org 0x300
+            : data";
+        let rom = compile(source).unwrap();
+        assert_eq!(words(&rom[2..]),
+                   vec![0x7001, 0x7110, 0x60A3, 0x6100, 0xF000, 0x0300]);
+    }
+
+    #[test]
+    fn octo_next_label() {
+        let rom = compile(": main :next target v0 := 7 i := target").unwrap();
+        assert_eq!(words(&rom[2..]), vec![0x6007, 0xA203]);
+    }
+
+    #[test]
+    fn octo_errors() {
+        assert!(compile("clear").unwrap_err().contains("main"));
+        assert!(compile(": main jump nowhere").unwrap_err().contains("nowhere"));
+        assert!(compile(": main v0 := 300").is_err());
+        assert!(compile(": main loop").is_err());
+        assert!(compile(": main\nelse").unwrap_err().starts_with("line 2"));
+    }
+}
@@ -1,6 +1,6 @@
 use std::fmt;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 pub struct Opcode {
     opcode: u16,
     pub x: usize,
@@ -20,7 +20,7 @@ impl fmt::Display for Opcode {
 impl Opcode {
     pub fn new(opcode: u16) -> Opcode {
         Opcode {
-            opcode: opcode,
+            opcode,
             x: ((opcode & 0x0F00) >> 8) as usize,
             y: ((opcode & 0x00F0) >> 4) as usize,
             address: opcode & 0x0FFF,
@@ -0,0 +1,248 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+use toml;
+
+use error::Chip8Error;
+use keyboard::NUM_KEYS;
+
+const DEFAULT_DEAD_ZONE: i16 = 8000;
+
+/// Game controller input identified by its SDL game controller name (`a`, `dpup`, `leftx`).
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum PadInput {
+    Button(String),
+    /// An axis pushed towards its positive (`true`) or negative end.
+    Axis(String, bool),
+}
+
+impl PadInput {
+    pub fn button(name: &str) -> PadInput {
+        PadInput::Button(name.to_lowercase())
+    }
+
+    pub fn axis(name: &str, positive: bool) -> PadInput {
+        PadInput::Axis(name.to_lowercase(), positive)
+    }
+
+    /// Parses a button name or an axis direction such as `leftx-`.
+    pub fn parse(name: &str) -> PadInput {
+        if let Some(axis) = name.strip_suffix('+') {
+            PadInput::axis(axis, true)
+        } else if let Some(axis) = name.strip_suffix('-') {
+            PadInput::axis(axis, false)
+        } else {
+            PadInput::button(name)
+        }
+    }
+}
+
+// D-pad and left stick follow the WASD keys of the default key layout.
+const DEFAULT_BINDINGS: [(&str, u8); 14] = [("dpup", 0x5),
+                                            ("dpleft", 0x7),
+                                            ("dpdown", 0x8),
+                                            ("dpright", 0x9),
+                                            ("lefty-", 0x5),
+                                            ("leftx-", 0x7),
+                                            ("lefty+", 0x8),
+                                            ("leftx+", 0x9),
+                                            ("a", 0x6),
+                                            ("b", 0x4),
+                                            ("x", 0xE),
+                                            ("y", 0xF),
+                                            ("start", 0x1),
+                                            ("back", 0x0)];
+
+#[derive(Deserialize)]
+struct PadMapFile {
+    dead_zone: Option<i16>,
+    #[serde(default)]
+    buttons: HashMap<String, String>,
+    #[serde(default)]
+    rom: HashMap<String, HashMap<String, String>>,
+}
+
+/// Axis directions of the game controllers that are pushed past the dead zone.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AxisState {
+    pushed: HashSet<PadInput>,
+}
+
+/// Mapping of game controller buttons and stick directions onto the 16-key hex keypad.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PadMap {
+    bindings: HashMap<PadInput, u8>,
+    dead_zone: i16,
+}
+
+impl PadMap {
+    pub fn new() -> PadMap {
+        PadMap {
+            bindings: HashMap::new(),
+            dead_zone: DEFAULT_DEAD_ZONE,
+        }
+    }
+
+    /// Parses a TOML mapping file and applies the profile of the ROM with the SHA-1 `rom` on
+    /// top of the default bindings. Profiles are keyed by hash like the per-ROM sections of the
+    /// configuration file, so they follow a ROM that is renamed or moved.
+    ///
+    /// ```toml
+    /// dead_zone = 8000
+    ///
+    /// [buttons]
+    /// a = "6"
+    /// "leftx-" = "4"
+    ///
+    /// [rom.0123456789abcdef0123456789abcdef01234567]
+    /// dpleft = "4"
+    /// dpright = "6"
+    /// ```
+    pub fn from_toml(text: &str, rom: Option<&str>) -> Result<PadMap, Chip8Error> {
+        let file: PadMapFile = toml::from_str(text).map_err(|err| err.to_string())?;
+        let mut padmap = PadMap::default();
+        match file.dead_zone {
+            Some(dead_zone) if dead_zone < 0 => {
+                return Err(Chip8Error::Message(format!("Negative dead zone: {}", dead_zone)));
+            }
+            Some(dead_zone) => padmap.dead_zone = dead_zone,
+            None => {}
+        }
+
+        padmap.bind_all(&file.buttons)?;
+        let profile = rom.and_then(|rom| {
+                                       file.rom
+                                           .iter()
+                                           .find(|&(hash, _)| hash.eq_ignore_ascii_case(rom))
+                                   });
+        if let Some((_, profile)) = profile {
+            padmap.bind_all(profile)?;
+        }
+        Ok(padmap)
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P, rom: Option<&str>) -> Result<PadMap, Chip8Error> {
+        let text = fs::read_to_string(path)?;
+        PadMap::from_toml(&text, rom)
+    }
+
+    pub fn bind(&mut self, input: PadInput, chip8_key: u8) {
+        self.bindings.insert(input, chip8_key);
+    }
+
+    pub fn lookup(&self, input: &PadInput) -> Option<u8> {
+        self.bindings.get(input).cloned()
+    }
+
+    /// Returns the key presses and releases caused by moving `axis` to `value`. Only
+    /// directions crossing the dead zone change keys, so small movements of a stick leave keys
+    /// held on the D-pad or the keyboard alone.
+    pub fn axis_keys(&self, state: &mut AxisState, axis: &str, value: i16) -> Vec<(u8, bool)> {
+        let directions = [(PadInput::axis(axis, false), value < -self.dead_zone),
+                          (PadInput::axis(axis, true), value > self.dead_zone)];
+        let mut keys = Vec::new();
+        for (input, pushed) in directions.iter().cloned() {
+            let changed = if pushed {
+                state.pushed.insert(input.clone())
+            } else {
+                state.pushed.remove(&input)
+            };
+            if let (true, Some(key)) = (changed, self.lookup(&input)) {
+                keys.push((key, pushed));
+            }
+        }
+        keys
+    }
+
+    fn bind_all(&mut self, bindings: &HashMap<String, String>) -> Result<(), Chip8Error> {
+        for (input, chip8_key) in bindings {
+            let chip8_key = match u8::from_str_radix(chip8_key, 16) {
+                Ok(key) if (key as usize) < NUM_KEYS => key,
+                _ => return Err(Chip8Error::Message(format!("Invalid CHIP-8 key: {}", chip8_key))),
+            };
+            self.bind(PadInput::parse(input), chip8_key);
+        }
+        Ok(())
+    }
+}
+
+impl Default for PadMap {
+    fn default() -> PadMap {
+        let mut padmap = PadMap::new();
+        for &(input, chip8_key) in DEFAULT_BINDINGS.iter() {
+            padmap.bind(PadInput::parse(input), chip8_key);
+        }
+        padmap
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn padmap_parse_input() {
+        assert_eq!(PadInput::parse("A"), PadInput::Button("a".to_string()));
+        assert_eq!(PadInput::parse("leftx+"), PadInput::Axis("leftx".to_string(), true));
+        assert_eq!(PadInput::parse("lefty-"), PadInput::Axis("lefty".to_string(), false));
+    }
+
+    #[test]
+    fn padmap_default() {
+        let padmap = PadMap::default();
+        assert_eq!(padmap.lookup(&PadInput::button("dpup")), Some(0x5));
+        assert_eq!(padmap.lookup(&PadInput::button("leftshoulder")), None);
+    }
+
+    #[test]
+    fn padmap_axis_keys() {
+        let padmap = PadMap::default();
+        let mut state = AxisState::default();
+        assert_eq!(padmap.axis_keys(&mut state, "leftx", -20000), vec![(0x7, true)]);
+        assert_eq!(padmap.axis_keys(&mut state, "leftx", -21000), vec![]);
+        assert_eq!(padmap.axis_keys(&mut state, "leftx", 100), vec![(0x7, false)]);
+        assert_eq!(padmap.axis_keys(&mut state, "leftx", 20000), vec![(0x9, true)]);
+        assert_eq!(padmap.axis_keys(&mut state, "leftx", -20000),
+                   vec![(0x7, true), (0x9, false)]);
+        assert!(padmap.axis_keys(&mut state, "rightx", 20000).is_empty());
+    }
+
+    #[test]
+    fn padmap_axis_jitter() {
+        // Jitter inside the dead zone must not release key 5 held on the D-pad.
+        let padmap = PadMap::default();
+        let mut state = AxisState::default();
+        for &value in [0, 300, -250, 7999, -8000, 0].iter() {
+            assert!(padmap.axis_keys(&mut state, "lefty", value).is_empty());
+        }
+    }
+
+    #[test]
+    fn padmap_rom_profile() {
+        let text = "dead_zone = 100\n[buttons]\na = \"C\"\n\
+                    [rom.DA39A3EE5E6B4B0D3255BFEF95601890AFD80709]\ndpleft = \"4\"\n";
+        let padmap = PadMap::from_toml(text, Some("da39a3ee5e6b4b0d3255bfef95601890afd80709"))
+            .unwrap();
+        assert_eq!(padmap.lookup(&PadInput::button("a")), Some(0xC));
+        assert_eq!(padmap.lookup(&PadInput::button("dpleft")), Some(0x4));
+        assert_eq!(padmap.axis_keys(&mut AxisState::default(), "leftx", 200),
+                   vec![(0x9, true)]);
+
+        let padmap = PadMap::from_toml(text, Some("0123456789abcdef0123456789abcdef01234567"))
+            .unwrap();
+        assert_eq!(padmap.lookup(&PadInput::button("dpleft")), Some(0x7));
+    }
+
+    #[test]
+    fn padmap_invalid_key() {
+        assert!(PadMap::from_toml("[buttons]\na = \"10\"", None).is_err());
+    }
+
+    #[test]
+    fn padmap_negative_dead_zone() {
+        assert!(PadMap::from_toml("dead_zone = -1", None).is_err());
+        assert!(PadMap::from_toml("dead_zone = -32768", None).is_err());
+        assert!(PadMap::from_toml("dead_zone = 0", None).is_ok());
+    }
+}
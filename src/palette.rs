@@ -0,0 +1,86 @@
+use error::Chip8Error;
+
+/// RGB color.
+pub type Color = (u8, u8, u8);
+
+pub const PRESETS: [&str; 4] = ["classic", "amber", "green", "lcd"];
+
+/// Colors of lit and unlit pixels.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Palette {
+    pub foreground: Color,
+    pub background: Color,
+}
+
+impl Palette {
+    /// Returns one of the built-in `PRESETS`.
+    pub fn preset(name: &str) -> Option<Palette> {
+        let (foreground, background) = match name.to_lowercase().as_str() {
+            "classic" => ((0xFF, 0xFF, 0xFF), (0x00, 0x00, 0x00)),
+            "amber" => ((0xFF, 0xB0, 0x00), (0x1A, 0x10, 0x00)),
+            "green" => ((0x33, 0xFF, 0x66), (0x00, 0x1A, 0x08)),
+            "lcd" => ((0x0F, 0x38, 0x0F), (0x9B, 0xBC, 0x0F)),
+            _ => return None,
+        };
+        Some(Palette {
+                 foreground,
+                 background,
+             })
+    }
+
+    /// Resolves a preset name or a `RRGGBB,RRGGBB` pair of foreground and background colors.
+    pub fn resolve(name_or_colors: &str) -> Result<Palette, Chip8Error> {
+        if let Some(palette) = Palette::preset(name_or_colors) {
+            return Ok(palette);
+        }
+
+        let colors: Vec<Option<Color>> = name_or_colors.split(',').map(parse_color).collect();
+        match colors.as_slice() {
+            [Some(foreground), Some(background)] => {
+                Ok(Palette {
+                       foreground: *foreground,
+                       background: *background,
+                   })
+            }
+            _ => Err(Chip8Error::Message(format!("Unknown palette: {}", name_or_colors))),
+        }
+    }
+}
+
+impl Default for Palette {
+    fn default() -> Palette {
+        Palette::preset("classic").unwrap()
+    }
+}
+
+fn parse_color(text: &str) -> Option<Color> {
+    let text = text.trim().trim_start_matches('#');
+    if text.len() != 6 {
+        return None;
+    }
+    u32::from_str_radix(text, 16)
+        .ok()
+        .map(|rgb| ((rgb >> 16) as u8, (rgb >> 8) as u8, rgb as u8))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn palette_presets() {
+        for name in PRESETS.iter() {
+            assert!(Palette::preset(name).is_some());
+        }
+        assert_eq!(Palette::default().foreground, (0xFF, 0xFF, 0xFF));
+    }
+
+    #[test]
+    fn palette_colors() {
+        let palette = Palette::resolve("#FFB000,101010").unwrap();
+        assert_eq!(palette.foreground, (0xFF, 0xB0, 0x00));
+        assert_eq!(palette.background, (0x10, 0x10, 0x10));
+        assert!(Palette::resolve("FFB000").is_err());
+        assert!(Palette::resolve("red,blue").is_err());
+    }
+}
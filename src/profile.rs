@@ -0,0 +1,305 @@
+//! Profiling of CHIP-8 programs.
+//!
+//! A `Profile` counts how often each instruction executes and what it costs in COSMAC VIP
+//! machine cycles (`timing::vip_cycles`). Subroutines are tracked by following `2NNN` and
+//! `00EE`, which gives the cycles spent in each subroutine with and without those it calls, the
+//! call graph and the call stacks in the folded format of flame graph tools.
+
+use std::collections::BTreeMap;
+
+use instruction::Instruction;
+use stack::STACK_SIZE;
+
+/// Executions of the instruction at one address.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Sample {
+    pub instruction: Instruction,
+    pub count: u64,
+    pub cycles: u64,
+}
+
+/// Cycles spent in a subroutine. The program itself is the subroutine at its start address.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Routine {
+    pub calls: u64,
+    /// Cycles of the instructions of the subroutine itself.
+    pub self_cycles: u64,
+    /// Cycles including the subroutines it calls.
+    pub total_cycles: u64,
+}
+
+/// Calls from one subroutine to another.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Edge {
+    pub calls: u64,
+    /// Cycles spent in the callee and the subroutines it calls when called from the caller.
+    pub cycles: u64,
+}
+
+pub struct Profile {
+    start: u16,
+    total: u64,
+    samples: BTreeMap<u16, Sample>,
+    routines: BTreeMap<u16, Routine>,
+    edges: BTreeMap<(u16, u16), Edge>,
+    folded: BTreeMap<Vec<u16>, u64>,
+    // Entry addresses of the subroutines being executed, starting with the program.
+    stack: Vec<u16>,
+}
+
+impl Profile {
+    /// Creates an empty profile of the program starting at `start`.
+    pub fn new(start: u16) -> Profile {
+        Profile {
+            start,
+            total: 0,
+            samples: BTreeMap::new(),
+            routines: BTreeMap::new(),
+            edges: BTreeMap::new(),
+            folded: BTreeMap::new(),
+            stack: vec![start],
+        }
+    }
+
+    /// Records that `instruction` at `address` executed, taking `cycles`.
+    pub fn record(&mut self, address: u16, instruction: Instruction, cycles: u32) {
+        let cycles = cycles as u64;
+        self.total += cycles;
+        let empty = Sample {
+            instruction,
+            count: 0,
+            cycles: 0,
+        };
+        let sample = self.samples.entry(address).or_insert(empty);
+        // Self-modifying code replaces the instruction.
+        sample.instruction = instruction;
+        sample.count += 1;
+        sample.cycles += cycles;
+
+        match self.folded.get_mut(&self.stack[..]) {
+            Some(folded) => *folded += cycles,
+            None => {
+                self.folded.insert(self.stack.clone(), cycles);
+            }
+        }
+        let current = *self.stack.last().unwrap();
+        self.routines.entry(current).or_default().self_cycles += cycles;
+        for (depth, &entry) in self.stack.iter().enumerate() {
+            // Recursive subroutines count once.
+            if !self.stack[..depth].contains(&entry) {
+                self.routines.entry(entry).or_default().total_cycles += cycles;
+            }
+        }
+        for pair in self.stack.windows(2) {
+            self.edges.entry((pair[0], pair[1])).or_default().cycles += cycles;
+        }
+
+        match instruction {
+            Instruction::Call(target) => {
+                self.routines.entry(target).or_default().calls += 1;
+                self.edges.entry((current, target)).or_default().calls += 1;
+                // A call that overflows the stack of the interpreter halts it instead.
+                if self.stack.len() <= STACK_SIZE {
+                    self.stack.push(target);
+                }
+            }
+            Instruction::Ret if self.stack.len() > 1 => {
+                self.stack.pop();
+            }
+            _ => {}
+        }
+    }
+
+    /// Returns the machine cycles of all recorded instructions.
+    pub fn total_cycles(&self) -> u64 {
+        self.total
+    }
+
+    /// Returns the executed addresses, those with the most cycles first.
+    pub fn samples(&self) -> Vec<(u16, Sample)> {
+        let mut samples: Vec<(u16, Sample)> = self.samples
+            .iter()
+            .map(|(&address, &sample)| (address, sample))
+            .collect();
+        samples.sort_by(|a, b| b.1.cycles.cmp(&a.1.cycles).then(a.0.cmp(&b.0)));
+        samples
+    }
+
+    /// Returns the subroutines, those with the most cycles including their callees first.
+    pub fn routines(&self) -> Vec<(u16, Routine)> {
+        let mut routines: Vec<(u16, Routine)> = self.routines
+            .iter()
+            .map(|(&entry, &routine)| (entry, routine))
+            .collect();
+        routines.sort_by(|a, b| b.1.total_cycles.cmp(&a.1.total_cycles).then(a.0.cmp(&b.0)));
+        routines
+    }
+
+    /// Returns the name of the subroutine at `entry`: `main` for the program, otherwise
+    /// `sub_` and the address.
+    pub fn name(&self, entry: u16) -> String {
+        if entry == self.start {
+            "main".to_string()
+        } else {
+            format!("sub_{:03X}", entry)
+        }
+    }
+
+    fn percent(&self, cycles: u64) -> f64 {
+        if self.total == 0 {
+            0.0
+        } else {
+            cycles as f64 * 100.0 / self.total as f64
+        }
+    }
+
+    /// Formats the instructions and subroutines as tables, those with the most cycles first.
+    pub fn flat(&self) -> String {
+        let mut text = format!("{:>12} {:>6} {:>10}  address  instruction\n",
+                               "cycles",
+                               "%",
+                               "count");
+        for (address, sample) in self.samples() {
+            text += &format!("{:>12} {:>6.2} {:>10}  {:03X}      {}\n",
+                             sample.cycles,
+                             self.percent(sample.cycles),
+                             sample.count,
+                             address,
+                             sample.instruction);
+        }
+        text += &format!("\n{:>12} {:>6} {:>12} {:>6} {:>10}  subroutine\n",
+                         "total",
+                         "%",
+                         "self",
+                         "%",
+                         "calls");
+        for (entry, routine) in self.routines() {
+            text += &format!("{:>12} {:>6.2} {:>12} {:>6.2} {:>10}  {}\n",
+                             routine.total_cycles,
+                             self.percent(routine.total_cycles),
+                             routine.self_cycles,
+                             self.percent(routine.self_cycles),
+                             routine.calls,
+                             self.name(entry));
+        }
+        text
+    }
+
+    /// Formats the callers and callees of each subroutine with the calls and the cycles spent
+    /// in the callee.
+    pub fn call_graph(&self) -> String {
+        let mut text = String::new();
+        for (entry, routine) in self.routines() {
+            text += &format!("{} {:.2}% ({} cycles, {} self, {} calls)\n",
+                             self.name(entry),
+                             self.percent(routine.total_cycles),
+                             routine.total_cycles,
+                             routine.self_cycles,
+                             routine.calls);
+            for (&(caller, callee), edge) in &self.edges {
+                if callee == entry {
+                    text += &format!("    <- {} ({} calls, {} cycles)\n",
+                                     self.name(caller),
+                                     edge.calls,
+                                     edge.cycles);
+                }
+            }
+            for (&(caller, callee), edge) in &self.edges {
+                if caller == entry {
+                    text += &format!("    -> {} ({} calls, {} cycles)\n",
+                                     self.name(callee),
+                                     edge.calls,
+                                     edge.cycles);
+                }
+            }
+        }
+        text
+    }
+
+    /// Formats the call stacks as lines of subroutine names separated by `;`, followed by the
+    /// cycles spent in the last of them, as read by `flamegraph.pl` and similar tools.
+    pub fn folded(&self) -> String {
+        let mut text = String::new();
+        for (stack, cycles) in &self.folded {
+            let names: Vec<String> = stack.iter().map(|&entry| self.name(entry)).collect();
+            text += &format!("{} {}\n", names.join(";"), cycles);
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // main calls 0x300 twice, which calls 0x400.
+    fn profile() -> Profile {
+        let mut profile = Profile::new(0x200);
+        for &address in [0x200, 0x202].iter() {
+            profile.record(address, Instruction::Call(0x300), 10);
+            profile.record(0x300, Instruction::Call(0x400), 10);
+            profile.record(0x400, Instruction::LdByte(0, 1), 5);
+            profile.record(0x402, Instruction::Ret, 5);
+            profile.record(0x302, Instruction::Ret, 5);
+        }
+        profile.record(0x204, Instruction::Jp(0x204), 5);
+        profile
+    }
+
+    #[test]
+    fn profile_samples() {
+        let profile = profile();
+        assert_eq!(profile.total_cycles(), 75);
+        let samples = profile.samples();
+        assert_eq!(samples[0].0, 0x300);
+        let ret = samples.iter().find(|sample| sample.0 == 0x302).unwrap().1;
+        assert_eq!((ret.count, ret.cycles), (2, 10));
+    }
+
+    #[test]
+    fn profile_routines() {
+        let profile = profile();
+        let routines: BTreeMap<u16, Routine> = profile.routines().into_iter().collect();
+        assert_eq!(routines[&0x200],
+                   Routine {
+                       calls: 0,
+                       self_cycles: 25,
+                       total_cycles: 75,
+                   });
+        assert_eq!(routines[&0x300],
+                   Routine {
+                       calls: 2,
+                       self_cycles: 30,
+                       total_cycles: 50,
+                   });
+        assert_eq!(routines[&0x400].total_cycles, 20);
+        assert_eq!(profile.edges[&(0x200, 0x300)],
+                   Edge {
+                       calls: 2,
+                       cycles: 50,
+                   });
+    }
+
+    #[test]
+    fn profile_recursion_and_empty_stack() {
+        let mut profile = Profile::new(0x200);
+        profile.record(0x200, Instruction::Call(0x200), 10);
+        profile.record(0x200, Instruction::Ret, 5);
+        profile.record(0x202, Instruction::Ret, 5);
+        assert_eq!(profile.routines()[0].1.total_cycles, 20);
+        assert_eq!(profile.stack, vec![0x200]);
+    }
+
+    #[test]
+    fn profile_reports() {
+        let profile = profile();
+        assert_eq!(profile.folded(),
+                   "main 25\nmain;sub_300 30\nmain;sub_300;sub_400 20\n");
+        let flat = profile.flat();
+        assert!(flat.lines().nth(1).unwrap().ends_with("300      CALL 0x400"));
+        assert!(flat.contains("sub_400"));
+        let graph = profile.call_graph();
+        assert!(graph.contains("sub_300 66.67% (50 cycles, 30 self, 2 calls)\n    <- main (2 \
+                                calls, 50 cycles)\n    -> sub_400 (2 calls, 20 cycles)\n"));
+    }
+}
@@ -0,0 +1,100 @@
+/// Behaviour that differs between CHIP-8 interpreters.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Quirks {
+    /// `FX0A` completes once the pressed key is released again, like the COSMAC VIP
+    /// interpreter, instead of as soon as it is pressed.
+    pub key_release: bool,
+    /// `8XY6`/`8XYE` shift VX in place instead of storing the shifted VY in VX.
+    pub shift: bool,
+    /// `FX55`/`FX65` leave I unchanged instead of advancing it past the last register.
+    pub load_store: bool,
+    /// `BNNN` jumps to NNN plus VX, X being the highest digit of NNN, instead of plus V0.
+    pub jump: bool,
+    /// `8XY1`, `8XY2` and `8XY3` reset VF.
+    pub logic: bool,
+    /// `DXYN` cuts sprites off at the edges of the display instead of wrapping them around.
+    pub clip: bool,
+    /// `DXYN` sets VF to the number of sprite rows that collided or were clipped at the bottom,
+    /// like SUPER-CHIP, instead of 1 on any collision.
+    pub collision_rows: bool,
+    /// `DXYN` waits for the vertical blank at the start of the next frame unless it is the
+    /// first instruction of the frame, like the COSMAC VIP interpreter.
+    pub display_wait: bool,
+    /// `FX1E` sets VF when I goes past 0xFFF, like the Amiga interpreter, instead of leaving VF
+    /// unchanged.
+    pub add_i_overflow: bool,
+}
+
+pub const PRESETS: [&str; 3] = ["vip", "schip", "modern"];
+
+impl Quirks {
+    /// Behaviour of the original COSMAC VIP interpreter.
+    pub fn vip() -> Quirks {
+        Quirks {
+            key_release: true,
+            shift: false,
+            load_store: false,
+            jump: false,
+            logic: true,
+            clip: true,
+            collision_rows: false,
+            display_wait: true,
+            add_i_overflow: false,
+        }
+    }
+
+    /// Behaviour of SUPER-CHIP 1.1 on the HP 48.
+    pub fn schip() -> Quirks {
+        Quirks {
+            key_release: false,
+            shift: true,
+            load_store: true,
+            jump: true,
+            logic: false,
+            clip: true,
+            collision_rows: true,
+            display_wait: false,
+            add_i_overflow: false,
+        }
+    }
+
+    /// Returns one of the `PRESETS`. `modern` is the default behaviour of this emulator.
+    pub fn preset(name: &str) -> Option<Quirks> {
+        match name.to_lowercase().as_str() {
+            "vip" => Some(Quirks::vip()),
+            "schip" => Some(Quirks::schip()),
+            "modern" => Some(Quirks::default()),
+            _ => None,
+        }
+    }
+}
+
+impl Default for Quirks {
+    fn default() -> Quirks {
+        Quirks {
+            key_release: false,
+            shift: true,
+            load_store: true,
+            jump: false,
+            logic: false,
+            clip: false,
+            collision_rows: false,
+            display_wait: false,
+            add_i_overflow: true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quirks_presets() {
+        for name in PRESETS.iter() {
+            assert!(Quirks::preset(name).is_some());
+        }
+        assert_eq!(Quirks::preset("VIP"), Some(Quirks::vip()));
+        assert!(Quirks::preset("eti660").is_none());
+    }
+}
@@ -22,6 +22,12 @@ impl Register {
     }
 }
 
+impl Default for Register {
+    fn default() -> Register {
+        Register::new()
+    }
+}
+
 impl Index<usize> for Register {
     type Output = u8;
 
@@ -1,153 +1,359 @@
 use super::*;
 
-use std::sync::{Arc, Mutex, Condvar};
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::Duration;
+
+use sdl2::audio::{AudioCallback, AudioDevice, AudioSpecDesired};
+use sdl2::controller::GameController;
 
 use gfx::GfxMemory;
-use keyboard::Keyboard;
+use input::InputQueue;
+use keymap::{HostKey, KeyMap};
+use padmap::{AxisState, PadInput, PadMap};
+use palette::Palette;
 use error::Chip8Error;
+use frontend::{self, Frontend, FrameLimiter};
+use frame::{self, FrameReceiver, FrameSender};
 
-const PIXEL_SIZE: usize = 20;
+// How long the display thread waits for a frame before handling events again.
+const POLL_INTERVAL_MS: u64 = 5;
 
-pub struct Renderer {
-    gfx: Arc<Mutex<GfxMemory>>,
-    keys: Arc<Mutex<Keyboard>>,
-    shutdown: Arc<(Mutex<bool>, Condvar)>,
-    started: Arc<(Mutex<bool>, Condvar)>,
+
+struct SquareWave {
+    phase_inc: f32,
+    phase: f32,
+    volume: f32,
 }
 
-impl Renderer {
-    pub fn new(gfx: Arc<Mutex<GfxMemory>>,
-               keyboard: Arc<Mutex<Keyboard>>,
-               shutdown: Arc<(Mutex<bool>, Condvar)>,
-               started: Arc<(Mutex<bool>, Condvar)>)
-               -> Renderer {
-        Renderer {
-            gfx: gfx,
-            keys: keyboard,
-            shutdown: shutdown,
-            started: started,
+impl AudioCallback for SquareWave {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        for sample in out.iter_mut() {
+            *sample = if self.phase <= 0.5 {
+                self.volume
+            } else {
+                -self.volume
+            };
+            self.phase = (self.phase + self.phase_inc) % 1.0;
         }
     }
+}
 
-    pub fn start(gfx: Arc<Mutex<GfxMemory>>,
-                 keyboard: Arc<Mutex<Keyboard>>,
-                 shutdown: Arc<(Mutex<bool>, Condvar)>,
-                 started: Arc<(Mutex<bool>, Condvar)>) {
-        let renderer = Renderer::new(gfx, keyboard, shutdown, started);
-        if let Err(err) = renderer.run() {
-            match err {
-                error::Chip8Error::Message(msg) => {
-                    println!("Error rendering: {}", msg);
-                }
-                _ => {
-                    println!("Error rendering: {:?}", err);
+/// Messages from the emulation to the display thread.
+enum Command {
+    PlayAudio(bool),
+    WaitForKey(bool),
+}
+
+/// Frontend showing the display in an SDL window, with keyboard, game controller and audio.
+///
+/// SDL runs on a display thread of its own. Completed frames are handed to it through
+/// `frame::channel`, so drawing and presenting never stall emulation, and key changes and
+/// errors come back over channels.
+pub struct Renderer {
+    frames: Option<FrameSender>,
+    commands: Sender<Command>,
+    keys: Receiver<(u8, bool)>,
+    // The result of initializing SDL, followed by the error that stopped the display thread.
+    status: Receiver<Result<(), Chip8Error>>,
+    quit: Arc<AtomicBool>,
+    display: Option<thread::JoinHandle<()>>,
+    limiter: FrameLimiter,
+    playing: bool,
+    waiting_for_key: bool,
+}
+
+impl Renderer {
+    pub fn new(options: frontend::Options) -> Result<Renderer, Chip8Error> {
+        let (frames, receiver) = frame::channel();
+        let (commands, command_receiver) = mpsc::channel();
+        let (key_sender, keys) = mpsc::channel();
+        let (status_sender, status) = mpsc::channel();
+        let quit = Arc::new(AtomicBool::new(false));
+        let display_quit = quit.clone();
+        let display = thread::spawn(move || {
+            let mut display = match Display::new(options) {
+                Ok(display) => display,
+                Err(err) => {
+                    let _ = status_sender.send(Err(err));
+                    return;
                 }
+            };
+            let _ = status_sender.send(Ok(()));
+            let result = display.run(receiver, &command_receiver, &key_sender, &display_quit);
+            if let Err(err) = result {
+                display_quit.store(true, Ordering::SeqCst);
+                let _ = status_sender.send(Err(err));
             }
+        });
+
+        match status.recv() {
+            Ok(Ok(())) => {}
+            Ok(Err(err)) => return Err(err),
+            Err(_) => return Err(Chip8Error::Message("SDL display thread failed".to_string())),
         }
+        Ok(Renderer {
+               frames: Some(frames),
+               commands,
+               keys,
+               status,
+               quit,
+               display: Some(display),
+               limiter: FrameLimiter::new(),
+               playing: false,
+               waiting_for_key: false,
+           })
     }
+}
 
-    pub fn run(&self) -> Result<(), Chip8Error> {
-        use sdl2::event::Event;
-        use sdl2::keyboard::Keycode;
+impl Drop for Renderer {
+    fn drop(&mut self) {
+        // Disconnecting the frames stops the display thread.
+        self.frames = None;
+        if let Some(display) = self.display.take() {
+            let _ = display.join();
+        }
+    }
+}
+
+impl Frontend for Renderer {
+    fn present(&mut self, gfx: &GfxMemory) -> Result<(), Chip8Error> {
+        if let Ok(Err(err)) = self.status.try_recv() {
+            return Err(err);
+        }
+        if let Some(ref mut frames) = self.frames {
+            frames.send(gfx);
+        }
+        self.limiter.wait();
+        Ok(())
+    }
+
+    fn poll_input(&mut self, input: &mut InputQueue) {
+        for (key, pressed) in self.keys.try_iter() {
+            input.push(key, pressed);
+        }
+    }
+
+    fn play_audio(&mut self, playing: bool) {
+        if playing != self.playing {
+            self.playing = playing;
+            let _ = self.commands.send(Command::PlayAudio(playing));
+        }
+    }
+
+    fn wait_for_key(&mut self, waiting: bool) {
+        if waiting != self.waiting_for_key {
+            self.waiting_for_key = waiting;
+            let _ = self.commands.send(Command::WaitForKey(waiting));
+        }
+    }
+
+    fn quit_requested(&self) -> bool {
+        self.quit.load(Ordering::SeqCst)
+    }
+}
+
+/// The SDL window, audio device and input devices, owned by the display thread.
+struct Display {
+    renderer: sdl2::render::Renderer<'static>,
+    events: sdl2::EventPump,
+    audio: AudioDevice<SquareWave>,
+    muted: bool,
+    keymap: KeyMap,
+    padmap: PadMap,
+    axes: AxisState,
+    scale: usize,
+    palette: Palette,
+    controller_subsys: sdl2::GameControllerSubsystem,
+    // Open game controllers by instance id.
+    controllers: HashMap<i32, GameController>,
+    // Keeps SDL initialized for as long as the display lives.
+    _sdl_context: sdl2::Sdl,
+}
+
+impl Display {
+    fn new(options: frontend::Options) -> Result<Display, Chip8Error> {
+        let sdl_context = sdl2::init()?;
+        let video_subsys = sdl_context.video()?;
+        let window = video_subsys
+            .window("chip8",
+                    (DISPLAY_WIDTH * options.scale) as u32,
+                    (DISPLAY_HEIGHT * options.scale) as u32)
+            .position_centered()
+            .opengl()
+            .build()?;
 
-        let sdl_context = try!(sdl2::init());
-        let video_subsys = try!(sdl_context.video());
-        let window = try!(video_subsys
-                              .window("chip8",
-                                      (DISPLAY_WIDTH * PIXEL_SIZE) as u32,
-                                      (DISPLAY_HEIGHT * PIXEL_SIZE) as u32)
-                              .position_centered()
-                              .opengl()
-                              .build());
-
-        let mut renderer = try!(window.renderer().build());
-        renderer.set_draw_color(sdl2::pixels::Color::RGB(0, 0, 0));
+        let mut renderer = window.renderer().build()?;
+        let (red, green, blue) = options.palette.background;
+        renderer.set_draw_color(sdl2::pixels::Color::RGB(red, green, blue));
         renderer.clear();
         renderer.present();
 
-        let &(ref lock, ref condition) = &*self.started;
-        {
-            let mut start = lock.lock().unwrap();
-            *start = true;
-            condition.notify_all();
-        }
+        let audio_subsys = sdl_context.audio()?;
+        let desired_spec = AudioSpecDesired {
+            freq: Some(44100),
+            channels: Some(1),
+            samples: None,
+        };
+        let audio = audio_subsys.open_playback(None, &desired_spec, |spec| {
+            SquareWave {
+                phase_inc: options.audio.frequency / spec.freq as f32,
+                phase: 0.0,
+                volume: options.audio.volume,
+            }
+        })?;
 
-        let mut events = try!(sdl_context.event_pump());
-        'main: loop {
-            for event in events.poll_iter() {
-                match event {
-                    Event::Quit { .. } => break 'main,
-                    Event::KeyDown { keycode: Some(keycode), .. } => {
-                        match keycode {
-                            Keycode::Escape => break 'main,
-                            _ => self.key_press(keycode, 1),
-                        }
-                    }
-                    Event::KeyUp { keycode: Some(keycode), .. } => {
-                        match keycode {
-                            Keycode::Escape => break 'main,
-                            _ => self.key_press(keycode, 0),
-                        }
-                    }
-                    _ => {}
+        // Controllers connected at startup are reported as added devices as well.
+        let controller_subsys = sdl_context.game_controller()?;
+        let events = sdl_context.event_pump()?;
+        Ok(Display {
+               renderer,
+               events,
+               audio,
+               muted: options.audio.muted,
+               keymap: options.keymap,
+               padmap: options.padmap,
+               axes: AxisState::default(),
+               scale: options.scale,
+               palette: options.palette,
+               controller_subsys,
+               controllers: HashMap::new(),
+               _sdl_context: sdl_context,
+           })
+    }
+
+    /// Shows the frames of `frames` and handles `commands` and SDL events until the emulation
+    /// disconnects.
+    fn run(&mut self,
+           mut frames: FrameReceiver,
+           commands: &Receiver<Command>,
+           keys: &Sender<(u8, bool)>,
+           quit: &AtomicBool)
+           -> Result<(), Chip8Error> {
+        loop {
+            match frames.recv_timeout(Duration::from_millis(POLL_INTERVAL_MS)) {
+                Ok(gfx) => {
+                    self.render(gfx)?;
+                    self.renderer.present();
                 }
+                Err(RecvTimeoutError::Timeout) => {}
+                Err(RecvTimeoutError::Disconnected) => return Ok(()),
+            }
+            for command in commands.try_iter() {
+                match command {
+                    Command::PlayAudio(playing) => self.play_audio(playing),
+                    Command::WaitForKey(waiting) => self.set_title(waiting),
+                }
+            }
+            if self.poll_events(keys) {
+                quit.store(true, Ordering::SeqCst);
             }
-
-            try!(self.render(&mut renderer));
-            renderer.present();
         }
-
-        let &(ref lock, ref condition) = &*self.shutdown;
-        let mut stopped = lock.lock().unwrap();
-        *stopped = true;
-        condition.notify_all();
-        Ok(())
     }
 
-    fn render<'a>(&self, renderer: &mut sdl2::render::Renderer<'a>) -> Result<(), String> {
+    fn render(&mut self, gfx: &GfxMemory) -> Result<(), String> {
         for y in 0..DISPLAY_HEIGHT {
             for x in 0..DISPLAY_WIDTH {
                 let index = (y * DISPLAY_WIDTH) + x;
-                let color = if self.gfx.as_ref().lock().unwrap()[index] == 0 {
-                    sdl2::pixels::Color::RGB(0, 0, 0)
+                let (red, green, blue) = if gfx[index] == 0 {
+                    self.palette.background
                 } else {
-                    sdl2::pixels::Color::RGB(255, 255, 255)
+                    self.palette.foreground
                 };
 
-                let rectangle = sdl2::rect::Rect::new((x * PIXEL_SIZE) as i32,
-                                                      (y * PIXEL_SIZE) as i32,
-                                                      PIXEL_SIZE as u32,
-                                                      PIXEL_SIZE as u32);
-                renderer.set_draw_color(color);
-                try!(renderer.fill_rect(rectangle));
+                let rectangle = sdl2::rect::Rect::new((x * self.scale) as i32,
+                                                      (y * self.scale) as i32,
+                                                      self.scale as u32,
+                                                      self.scale as u32);
+                self.renderer.set_draw_color(sdl2::pixels::Color::RGB(red, green, blue));
+                self.renderer.fill_rect(rectangle)?;
             }
         }
 
         Ok(())
     }
 
-    fn key_press(&self, keycode: sdl2::keyboard::Keycode, up: u8) {
+    fn key_press(keymap: &KeyMap,
+                 keys: &Sender<(u8, bool)>,
+                 keycode: Option<sdl2::keyboard::Keycode>,
+                 scancode: Option<sdl2::keyboard::Scancode>,
+                 pressed: bool) {
+        let by_keycode =
+            keycode.and_then(|keycode| keymap.lookup(&HostKey::keycode(&keycode.name())));
+        let by_scancode =
+            scancode.and_then(|scancode| keymap.lookup(&HostKey::scancode(scancode.name())));
+        if let Some(key) = by_keycode.or(by_scancode) {
+            let _ = keys.send((key, pressed));
+        }
+    }
+
+    /// Sends the key changes of the keyboard and game controllers since the last call to
+    /// `keys`. Returns true if the user asked to quit.
+    fn poll_events(&mut self, keys: &Sender<(u8, bool)>) -> bool {
+        use sdl2::event::Event;
         use sdl2::keyboard::Keycode;
-        let mut keys = self.keys.lock().unwrap();
-        match keycode {
-            Keycode::Num1 => keys[0x1] = up,
-            Keycode::Num2 => keys[0x2] = up,
-            Keycode::Num3 => keys[0x3] = up,
-            Keycode::Num4 => keys[0xC] = up,
-            Keycode::Q => keys[0x4] = up,
-            Keycode::W => keys[0x5] = up,
-            Keycode::E => keys[0x6] = up,
-            Keycode::R => keys[0xD] = up,
-            Keycode::A => keys[0x7] = up,
-            Keycode::S => keys[0x8] = up,
-            Keycode::D => keys[0x9] = up,
-            Keycode::F => keys[0xE] = up,
-            Keycode::Y => keys[0xA] = up,
-            Keycode::X => keys[0x0] = up,
-            Keycode::C => keys[0xB] = up,
-            Keycode::V => keys[0xF] = up,
-            _ => {}
+
+        let mut quit = false;
+        for event in self.events.poll_iter() {
+            match event {
+                Event::Quit { .. } |
+                Event::KeyDown { keycode: Some(Keycode::Escape), .. } => quit = true,
+                Event::KeyDown { keycode, scancode, repeat: false, .. } => {
+                    Display::key_press(&self.keymap, keys, keycode, scancode, true)
+                }
+                Event::KeyUp { keycode, scancode, .. } => {
+                    Display::key_press(&self.keymap, keys, keycode, scancode, false)
+                }
+                Event::ControllerDeviceAdded { which, .. } => {
+                    if let Ok(controller) = self.controller_subsys.open(which as u32) {
+                        self.controllers.insert(controller.instance_id(), controller);
+                    }
+                }
+                Event::ControllerDeviceRemoved { which, .. } => {
+                    self.controllers.remove(&which);
+                }
+                Event::ControllerButtonDown { button, .. } => {
+                    if let Some(key) = self.padmap.lookup(&PadInput::button(&button.string())) {
+                        let _ = keys.send((key, true));
+                    }
+                }
+                Event::ControllerButtonUp { button, .. } => {
+                    if let Some(key) = self.padmap.lookup(&PadInput::button(&button.string())) {
+                        let _ = keys.send((key, false));
+                    }
+                }
+                Event::ControllerAxisMotion { axis, value, .. } => {
+                    for key in self.padmap.axis_keys(&mut self.axes, &axis.string(), value) {
+                        let _ = keys.send(key);
+                    }
+                }
+                _ => {}
+            }
         }
+        quit
     }
-}
 
+    fn play_audio(&mut self, playing: bool) {
+        if playing && !self.muted {
+            self.audio.resume();
+        } else {
+            self.audio.pause();
+        }
+    }
+
+    fn set_title(&mut self, waiting_for_key: bool) {
+        let title = if waiting_for_key {
+            "chip8 - waiting for key"
+        } else {
+            "chip8"
+        };
+        if let Some(window) = self.renderer.window_mut() {
+            let _ = window.set_title(title);
+        }
+    }
+}
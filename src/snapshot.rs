@@ -0,0 +1,235 @@
+//! Snapshot testing of the display.
+//!
+//! `assert_snapshot` compares the display with an expected ASCII file and panics with a diff
+//! when they differ. Running the tests with `CHIP8_BLESS=1` writes the current display to the
+//! expected files instead, which is how new snapshots are created.
+
+use std::env;
+use std::fs;
+use std::path::Path;
+
+use gfx::GfxMemory;
+
+use super::{DISPLAY_HEIGHT, DISPLAY_WIDTH};
+
+/// Environment variable that makes the assertions write their snapshots.
+pub const BLESS_VAR: &str = "CHIP8_BLESS";
+
+/// Returns the display as 32 lines of `#` for lit and `.` for dark pixels.
+pub fn ascii(gfx: &GfxMemory) -> String {
+    let mut text = String::with_capacity((DISPLAY_WIDTH + 1) * DISPLAY_HEIGHT);
+    for y in 0..DISPLAY_HEIGHT {
+        for x in 0..DISPLAY_WIDTH {
+            text.push(if gfx[y * DISPLAY_WIDTH + x] != 0 { '#' } else { '.' });
+        }
+        text.push('\n');
+    }
+    text
+}
+
+/// Encodes the display as grayscale PNG with `scale` image pixels per display pixel.
+pub fn png(gfx: &GfxMemory, scale: usize) -> Vec<u8> {
+    let width = DISPLAY_WIDTH * scale;
+    let height = DISPLAY_HEIGHT * scale;
+    let mut pixels = Vec::with_capacity((width + 1) * height);
+    for y in 0..height {
+        // Each line starts with its filter type, 0 for none.
+        pixels.push(0);
+        for x in 0..width {
+            let lit = gfx[y / scale * DISPLAY_WIDTH + x / scale] != 0;
+            pixels.push(if lit { 0xFF } else { 0x00 });
+        }
+    }
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&(width as u32).to_be_bytes());
+    header.extend_from_slice(&(height as u32).to_be_bytes());
+    // 8 bit grayscale, deflate, adaptive filtering, no interlacing.
+    header.extend_from_slice(&[8, 0, 0, 0, 0]);
+
+    let mut png = vec![0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+    chunk(&mut png, b"IHDR", &header);
+    chunk(&mut png, b"IDAT", &zlib_stored(&pixels));
+    chunk(&mut png, b"IEND", &[]);
+    png
+}
+
+fn chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    let start = png.len();
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let crc = crc32(&png[start..]);
+    png.extend_from_slice(&crc.to_be_bytes());
+}
+
+// Wraps `data` in a zlib stream of uncompressed deflate blocks.
+fn zlib_stored(data: &[u8]) -> Vec<u8> {
+    let mut stream = vec![0x78, 0x01];
+    let blocks: Vec<&[u8]> = data.chunks(0xFFFF).collect();
+    for (index, block) in blocks.iter().enumerate() {
+        stream.push((index + 1 == blocks.len()) as u8);
+        let length = block.len() as u16;
+        stream.extend_from_slice(&length.to_le_bytes());
+        stream.extend_from_slice(&(!length).to_le_bytes());
+        stream.extend_from_slice(block);
+    }
+    stream.extend_from_slice(&adler32(data).to_be_bytes());
+    stream
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 { crc >> 1 ^ 0xEDB8_8320 } else { crc >> 1 };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    b << 16 | a
+}
+
+/// Shows `expected` and `actual` side by side, marking the lines that differ with `!`.
+pub fn diff(expected: &str, actual: &str) -> String {
+    let expected: Vec<&str> = expected.lines().collect();
+    let actual: Vec<&str> = actual.lines().collect();
+    let width = expected.iter().map(|line| line.len()).max().unwrap_or(0).max("expected".len());
+
+    let mut text = format!("   {:<width$}   {}\n", "expected", "actual", width = width);
+    for index in 0..expected.len().max(actual.len()) {
+        let left = expected.get(index).cloned().unwrap_or("");
+        let right = actual.get(index).cloned().unwrap_or("");
+        let mark = if left == right { '|' } else { '!' };
+        text += &format!("{:>2} {:<width$} {} {}\n", index, left, mark, right, width = width);
+    }
+    text
+}
+
+/// Returns true when the snapshots are to be written rather than compared.
+pub fn blessing() -> bool {
+    env::var_os(BLESS_VAR).is_some_and(|value| value != "0")
+}
+
+/// Compares `actual` with the snapshot file at `path`, or writes it there when blessing.
+///
+/// Returns a message with a diff if the snapshot differs or is missing.
+pub fn check_text(path: &Path, actual: &str) -> Result<(), String> {
+    if blessing() {
+        return write(path, actual.as_bytes());
+    }
+    match fs::read_to_string(path) {
+        Ok(ref expected) if expected == actual => Ok(()),
+        Ok(expected) => {
+            Err(format!("Snapshot {} does not match, run with {}=1 to update it:\n{}",
+                        path.display(),
+                        BLESS_VAR,
+                        diff(&expected, actual)))
+        }
+        Err(_) => {
+            Err(format!("Snapshot {} is missing, run with {}=1 to create it:\n{}",
+                        path.display(),
+                        BLESS_VAR,
+                        actual))
+        }
+    }
+}
+
+/// Compares the PNG of the display with the file at `path`, or writes it there when blessing.
+/// The mismatching image is written next to it with the extension `actual.png`.
+pub fn check_png(path: &Path, gfx: &GfxMemory, scale: usize) -> Result<(), String> {
+    let actual = png(gfx, scale);
+    if blessing() {
+        return write(path, &actual);
+    }
+    match fs::read(path) {
+        Ok(ref expected) if *expected == actual => Ok(()),
+        result => {
+            let actual_path = path.with_extension("actual.png");
+            write(&actual_path, &actual)?;
+            let problem = if result.is_ok() { "does not match" } else { "is missing" };
+            Err(format!("Snapshot {} {}, see {} or run with {}=1 to update it:\n{}",
+                        path.display(),
+                        problem,
+                        actual_path.display(),
+                        BLESS_VAR,
+                        ascii(gfx)))
+        }
+    }
+}
+
+fn write(path: &Path, data: &[u8]) -> Result<(), String> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|err| err.to_string())?;
+    }
+    fs::write(path, data).map_err(|err| format!("{}: {}", path.display(), err))
+}
+
+/// Panics unless the display matches the ASCII snapshot at `path`.
+pub fn assert_snapshot<P: AsRef<Path>>(path: P, gfx: &GfxMemory) {
+    if let Err(msg) = check_text(path.as_ref(), &ascii(gfx)) {
+        panic!("{}", msg);
+    }
+}
+
+/// Panics unless the display matches the PNG snapshot at `path`.
+pub fn assert_png_snapshot<P: AsRef<Path>>(path: P, gfx: &GfxMemory, scale: usize) {
+    if let Err(msg) = check_png(path.as_ref(), gfx, scale) {
+        panic!("{}", msg);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn snapshot_ascii() {
+        let mut gfx = GfxMemory::new();
+        gfx[1] = 1;
+        gfx[DISPLAY_WIDTH] = 1;
+        let text = ascii(&gfx);
+        let lines: Vec<&str> = text.lines().collect();
+        assert_eq!(lines.len(), DISPLAY_HEIGHT);
+        assert_eq!(&lines[0][..4], ".#..");
+        assert_eq!(&lines[1][..4], "#...");
+    }
+
+    #[test]
+    fn snapshot_png() {
+        let mut gfx = GfxMemory::new();
+        gfx[0] = 1;
+        let png = png(&gfx, 2);
+        assert_eq!(&png[..8], &[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A]);
+        // IHDR: 128 x 64
+        assert_eq!(&png[12..24], b"IHDR\0\0\0\x80\0\0\0\x40");
+        assert_eq!(&png[png.len() - 8..png.len() - 4], b"IEND");
+        assert_eq!(crc32(b"IEND"), 0xAE42_6082);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+    }
+
+    #[test]
+    fn snapshot_diff() {
+        let diff = diff("#.\n..\n", "#.\n.#\n");
+        assert_eq!(diff,
+                   "   expected   actual\n 0 #.       | #.\n 1 ..       ! .#\n");
+    }
+
+    #[test]
+    fn snapshot_check_missing() {
+        if blessing() {
+            return;
+        }
+        let err = check_text(Path::new("/nonexistent/snapshot.txt"), "..\n").unwrap_err();
+        assert!(err.contains("is missing"));
+        assert!(err.contains(BLESS_VAR));
+    }
+}
@@ -1,5 +1,22 @@
-const STACK_SIZE: usize = 16;
+pub const STACK_SIZE: usize = 16;
 
+/// Error of a call with a full stack or a return with an empty one.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StackError {
+    Overflow,
+    Underflow,
+}
+
+impl std::fmt::Display for StackError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match *self {
+            StackError::Overflow => f.write_str("Stack overflow"),
+            StackError::Underflow => f.write_str("Stack underflow"),
+        }
+    }
+}
+
+/// Return addresses of `2NNN`, at most `STACK_SIZE` of them.
 pub struct Stack {
     stack: [u16; STACK_SIZE],
     stack_pointer: u16,
@@ -13,20 +30,31 @@ impl Stack {
         }
     }
 
-    pub fn push(&mut self, reg: u16) {
-        self.stack_pointer += 1;
+    pub fn push(&mut self, reg: u16) -> Result<(), StackError> {
+        if self.stack_pointer as usize == STACK_SIZE {
+            return Err(StackError::Overflow);
+        }
         self.stack[self.stack_pointer as usize] = reg;
+        self.stack_pointer += 1;
+        Ok(())
     }
 
-    pub fn pop(&mut self) -> u16 {
-        let ret_val = self.stack[self.stack_pointer as usize];
+    pub fn pop(&mut self) -> Result<u16, StackError> {
+        if self.stack_pointer == 0 {
+            return Err(StackError::Underflow);
+        }
         self.stack_pointer -= 1;
-        ret_val
+        Ok(self.stack[self.stack_pointer as usize])
+    }
+
+    /// Returns the number of return addresses on the stack.
+    pub fn depth(&self) -> usize {
+        self.stack_pointer as usize
     }
 
     #[cfg(test)]
     pub fn current(&self) -> u16 {
-        self.stack[self.stack_pointer as usize]
+        self.stack[self.stack_pointer as usize - 1]
     }
 
     #[cfg(test)]
@@ -35,6 +63,12 @@ impl Stack {
     }
 }
 
+impl Default for Stack {
+    fn default() -> Stack {
+        Stack::new()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -43,7 +77,7 @@ mod tests {
     fn stack_push() {
         let mut stack = Stack::new();
         assert_eq!(stack.get_pointer(), 0);
-        stack.push(0x1234);
+        stack.push(0x1234).unwrap();
         assert_eq!(stack.get_pointer(), 1);
         assert_eq!(stack.current(), 0x1234);
     }
@@ -52,9 +86,25 @@ mod tests {
     fn stack_pop() {
         let mut stack = Stack::new();
         assert_eq!(stack.get_pointer(), 0);
-        stack.push(0x1234);
+        stack.push(0x1234).unwrap();
         assert_eq!(stack.get_pointer(), 1);
-        assert_eq!(stack.pop(), 0x1234);
+        assert_eq!(stack.pop(), Ok(0x1234));
         assert_eq!(stack.get_pointer(), 0);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn stack_overflow_underflow() {
+        let mut stack = Stack::new();
+        for address in 0..STACK_SIZE as u16 {
+            stack.push(address).unwrap();
+        }
+        assert_eq!(stack.depth(), STACK_SIZE);
+        assert_eq!(stack.push(0x0200), Err(StackError::Overflow));
+        assert_eq!(stack.depth(), STACK_SIZE);
+        assert_eq!(stack.pop(), Ok(0x000F));
+
+        let mut stack = Stack::new();
+        assert_eq!(stack.pop(), Err(StackError::Underflow));
+        assert_eq!(stack.depth(), 0);
+    }
+}
@@ -0,0 +1,109 @@
+use std::io::{self, Write};
+use std::thread;
+
+use super::*;
+
+use gfx::GfxMemory;
+use input::InputQueue;
+use error::Chip8Error;
+use frontend::{Frontend, FrameLimiter};
+use frame::{self, FrameSender};
+
+/// Frontend drawing the display with ANSI escape codes. It has no input or audio.
+///
+/// Frames are printed on a separate thread so a slow terminal never stalls emulation.
+pub struct Terminal {
+    frames: Option<FrameSender>,
+    printer: Option<thread::JoinHandle<io::Result<()>>>,
+    limiter: FrameLimiter,
+}
+
+impl Terminal {
+    pub fn new() -> Terminal {
+        let (sender, mut receiver) = frame::channel();
+        let printer = thread::spawn(move || {
+            let stdout = io::stdout();
+            while let Some(gfx) = receiver.recv() {
+                let mut handle = stdout.lock();
+                write!(handle, "\x1B[H{}", render(gfx))?;
+                handle.flush()?;
+            }
+            Ok(())
+        });
+
+        Terminal {
+            frames: Some(sender),
+            printer: Some(printer),
+            limiter: FrameLimiter::new(),
+        }
+    }
+}
+
+impl Default for Terminal {
+    fn default() -> Terminal {
+        Terminal::new()
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        self.frames = None;
+        if let Some(printer) = self.printer.take() {
+            let _ = printer.join();
+        }
+    }
+}
+
+/// Renders two display rows per text line using half block characters.
+pub fn render(gfx: &GfxMemory) -> String {
+    let mut output = String::new();
+    for y in 0..(DISPLAY_HEIGHT / 2) {
+        for x in 0..DISPLAY_WIDTH {
+            let top = gfx[(y * 2) * DISPLAY_WIDTH + x] != 0;
+            let bottom = gfx[(y * 2 + 1) * DISPLAY_WIDTH + x] != 0;
+            output.push(match (top, bottom) {
+                            (true, true) => '█',
+                            (true, false) => '▀',
+                            (false, true) => '▄',
+                            (false, false) => ' ',
+                        });
+        }
+        output.push('\n');
+    }
+    output
+}
+
+impl Frontend for Terminal {
+    fn present(&mut self, gfx: &GfxMemory) -> Result<(), Chip8Error> {
+        if let Some(ref mut frames) = self.frames {
+            frames.send(gfx);
+        }
+        self.limiter.wait();
+        Ok(())
+    }
+
+    fn poll_input(&mut self, _: &mut InputQueue) {}
+
+    fn play_audio(&mut self, _: bool) {}
+
+    fn quit_requested(&self) -> bool {
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_half_blocks() {
+        let mut gfx = GfxMemory::new();
+        gfx[0] = 0x01;
+        gfx[DISPLAY_WIDTH + 1] = 0x01;
+        gfx[2] = 0x01;
+        gfx[DISPLAY_WIDTH + 2] = 0x01;
+        let output = render(&gfx);
+        assert!(output.starts_with("▀▄█ "));
+        assert_eq!(output.lines().count(), DISPLAY_HEIGHT / 2);
+    }
+}
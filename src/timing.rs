@@ -0,0 +1,192 @@
+use instruction::Instruction;
+use register::Register;
+
+/// Machine cycles of a COSMAC VIP frame: 262 lines of 14 machine cycles each. A machine cycle
+/// is 8 clocks of the 1.7609 MHz CDP1802.
+pub const VIP_FRAME_CYCLES: u32 = 262 * 14;
+/// Machine cycles per frame taken by the CDP1861 display DMA, which reads 8 bytes for each of
+/// the 128 lines it shows, and by the interrupt routine around it.
+pub const VIP_DISPLAY_CYCLES: u32 = 128 * 8 + 46;
+/// Machine cycles left to the interpreter in each frame.
+pub const VIP_FRAME_BUDGET: u32 = VIP_FRAME_CYCLES - VIP_DISPLAY_CYCLES;
+
+pub const PRESETS: [&str; 2] = ["instructions", "vip"];
+
+// Costs in machine cycles, modelled on the interpreter routines as Laurence Scotford's
+// "CHIP-8 on the COSMAC VIP" series walks through them: a common fetch and dispatch, then the
+// routine of each instruction with its loops over sprite rows, decimal digits or registers.
+// The figures are an estimate from that analysis and have not been checked against a
+// measured table; `timing_vip_costs` pins them so a correction shows up as a test change.
+
+// Fetching, decoding and dispatching an instruction, common to all of them.
+const FETCH: u32 = 40;
+// Extra cost of a skip instruction that skips.
+const SKIP: u32 = 4;
+// DXYN: setup, each sprite row, and each bit the row is shifted to its position in the byte.
+const DRAW: u32 = 68;
+const DRAW_ROW: u32 = 34;
+const DRAW_SHIFT: u32 = 8;
+// FX33: setup and each subtraction of 100, 10 or 1 while converting to decimal.
+const BCD: u32 = 76;
+const BCD_STEP: u32 = 8;
+// FX55/FX65: setup and each register copied.
+const COPY: u32 = 14;
+const COPY_REGISTER: u32 = 14;
+// 00E0 clears the 256 bytes of display memory.
+const CLEAR: u32 = 24 + 256 * 12;
+// Arithmetic and logic (8XYN), also charged for instructions the VIP does not have.
+const ALU: u32 = 44;
+
+/// How the scheduler spends a frame.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Timing {
+    /// A fixed number of instructions per frame (`Chip8::set_speed`), each counting the same.
+    #[default]
+    Instructions,
+    /// The machine cycles the COSMAC VIP interpreter spends on each instruction, within the
+    /// `VIP_FRAME_BUDGET` of a frame. Instructions that run over the budget delay the next
+    /// frame.
+    Vip,
+}
+
+impl Timing {
+    pub fn preset(name: &str) -> Option<Timing> {
+        match name.to_lowercase().as_str() {
+            "instructions" => Some(Timing::Instructions),
+            "vip" | "cosmac-vip" => Some(Timing::Vip),
+            _ => None,
+        }
+    }
+}
+
+/// Returns the machine cycles the COSMAC VIP interpreter takes for `instruction` with the
+/// registers `reg_v`, read before the instruction executes. Skips that skip take
+/// `vip_skip_cycles` on top.
+///
+/// `DXYN` depends on the number of rows and how far the sprite is shifted from a byte boundary,
+/// `FX33` on the digits of VX and `FX55`/`FX65` on the number of registers copied.
+pub fn vip_cycles(instruction: Instruction, reg_v: &Register) -> u32 {
+    FETCH +
+    match instruction {
+        Instruction::Sys(_) => 20,
+        Instruction::Cls => CLEAR,
+        Instruction::Ret => 10,
+        Instruction::Jp(_) => 12,
+        Instruction::Call(_) => 26,
+        Instruction::SeByte(..) |
+        Instruction::SneByte(..) => 10,
+        Instruction::SeReg(..) |
+        Instruction::SneReg(..) => 14,
+        Instruction::LdByte(..) => 6,
+        Instruction::AddByte(..) => 10,
+        Instruction::LdI(_) => 12,
+        Instruction::JpV0(_) => 22,
+        Instruction::Rnd(..) => 36,
+        Instruction::Drw { x, n, .. } => {
+            let shift = (reg_v[x as usize] % 8) as u32;
+            DRAW + n as u32 * (DRAW_ROW + DRAW_SHIFT * shift)
+        }
+        Instruction::Skp(_) |
+        Instruction::Sknp(_) => 14,
+        Instruction::LdVxDt(_) |
+        Instruction::LdVxK(_) |
+        Instruction::LdDt(_) |
+        Instruction::LdSt(_) => 10,
+        Instruction::AddI(_) |
+        Instruction::LdF(_) => 16,
+        Instruction::LdB(x) => {
+            let value = reg_v[x as usize] as u32;
+            BCD + BCD_STEP * (value / 100 + value / 10 % 10 + value % 10)
+        }
+        Instruction::LdIVx(x) |
+        Instruction::LdVxI(x) => COPY + COPY_REGISTER * (x as u32 + 1),
+        _ => ALU,
+    }
+}
+
+/// Returns the extra machine cycles of `instruction` when it skips the next instruction.
+pub fn vip_skip_cycles(instruction: Instruction) -> u32 {
+    match instruction {
+        Instruction::SeByte(..) |
+        Instruction::SneByte(..) |
+        Instruction::SeReg(..) |
+        Instruction::SneReg(..) |
+        Instruction::Skp(_) |
+        Instruction::Sknp(_) => SKIP,
+        _ => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timing_presets() {
+        for name in PRESETS.iter() {
+            assert!(Timing::preset(name).is_some());
+        }
+        assert_eq!(Timing::preset("VIP"), Some(Timing::Vip));
+        assert!(Timing::preset("eti660").is_none());
+    }
+
+    #[test]
+    fn timing_vip_cycles() {
+        let reg_v = Register::new();
+        assert_eq!(vip_cycles(Instruction::LdByte(0, 1), &reg_v), FETCH + 6);
+        assert!(vip_cycles(Instruction::Cls, &reg_v) > VIP_FRAME_BUDGET / 2);
+        assert_eq!(vip_skip_cycles(Instruction::Skp(0)), SKIP);
+        assert_eq!(vip_skip_cycles(Instruction::Jp(0x200)), 0);
+        assert_eq!(vip_cycles(Instruction::LdIVx(0xF), &reg_v),
+                   vip_cycles(Instruction::LdIVx(0), &reg_v) + 15 * COPY_REGISTER);
+    }
+
+    #[test]
+    fn timing_vip_costs() {
+        let reg_v = Register::new();
+        let costs = [(Instruction::Sys(0x100), 60),
+                     (Instruction::Cls, 3136),
+                     (Instruction::Ret, 50),
+                     (Instruction::Jp(0x200), 52),
+                     (Instruction::Call(0x200), 66),
+                     (Instruction::SeByte(0, 0), 50),
+                     (Instruction::SeReg(0, 1), 54),
+                     (Instruction::LdByte(0, 0), 46),
+                     (Instruction::AddByte(0, 1), 50),
+                     (Instruction::LdReg(0, 1), 84),
+                     (Instruction::LdI(0x200), 52),
+                     (Instruction::JpV0(0x200), 62),
+                     (Instruction::Rnd(0, 0xFF), 76),
+                     (Instruction::Drw { x: 0, y: 0, n: 1 }, 142),
+                     (Instruction::Skp(0), 54),
+                     (Instruction::LdVxDt(0), 50),
+                     (Instruction::AddI(0), 56),
+                     (Instruction::LdF(0), 56),
+                     (Instruction::LdB(0), 116),
+                     (Instruction::LdIVx(0), 68),
+                     (Instruction::LdVxI(2), 96)];
+        for &(instruction, cycles) in costs.iter() {
+            assert_eq!(vip_cycles(instruction, &reg_v), cycles, "{:?}", instruction);
+        }
+    }
+
+    #[test]
+    fn timing_vip_cycles_depend_on_data() {
+        let mut reg_v = Register::new();
+        let draw = Instruction::Drw { x: 0, y: 1, n: 5 };
+        let bcd = Instruction::LdB(0);
+        let aligned = vip_cycles(draw, &reg_v);
+        let zero = vip_cycles(bcd, &reg_v);
+        assert_eq!(aligned, FETCH + DRAW + 5 * DRAW_ROW);
+        assert_eq!(zero, FETCH + BCD);
+
+        reg_v[0] = 13;
+        assert_eq!(vip_cycles(draw, &reg_v), aligned + 5 * 5 * DRAW_SHIFT);
+        assert_eq!(vip_cycles(bcd, &reg_v), zero + 4 * BCD_STEP);
+
+        reg_v[0] = 255;
+        assert_eq!(vip_cycles(bcd, &reg_v), zero + 12 * BCD_STEP);
+        assert_eq!(vip_cycles(Instruction::Drw { x: 0, y: 1, n: 0 }, &reg_v),
+                   FETCH + DRAW);
+    }
+}
@@ -0,0 +1,295 @@
+use cdp1802::{Bus, Cdp1802};
+use error::Chip8Error;
+use frontend::Frontend;
+use gfx::GfxMemory;
+use input::InputQueue;
+use keyboard::Keyboard;
+use layout::Layout;
+
+use super::DISPLAY_WIDTH;
+
+/// RAM of a COSMAC VIP with 4 KB, mirrored throughout the lower 32 KB.
+pub const RAM_SIZE: usize = 0x1000;
+/// Size of the monitor ROM at 0x8000.
+pub const MONITOR_SIZE: usize = 0x200;
+/// Size of the CHIP-8 interpreter at 0x0000.
+pub const INTERPRETER_SIZE: usize = 0x200;
+pub const PROGRAM_START: usize = 0x200;
+
+// CDP1861 timing: a frame has 262 lines of 14 machine cycles. The 128 displayed lines start
+// at line 80, each with 8 DMA cycles followed by 6 cycles for the CPU. The interrupt is
+// requested 29 cycles before the first DMA cycle and EF1 is asserted during the 4 lines before
+// the display starts and ends.
+const LINE_CYCLES: u32 = 14;
+const FRAME_CYCLES: u32 = 262 * LINE_CYCLES;
+const FIRST_LINE: u32 = 80;
+const DISPLAY_LINES: u32 = 128;
+const DMA_CYCLES: u32 = 8;
+const INTERRUPT_CYCLE: u32 = FIRST_LINE * LINE_CYCLES - 29;
+
+/// Memory map and I/O of the VIP: RAM, the monitor ROM, the CDP1861 display chip and the hex
+/// keypad.
+struct VipBus {
+    ram: Vec<u8>,
+    monitor: Vec<u8>,
+    // After a reset the monitor also appears at 0x0000 until an address with A15 set is read.
+    monitor_at_zero: bool,
+    keys: Keyboard,
+    // Key selected by `OUT 2`, reported on EF3 while it is pressed.
+    key_latch: u8,
+    // Turned on by `INP 1` and off by `OUT 1`.
+    display: bool,
+    line: u32,
+}
+
+impl Bus for VipBus {
+    fn read(&mut self, address: u16) -> u8 {
+        let address = address as usize;
+        if address & 0x8000 != 0 {
+            self.monitor_at_zero = false;
+            self.monitor[address % MONITOR_SIZE]
+        } else if self.monitor_at_zero {
+            self.monitor[address % MONITOR_SIZE]
+        } else {
+            self.ram[address % RAM_SIZE]
+        }
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        let address = address as usize;
+        if address & 0x8000 == 0 {
+            self.ram[address % RAM_SIZE] = value;
+        }
+    }
+
+    fn output(&mut self, port: u8, value: u8) {
+        match port {
+            1 => self.display = false,
+            2 => self.key_latch = value & 0x0F,
+            _ => {}
+        }
+    }
+
+    fn input(&mut self, port: u8) -> u8 {
+        if port == 1 {
+            self.display = true;
+        }
+        0
+    }
+
+    fn flag(&mut self, flag: u8) -> bool {
+        match flag {
+            1 => {
+                let last_line = FIRST_LINE + DISPLAY_LINES;
+                (FIRST_LINE - 4..FIRST_LINE).contains(&self.line) ||
+                (last_line - 4..last_line).contains(&self.line)
+            }
+            3 => self.keys[self.key_latch as usize] != 0,
+            _ => false,
+        }
+    }
+}
+
+/// COSMAC VIP running its original CHIP-8 interpreter on an emulated CDP1802.
+///
+/// The monitor ROM and the interpreter are not included and have to be loaded from dumps. Like
+/// the real machine, the VIP starts in the monitor, which jumps to the interpreter at 0x0000
+/// unless key C is held. The display and the keypad use the same `GfxMemory` and `Keyboard` as
+/// `Chip8`, so frontends work unchanged.
+pub struct Vip {
+    cpu: Cdp1802,
+    bus: VipBus,
+    gfx: GfxMemory,
+    input: InputQueue,
+    // Machine cycles into the current frame.
+    cycle: u32,
+}
+
+impl Vip {
+    pub fn new(monitor: &[u8], interpreter: &[u8]) -> Result<Vip, Chip8Error> {
+        if monitor.len() > MONITOR_SIZE || interpreter.len() > INTERPRETER_SIZE {
+            let msg = format!("The VIP monitor and interpreter have at most {} bytes each",
+                              MONITOR_SIZE);
+            return Err(Chip8Error::Message(msg));
+        }
+        let mut bus = VipBus {
+            ram: vec![0; RAM_SIZE],
+            monitor: vec![0; MONITOR_SIZE],
+            monitor_at_zero: true,
+            keys: Keyboard::new(),
+            key_latch: 0,
+            display: false,
+            line: 0,
+        };
+        bus.monitor[..monitor.len()].copy_from_slice(monitor);
+        bus.ram[..interpreter.len()].copy_from_slice(interpreter);
+        Ok(Vip {
+               cpu: Cdp1802::new(),
+               bus,
+               gfx: GfxMemory::new(),
+               input: InputQueue::new(),
+               cycle: 0,
+           })
+    }
+
+    /// Loads a CHIP-8 program at 0x200. It has to end before the memory the interpreter uses.
+    pub fn load_rom(&mut self, rom: &[u8]) -> Result<(), Chip8Error> {
+        let capacity = Layout::vip().capacity(PROGRAM_START);
+        if rom.len() > capacity {
+            let msg = format!("ROM of {} bytes does not fit into the {} bytes of the VIP",
+                              rom.len(),
+                              capacity);
+            return Err(Chip8Error::Message(msg));
+        }
+        self.bus.ram[PROGRAM_START..PROGRAM_START + rom.len()].copy_from_slice(rom);
+        Ok(())
+    }
+
+    pub fn run(&mut self, frontend: &mut dyn Frontend) -> Result<(), Chip8Error> {
+        while !frontend.quit_requested() {
+            frontend.poll_input(&mut self.input);
+            self.input.apply(&mut self.bus.keys);
+            self.step_frame();
+            frontend.play_audio(self.cpu.q);
+            frontend.present(&self.gfx)?;
+        }
+
+        Ok(())
+    }
+
+    /// Runs the CPU for one frame of the CDP1861 and copies the displayed lines to the
+    /// display. Each CHIP-8 row covers 4 lines, which the interpreter fills with the same bytes;
+    /// the row shows the first of them.
+    pub fn step_frame(&mut self) {
+        self.run_until(INTERRUPT_CYCLE, false);
+        let interrupt = self.bus.display;
+        self.run_until(FIRST_LINE * LINE_CYCLES, interrupt);
+        for line in 0..DISPLAY_LINES {
+            self.run_until((FIRST_LINE + line) * LINE_CYCLES, false);
+            if self.bus.display {
+                self.dma(line);
+            }
+        }
+        self.run_until(FRAME_CYCLES, false);
+        self.cycle -= FRAME_CYCLES;
+
+        if !self.bus.display {
+            self.gfx.clear();
+        }
+    }
+
+    // Executes instructions until `end`, acknowledging the interrupt first if it is requested.
+    // An instruction running past `end` delays what follows.
+    fn run_until(&mut self, end: u32, interrupt: bool) {
+        while self.cycle < end {
+            self.bus.line = self.cycle / LINE_CYCLES;
+            if interrupt && self.cpu.ie {
+                self.cpu.interrupt();
+                self.cycle += 1;
+            } else if self.cpu.idle {
+                self.cycle = end;
+            } else {
+                self.cycle += self.cpu.step(&mut self.bus);
+            }
+        }
+    }
+
+    fn dma(&mut self, line: u32) {
+        let row = line as usize / 4;
+        for byte in 0..DMA_CYCLES as usize {
+            let bits = self.cpu.dma_out(&mut self.bus);
+            if !line.is_multiple_of(4) {
+                continue;
+            }
+            for bit in 0..8 {
+                self.gfx[row * DISPLAY_WIDTH + byte * 8 + bit] = bits >> (7 - bit) & 0x01;
+            }
+        }
+        self.cycle += DMA_CYCLES;
+    }
+
+    pub fn gfx(&self) -> &GfxMemory {
+        &self.gfx
+    }
+
+    pub fn keys_mut(&mut self) -> &mut Keyboard {
+        &mut self.bus.keys
+    }
+
+    /// Returns true while the buzzer, driven by Q, sounds.
+    pub fn buzzer(&self) -> bool {
+        self.cpu.q
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Jumps from the mirrored monitor to 0x8003, which starts the interpreter with P = 3.
+    const MONITOR: [u8; 8] = [0xC0, 0x80, 0x03, 0xF8, 0x00, 0xA3, 0xB3, 0xD3];
+
+    // Points the interrupt to 0x0101 and the stack to 0x0EFF, turns on the display and Q and
+    // loops. The interrupt routine sets R0 to 0x0F00.
+    fn interpreter() -> Vec<u8> {
+        let mut code = vec![0xF8, 0x01, 0xB1, 0xF8, 0x01, 0xA1, 0xF8, 0x0E, 0xB2, 0xF8, 0xFF,
+                            0xA2, 0xE2, 0x69, 0x7B, 0x30, 0x0F];
+        code.resize(0x100, 0);
+        code.extend_from_slice(&[0x70, 0x22, 0x78, 0xF8, 0x0F, 0xB0, 0xF8, 0x00, 0xA0, 0x30,
+                                 0x00]);
+        code
+    }
+
+    #[test]
+    fn vip_display() {
+        let mut vip = Vip::new(&MONITOR, &interpreter()).unwrap();
+        // The interrupt routine does not repeat rows, so each line reads the next 8 bytes. Row 0
+        // shows the first of its 4 lines, 0x0F00, and row 1 starts at 0x0F20.
+        vip.bus.ram[0x0F00] = 0xF0;
+        vip.bus.ram[0x0F18] = 0xFF;
+        vip.bus.ram[0x0F20] = 0x81;
+        vip.step_frame();
+        vip.step_frame();
+
+        assert!(vip.buzzer());
+        assert!(!vip.bus.monitor_at_zero);
+        assert!((0..4).all(|x| vip.gfx()[x] == 1));
+        assert!((4..8).all(|x| vip.gfx()[x] == 0));
+        assert_eq!(vip.gfx()[DISPLAY_WIDTH], 1);
+        assert_eq!(vip.gfx()[DISPLAY_WIDTH + 6], 0);
+        assert_eq!(vip.gfx()[DISPLAY_WIDTH + 7], 1);
+        // The interrupt routine returned to the main loop.
+        assert_eq!(vip.cpu.p, 3);
+        assert!(vip.cpu.ie);
+        assert!(vip.cycle < 3);
+    }
+
+    #[test]
+    fn vip_display_off() {
+        let mut vip = Vip::new(&MONITOR, &[0x30, 0x00]).unwrap();
+        vip.gfx[0] = 1;
+        vip.step_frame();
+        assert_eq!(vip.gfx()[0], 0);
+        assert!(!vip.bus.display);
+    }
+
+    #[test]
+    fn vip_keypad() {
+        let mut vip = Vip::new(&MONITOR, &[]).unwrap();
+        vip.bus.output(2, 0x15);
+        assert!(!vip.bus.flag(3));
+        vip.keys_mut()[5] = 1;
+        assert!(vip.bus.flag(3));
+        vip.bus.output(2, 0x06);
+        assert!(!vip.bus.flag(3));
+    }
+
+    #[test]
+    fn vip_load_rom() {
+        let mut vip = Vip::new(&MONITOR, &[]).unwrap();
+        vip.load_rom(&[0x12, 0x00]).unwrap();
+        assert_eq!(vip.bus.ram[PROGRAM_START], 0x12);
+        assert!(vip.load_rom(&vec![0; RAM_SIZE]).is_err());
+        assert!(Vip::new(&[0; MONITOR_SIZE + 1], &[]).is_err());
+    }
+}
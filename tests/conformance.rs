@@ -0,0 +1,205 @@
+//! Runs the community test ROMs listed in `tests/conformance/suite.toml` and compares their
+//! final display with golden images. A test whose ROM is present fails when the display
+//! differs from the golden image of a preset it is required to pass, or when that golden image
+//! is missing. Tests without a ROM are skipped with a message on stderr, or fail when
+//! `CHIP8_TEST_ROMS` names the directory the ROMs should be in. `CHIP8_CONFORMANCE_REPORT=1` prints which tests pass under each quirks preset, and
+//! `CHIP8_BLESS=1` writes the golden images of the presets each test is required to pass.
+
+extern crate chip8;
+extern crate toml;
+#[macro_use]
+extern crate serde_derive;
+
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use chip8::chip8::Chip8;
+use chip8::error::Chip8Error;
+use chip8::frontend::Frontend;
+use chip8::headless::Headless;
+use chip8::input::{Recording, Replay};
+use chip8::quirks::{self, Quirks};
+use chip8::snapshot;
+
+#[derive(Debug, Deserialize)]
+struct Suite {
+    test: Vec<Test>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Test {
+    name: String,
+    rom: String,
+    frames: u64,
+    #[serde(default)]
+    input: String,
+    #[serde(default)]
+    presets: Vec<String>,
+}
+
+/// Outcome of one test under one quirks preset.
+#[derive(Debug, PartialEq)]
+enum Outcome {
+    Pass,
+    /// The display differs from the golden image, as described by the message.
+    Fail(String),
+    NoRom,
+    NoGolden,
+}
+
+impl Outcome {
+    fn symbol(&self) -> &'static str {
+        match *self {
+            Outcome::Pass => "pass",
+            Outcome::Fail(_) => "FAIL",
+            Outcome::NoRom => "no rom",
+            Outcome::NoGolden => "no golden",
+        }
+    }
+}
+
+fn suite_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("conformance")
+}
+
+fn rom_dir() -> PathBuf {
+    env::var_os("CHIP8_TEST_ROMS")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| suite_dir().join("roms"))
+}
+
+/// Runs `rom` headless without the ROM database, so only `quirks` decide the behaviour.
+fn run(rom: Vec<u8>, test: &Test, quirks: Quirks) -> Result<String, Chip8Error> {
+    let mut chip = Chip8::new();
+    chip.set_database(None);
+    chip.set_seed(0);
+    chip.initialize();
+    chip.load_rom(rom)?;
+    chip.set_quirks(quirks);
+
+    let headless: Box<dyn Frontend> = Box::new(Headless::new(Some(test.frames)));
+    let mut frontend = Replay::new(headless, Recording::parse(&test.input)?);
+    chip.run(&mut frontend)?;
+    Ok(snapshot::ascii(chip.gfx()))
+}
+
+// Returns the golden image of `preset`, falling back to the one shared by all presets.
+fn golden(test: &Test, preset: &str) -> PathBuf {
+    let dir = suite_dir().join("golden");
+    let path = dir.join(format!("{}.{}.txt", test.name, preset));
+    if path.exists() {
+        path
+    } else {
+        dir.join(format!("{}.txt", test.name))
+    }
+}
+
+// Runs `test` under `preset`. The golden images of the required presets are written instead
+// when blessing snapshots.
+fn check(test: &Test, preset: &str, required: bool) -> Outcome {
+    let rom = match fs::read(rom_dir().join(&test.rom)) {
+        Ok(rom) => rom,
+        Err(_) => return Outcome::NoRom,
+    };
+    let path = golden(test, preset);
+    let blessing = required && snapshot::blessing();
+    if !path.exists() && !blessing {
+        return Outcome::NoGolden;
+    }
+    let display = match run(rom, test, Quirks::preset(preset).unwrap()) {
+        Ok(display) => display,
+        Err(err) => return Outcome::Fail(err.to_string()),
+    };
+    let result = if required {
+        snapshot::check_text(&path, &display)
+    } else {
+        let expected = fs::read_to_string(&path).unwrap_or_default();
+        if expected == display {
+            Ok(())
+        } else {
+            Err(snapshot::diff(&expected, &display))
+        }
+    };
+    match result {
+        Ok(()) => Outcome::Pass,
+        Err(msg) => Outcome::Fail(msg),
+    }
+}
+
+#[test]
+fn conformance() {
+    let text = fs::read_to_string(suite_dir().join("suite.toml")).unwrap();
+    let suite: Suite = toml::from_str(&text).unwrap();
+
+    let mut report = format!("{:<16}", "test");
+    for preset in quirks::PRESETS.iter() {
+        report += &format!("{:<12}", preset);
+    }
+    report.push('\n');
+
+    let mut failures = Vec::new();
+    let mut missing = Vec::new();
+    for test in &suite.test {
+        report += &format!("{:<16}", test.name);
+        for preset in quirks::PRESETS.iter() {
+            let required = test.presets.iter().any(|name| name == preset);
+            let outcome = check(test, preset, required);
+            if required {
+                match outcome {
+                    Outcome::Fail(ref msg) => {
+                        failures.push(format!("{} ({}): {}", test.name, preset, msg))
+                    }
+                    Outcome::NoGolden => {
+                        failures.push(format!("{} ({}): no golden image", test.name, preset))
+                    }
+                    Outcome::NoRom if !missing.contains(&test.rom) => {
+                        missing.push(test.rom.clone())
+                    }
+                    _ => {}
+                }
+            }
+            let mark = if required { "" } else { "*" };
+            report += &format!("{:<12}", format!("{}{}", outcome.symbol(), mark));
+        }
+        report.push('\n');
+    }
+    report += "* not required to pass under this preset\n";
+    // The harness captures the output of passing tests, so skipped tests are reported on stderr
+    // directly.
+    if !missing.is_empty() {
+        let roms = format!("{} ROMs missing from {}: {}",
+                           missing.len(),
+                           rom_dir().display(),
+                           missing.join(", "));
+        if env::var_os("CHIP8_TEST_ROMS").is_some() {
+            failures.push(roms);
+        } else {
+            writeln!(io::stderr(),
+                     "conformance: skipped the tests of {}, see tests/conformance/fetch.sh",
+                     roms)
+                .unwrap();
+        }
+    }
+    if env::var_os("CHIP8_CONFORMANCE_REPORT").is_some() {
+        println!("{}", report);
+    }
+
+    assert!(failures.is_empty(),
+            "Failed:\n{}\n\n{}",
+            failures.join("\n"),
+            report);
+}
+
+#[test]
+fn conformance_suite_is_valid() {
+    let text = fs::read_to_string(suite_dir().join("suite.toml")).unwrap();
+    let suite: Suite = toml::from_str(&text).unwrap();
+    for test in &suite.test {
+        assert!(Recording::parse(&test.input).is_ok(), "{}", test.name);
+        for preset in &test.presets {
+            assert!(Quirks::preset(preset).is_some(), "{}: {}", test.name, preset);
+        }
+    }
+}
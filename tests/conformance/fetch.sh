@@ -0,0 +1,14 @@
+#!/bin/sh
+# Downloads the Timendus chip8-test-suite ROMs into tests/conformance/roms. Set
+# CHIP8_TEST_SUITE_REF to a tag or commit of Timendus/chip8-test-suite to pin them. BC_test is
+# not published there and has to be copied in by hand.
+set -e
+
+ref=${CHIP8_TEST_SUITE_REF:-main}
+url=https://raw.githubusercontent.com/Timendus/chip8-test-suite/$ref/bin
+dir=$(dirname "$0")/roms
+
+for rom in 1-chip8-logo.ch8 2-ibm-logo.ch8 3-corax+.ch8 4-flags.ch8 5-quirks.ch8 6-keypad.ch8; do
+    curl --fail --silent --show-error --location -o "$dir/$rom.tmp" "$url/$rom"
+    mv "$dir/$rom.tmp" "$dir/$rom"
+done
@@ -0,0 +1,100 @@
+//! Runs random programs on `Chip8` and on the reference interpreter in `tests/reference` under
+//! each quirks preset and compares them after every instruction. The `differential` fuzz
+//! target does the same with the programs libFuzzer generates.
+
+extern crate chip8;
+extern crate rand;
+
+mod reference;
+
+use rand::{Rng, SeedableRng, XorShiftRng};
+
+use chip8::chip8::PROGRAM_START;
+use chip8::quirks::{self, Quirks};
+
+const PROGRAMS: u32 = 100;
+const LENGTH: usize = 64;
+const STEPS: usize = 200;
+
+// FXNN instructions the reference implements, without FX0A, which ends the comparison.
+const MISC: [u8; 9] = [0x07, 0x15, 0x18, 0x1E, 0x29, 0x30, 0x33, 0x55, 0x65];
+// 8XYN instructions, mostly arithmetic.
+const ALU: [u8; 12] = [0x0, 0x1, 0x2, 0x3, 0x4, 0x4, 0x5, 0x5, 0x6, 0x7, 0x7, 0xE];
+
+// Returns a random instruction, mostly valid ones with jumps into the program.
+fn instruction(rng: &mut XorShiftRng) -> u16 {
+    let x = rng.gen_range(0, 16) << 8;
+    let y = rng.gen_range(0, 16) << 4;
+    let byte = rng.gen::<u8>() as u16;
+    let target = (PROGRAM_START + 2 * rng.gen_range(0, LENGTH)) as u16;
+    match rng.gen_range(0, 18) {
+        0 => if rng.gen() { 0x00E0 } else { 0x00EE },
+        1 => 0x1000 | target,
+        2 => 0x2000 | target,
+        3 => 0x3000 | x | byte,
+        4 => 0x4000 | x | byte,
+        5 => 0x5000 | x | y,
+        6 => 0x6000 | x | byte,
+        7 => 0x7000 | x | byte,
+        8..=10 => 0x8000 | x | y | *rng.choose(&ALU).unwrap() as u16,
+        11 => 0x9000 | x | y,
+        12 => 0xA000 | rng.gen_range(0, 0x1000),
+        13 => 0xB000 | (target - 0x100),
+        14 => 0xC000 | x | byte,
+        15 => 0xD000 | x | y | rng.gen_range(0, 16),
+        16 => if rng.gen() { 0xE09E | x } else { 0xE0A1 | x },
+        _ => 0xF000 | x | *rng.choose(&MISC).unwrap() as u16,
+    }
+}
+
+fn program(seed: u32) -> Vec<u8> {
+    let mut rng = XorShiftRng::from_seed([0x4348_4950, 0x2D38, seed, 1]);
+    let mut program = Vec::with_capacity(LENGTH * 2);
+    for _ in 0..LENGTH {
+        let word = if rng.gen_weighted_bool(50) {
+            rng.gen()
+        } else {
+            instruction(&mut rng)
+        };
+        program.push((word >> 8) as u8);
+        program.push(word as u8);
+    }
+    program
+}
+
+#[test]
+fn differential() {
+    for preset in quirks::PRESETS.iter() {
+        for seed in 0..PROGRAMS {
+            let program = program(seed);
+            if let Err(msg) = reference::run(&program, Quirks::preset(preset).unwrap(), STEPS) {
+                panic!("Program {} differs under {} at {}\n{:02X?}",
+                       seed,
+                       preset,
+                       msg,
+                       program);
+            }
+        }
+    }
+}
+
+#[test]
+fn differential_flags() {
+    // 8XY4, 8XY5, 8XY7, 8XY6 and 8XYE with VF as an operand, and FX1E past 0xFFF.
+    let program = [0x6F, 0xFF, 0x60, 0x01, 0x80, 0xF4, 0x8F, 0x04, 0x6F, 0x10, 0x8F, 0x05,
+                   0x8F, 0xF7, 0x8F, 0x06, 0x8F, 0x0E, 0xAF, 0xF0, 0x6F, 0x20, 0xFF, 0x1E];
+    for preset in quirks::PRESETS.iter() {
+        reference::run(&program, Quirks::preset(preset).unwrap(), 12).unwrap();
+    }
+}
+
+#[test]
+fn differential_stack_faults() {
+    // A return with an empty stack and a subroutine calling itself until the stack is full.
+    let programs: [&[u8]; 2] = [&[0x00, 0xEE], &[0x22, 0x00]];
+    for program in programs.iter() {
+        for preset in quirks::PRESETS.iter() {
+            reference::run(program, Quirks::preset(preset).unwrap(), 20).unwrap();
+        }
+    }
+}
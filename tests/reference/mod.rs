@@ -0,0 +1,340 @@
+//! A deliberately simple CHIP-8 interpreter written straight from the instruction table, used
+//! as the reference `Chip8` is compared with by `tests/differential.rs` and the `differential`
+//! fuzz target. It shares nothing with `Chip8` but the `Quirks` it follows and the initial
+//! memory, which holds the font.
+//!
+//! It implements the CHIP-8 instructions and `FX30`. The memory and the program counter wrap
+//! around at 4 KB, no key is pressed and `CXNN` draws from a generator seeded the way
+//! `Chip8::set_seed` seeds its own. A call with 16 return addresses on the stack or a return
+//! with none is a fault, which has to halt `Chip8`. What happens on instructions outside the
+//! table is left open, so the comparison ends there.
+
+#![allow(dead_code)]
+
+use rand::{Rng, SeedableRng, XorShiftRng};
+
+use chip8::chip8::{Chip8, Fault, State};
+use chip8::stack::StackError;
+use chip8::layout::Layout;
+use chip8::quirks::Quirks;
+
+pub const MEMORY_SIZE: usize = 0x1000;
+const WIDTH: usize = 64;
+const HEIGHT: usize = 32;
+const STACK_SIZE: usize = 16;
+const SMALL_DIGIT: usize = 5;
+const LARGE_DIGIT: usize = 10;
+
+/// Result of `Reference::step`.
+#[derive(Debug, PartialEq)]
+pub enum Step {
+    Executed,
+    /// `FX0A`, which ends the comparison.
+    WaitForKey,
+    /// An instruction outside the table, which ends the comparison as well.
+    Undefined,
+    /// A call with a full stack or a return with an empty one.
+    Fault(StackError),
+}
+
+pub struct Reference {
+    pub v: [u8; 16],
+    pub i: u16,
+    pub pc: u16,
+    pub stack: Vec<u16>,
+    pub delay: u8,
+    pub sound: u8,
+    pub memory: Vec<u8>,
+    /// 64 x 32 pixels, row by row, 1 for lit.
+    pub display: Vec<u8>,
+    quirks: Quirks,
+    font_address: usize,
+    rng: XorShiftRng,
+}
+
+impl Reference {
+    /// Starts from the state of `chip`, which has just been initialized and loaded, and draws
+    /// the random numbers of a `Chip8` given `seed`.
+    pub fn new(chip: &Chip8, quirks: Quirks, seed: u64) -> Reference {
+        Reference {
+            v: [0; 16],
+            i: 0,
+            pc: chip.program_counter(),
+            stack: Vec::new(),
+            delay: 0,
+            sound: 0,
+            memory: (0..MEMORY_SIZE).map(|address| chip.memory().load8(address)).collect(),
+            display: vec![0; WIDTH * HEIGHT],
+            quirks,
+            font_address: Layout::default().font_address,
+            rng: XorShiftRng::from_seed([seed as u32, (seed >> 32) as u32, 0x9E37_79B9,
+                                         0x7F4A_7C15]),
+        }
+    }
+
+    /// Returns the instruction at the program counter.
+    pub fn opcode(&self) -> u16 {
+        (self.load(self.pc as usize) as u16) << 8 | self.load(self.pc as usize + 1) as u16
+    }
+
+    fn load(&self, address: usize) -> u8 {
+        self.memory[address % MEMORY_SIZE]
+    }
+
+    fn store(&mut self, address: usize, value: u8) {
+        self.memory[address % MEMORY_SIZE] = value;
+    }
+
+    /// Executes one instruction and counts the timers down.
+    ///
+    /// Does nothing on `FX0A`, which would wait for a key forever, on stack faults and on
+    /// instructions outside the table.
+    pub fn step(&mut self) -> Step {
+        let opcode = self.opcode();
+        let x = (opcode >> 8 & 0xF) as usize;
+        let y = (opcode >> 4 & 0xF) as usize;
+        let n = (opcode & 0xF) as usize;
+        let nn = (opcode & 0xFF) as u8;
+        let nnn = opcode & 0xFFF;
+        let pc = self.pc;
+        let mut next = pc + 2;
+
+        match opcode >> 12 {
+            0x0 if opcode == 0x00E0 => self.display = vec![0; WIDTH * HEIGHT],
+            0x0 if opcode == 0x00EE => {
+                match self.stack.pop() {
+                    Some(address) => next = address + 2,
+                    None => return Step::Fault(StackError::Underflow),
+                }
+            }
+            0x1 => next = nnn,
+            0x2 if self.stack.len() == STACK_SIZE => return Step::Fault(StackError::Overflow),
+            0x2 => {
+                self.stack.push(pc);
+                next = nnn;
+            }
+            0x3 if self.v[x] == nn => next = pc + 4,
+            0x4 if self.v[x] != nn => next = pc + 4,
+            0x5 if n == 0 && self.v[x] == self.v[y] => next = pc + 4,
+            0x3 | 0x4 => {}
+            0x5 if n == 0 => {}
+            0x6 => self.v[x] = nn,
+            0x7 => self.v[x] = self.v[x].wrapping_add(nn),
+            0x8 if n < 8 || n == 0xE => self.alu(x, y, n),
+            0x9 if n == 0 && self.v[x] != self.v[y] => next = pc + 4,
+            0x9 if n == 0 => {}
+            0xA => self.i = nnn,
+            0xB => {
+                let offset = if self.quirks.jump { self.v[x] } else { self.v[0] };
+                next = nnn + offset as u16;
+            }
+            0xC => self.v[x] = self.rng.gen::<u8>() & nn,
+            0xD => self.v[0xF] = self.draw(self.v[x], self.v[y], n),
+            // No key is ever pressed.
+            0xE if nn == 0x9E => {}
+            0xE if nn == 0xA1 => next = pc + 4,
+            0xF if nn == 0x0A => return Step::WaitForKey,
+            0xF => {
+                if !self.misc(x, nn) {
+                    return Step::Undefined;
+                }
+            }
+            _ => return Step::Undefined,
+        }
+
+        self.pc = next & 0xFFF;
+        self.delay = self.delay.saturating_sub(1);
+        self.sound = self.sound.saturating_sub(1);
+        Step::Executed
+    }
+
+    // 8XYN, the flag is written after the result.
+    fn alu(&mut self, x: usize, y: usize, n: usize) {
+        let (vx, vy) = (self.v[x], self.v[y]);
+        let source = if self.quirks.shift { vx } else { vy };
+        let (result, flag) = match n {
+            0x0 => (vy, None),
+            0x1 => (vx | vy, if self.quirks.logic { Some(0) } else { None }),
+            0x2 => (vx & vy, if self.quirks.logic { Some(0) } else { None }),
+            0x3 => (vx ^ vy, if self.quirks.logic { Some(0) } else { None }),
+            0x4 => {
+                let sum = vx as u16 + vy as u16;
+                (sum as u8, Some((sum >> 8) as u8))
+            }
+            0x5 => (vx.wrapping_sub(vy), Some(if vx >= vy { 1 } else { 0 })),
+            0x6 => (source / 2, Some(source % 2)),
+            0x7 => (vy.wrapping_sub(vx), Some(if vy >= vx { 1 } else { 0 })),
+            _ => (source.wrapping_mul(2), Some(source / 128)),
+        };
+        self.v[x] = result;
+        if let Some(flag) = flag {
+            self.v[0xF] = flag;
+        }
+    }
+
+    // FXNN other than FX0A. Returns false for an unknown instruction.
+    fn misc(&mut self, x: usize, nn: u8) -> bool {
+        let i = self.i as usize;
+        match nn {
+            0x07 => self.v[x] = self.delay,
+            0x15 => self.delay = self.v[x],
+            0x18 => self.sound = self.v[x],
+            0x1E => {
+                let sum = self.i as u32 + self.v[x] as u32;
+                self.i = sum as u16;
+                if self.quirks.add_i_overflow {
+                    self.v[0xF] = if sum > 0xFFF { 1 } else { 0 };
+                }
+            }
+            0x29 => self.i = (self.font_address + (self.v[x] % 16) as usize * SMALL_DIGIT) as u16,
+            0x30 => {
+                let digit = (self.v[x] % 16) as usize;
+                self.i = (self.font_address + 16 * SMALL_DIGIT + digit * LARGE_DIGIT) as u16;
+            }
+            0x33 => {
+                let value = self.v[x];
+                self.store(i, value / 100);
+                self.store(i + 1, value / 10 % 10);
+                self.store(i + 2, value % 10);
+            }
+            0x55 | 0x65 => {
+                for register in 0..x + 1 {
+                    if nn == 0x55 {
+                        let value = self.v[register];
+                        self.store(i + register, value);
+                    } else {
+                        self.v[register] = self.load(i + register);
+                    }
+                }
+                if !self.quirks.load_store {
+                    self.i = self.i.wrapping_add(x as u16 + 1);
+                }
+            }
+            _ => return false,
+        }
+        true
+    }
+
+    // Draws `height` rows of the sprite at I and returns the value of VF.
+    fn draw(&mut self, vx: u8, vy: u8, height: usize) -> u8 {
+        let left = vx as usize % WIDTH;
+        let top = vy as usize % HEIGHT;
+        let mut collision = 0;
+        let mut rows = 0;
+        for row in 0..height {
+            if self.quirks.clip && top + row >= HEIGHT {
+                rows += 1;
+                continue;
+            }
+            let bits = self.load(self.i as usize + row);
+            let mut row_collision = 0;
+            for column in 0..8 {
+                let lit = bits >> (7 - column) & 1;
+                if lit == 0 || self.quirks.clip && left + column >= WIDTH {
+                    continue;
+                }
+                let pixel = (top + row) % HEIGHT * WIDTH + (left + column) % WIDTH;
+                row_collision |= self.display[pixel] & lit;
+                self.display[pixel] ^= lit;
+            }
+            collision |= row_collision;
+            rows += row_collision;
+        }
+        if self.quirks.collision_rows {
+            rows
+        } else {
+            collision
+        }
+    }
+}
+
+/// Returns a description of the first difference between `chip` and `reference`.
+pub fn compare(chip: &Chip8, reference: &Reference) -> Result<(), String> {
+    let registers = chip.registers();
+    for index in 0..16 {
+        if registers[index] != reference.v[index] {
+            return Err(format!("V{:X} is {:02X} instead of {:02X}",
+                               index,
+                               registers[index],
+                               reference.v[index]));
+        }
+    }
+    let pairs = [("I", registers.reg_i, reference.i),
+                 ("PC", chip.program_counter(), reference.pc),
+                 ("stack depth", chip.stack_depth() as u16, reference.stack.len() as u16),
+                 ("delay timer", chip.delay_timer() as u16, reference.delay as u16),
+                 ("sound timer", chip.sound_timer() as u16, reference.sound as u16)];
+    for &(name, actual, expected) in pairs.iter() {
+        if actual != expected {
+            return Err(format!("{} is {:03X} instead of {:03X}", name, actual, expected));
+        }
+    }
+    for address in 0..MEMORY_SIZE {
+        let value = chip.memory().load8(address);
+        if value != reference.memory[address] {
+            return Err(format!("Memory at {:03X} is {:02X} instead of {:02X}",
+                               address,
+                               value,
+                               reference.memory[address]));
+        }
+    }
+    for pixel in 0..WIDTH * HEIGHT {
+        if chip.gfx()[pixel] != reference.display[pixel] {
+            return Err(format!("Pixel ({}, {}) is {} instead of {}",
+                               pixel % WIDTH,
+                               pixel / WIDTH,
+                               chip.gfx()[pixel],
+                               reference.display[pixel]));
+        }
+    }
+    Ok(())
+}
+
+/// Runs `program` for up to `steps` instructions on a `Chip8` and on the reference, comparing
+/// them after every instruction. The display wait quirk is turned off, as the reference has
+/// no frames.
+///
+/// Returns the address and instruction where they first differ with the difference.
+pub fn run(program: &[u8], quirks: Quirks, steps: usize) -> Result<(), String> {
+    let quirks = Quirks { display_wait: false, ..quirks };
+    let seed = 0x4348_4950_2D38;
+    let mut chip = Chip8::new();
+    chip.set_database(None);
+    chip.set_seed(seed);
+    chip.initialize();
+    if chip.load_rom(program.to_vec()).is_err() {
+        return Ok(());
+    }
+    chip.set_quirks(quirks);
+    let mut reference = Reference::new(&chip, quirks, seed);
+
+    for _ in 0..steps {
+        let (pc, opcode) = (reference.pc, reference.opcode());
+        chip.cycle();
+        match reference.step() {
+            Step::Executed => {}
+            Step::WaitForKey | Step::Undefined => return Ok(()),
+            Step::Fault(error) => {
+                let halted = State::Halted {
+                    fault: Fault::Stack(error),
+                    address: pc,
+                };
+                if chip.state() == halted {
+                    return Ok(());
+                }
+                return Err(format!("{:03X}: {:04X}: state is {:?} instead of {:?}",
+                                   pc,
+                                   opcode,
+                                   chip.state(),
+                                   halted));
+            }
+        }
+        if chip.state() != State::Running {
+            return Ok(());
+        }
+        if let Err(msg) = compare(&chip, &reference) {
+            return Err(format!("{:03X}: {:04X}: {}", pc, opcode, msg));
+        }
+    }
+    Ok(())
+}
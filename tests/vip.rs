@@ -0,0 +1,43 @@
+//! Runs a CHIP-8 program on the original interpreter of an emulated COSMAC VIP. The monitor
+//! and interpreter are not included: set `CHIP8_VIP_MONITOR` and `CHIP8_VIP_INTERPRETER` to the
+//! paths of their dumps to run this test, which does nothing otherwise.
+
+extern crate chip8;
+
+use std::env;
+use std::fs;
+
+use chip8::vip::Vip;
+use chip8::DISPLAY_WIDTH;
+
+// Draws the font digit 0 at (0, 0) and loops.
+const PROGRAM: [u8; 8] = [0x60, 0x00, // V0 = 0
+                          0xF0, 0x29, // I = digit V0
+                          0xD0, 0x05, // draw 5 rows at (V0, V0)
+                          0x12, 0x06]; // jump to itself
+
+#[test]
+fn vip_runs_program() {
+    let (monitor, interpreter) = match (env::var_os("CHIP8_VIP_MONITOR"),
+                                        env::var_os("CHIP8_VIP_INTERPRETER")) {
+        (Some(monitor), Some(interpreter)) => (monitor, interpreter),
+        _ => return,
+    };
+    let mut vip = Vip::new(&fs::read(monitor).unwrap(), &fs::read(interpreter).unwrap())
+        .unwrap();
+    vip.load_rom(&PROGRAM).unwrap();
+    for _ in 0..120 {
+        vip.step_frame();
+    }
+
+    let digit = [0xF0, 0x90, 0x90, 0x90, 0xF0, 0x00];
+    for (row, bits) in digit.iter().enumerate() {
+        for x in 0..8 {
+            assert_eq!(vip.gfx()[row * DISPLAY_WIDTH + x],
+                       bits >> (7 - x) & 0x01,
+                       "pixel ({}, {})",
+                       x,
+                       row);
+        }
+    }
+}